@@ -2,13 +2,33 @@
 pragma solidity ^0.8.0;
 
 import "@openzeppelin/contracts/token/ERC1155/ERC1155.sol";
+import "@openzeppelin/contracts/token/ERC1155/extensions/ERC1155Burnable.sol";
+import "@openzeppelin/contracts/token/common/ERC2981.sol";
+import "@openzeppelin/contracts/access/Ownable.sol";
 import "@openzeppelin/contracts/utils/Counters.sol";
 
-contract MyERC1155 is ERC1155{
+contract MyERC1155 is ERC1155, ERC1155Burnable, ERC2981, Ownable {
     using Counters for Counters.Counter;
     Counters.Counter private _tokenIds;
 
-    constructor() ERC1155("") {}
+    // ERC-1155 has no name or symbol; marketplaces read these when present
+    string public name;
+    string public symbol;
+
+    // `uri_` may contain {id}; a zero receiver sets no royalty
+    constructor(
+        string memory name_,
+        string memory symbol_,
+        string memory uri_,
+        address royaltyReceiver,
+        uint96 royaltyFee
+    ) ERC1155(uri_) {
+        name = name_;
+        symbol = symbol_;
+        if (royaltyReceiver != address(0)) {
+            _setDefaultRoyalty(royaltyReceiver, royaltyFee);
+        }
+    }
 
     function mint(address account, uint256 amount) public returns (uint256) {
         _tokenIds.increment();
@@ -16,4 +36,24 @@ contract MyERC1155 is ERC1155{
         _mint(account, newItemId, amount, "");
         return newItemId;
     }
-}
\ No newline at end of file
+
+    function setDefaultRoyalty(address receiver, uint96 feeNumerator) public onlyOwner {
+        _setDefaultRoyalty(receiver, feeNumerator);
+    }
+
+    function deleteDefaultRoyalty() public onlyOwner {
+        _deleteDefaultRoyalty();
+    }
+
+    function setTokenRoyalty(uint256 tokenId, address receiver, uint96 feeNumerator) public onlyOwner {
+        _setTokenRoyalty(tokenId, receiver, feeNumerator);
+    }
+
+    function resetTokenRoyalty(uint256 tokenId) public onlyOwner {
+        _resetTokenRoyalty(tokenId);
+    }
+
+    function supportsInterface(bytes4 interfaceId) public view virtual override(ERC1155, ERC2981) returns (bool) {
+        return super.supportsInterface(interfaceId);
+    }
+}
@@ -1,19 +1,78 @@
 // SPDX-License-Identifier: MIT
 pragma solidity ^0.8.0;
 
-import "@openzeppelin/contracts/token/ERC721/ERC721.sol";
+import "@openzeppelin/contracts/token/ERC721/extensions/ERC721URIStorage.sol";
+import "@openzeppelin/contracts/token/ERC721/extensions/ERC721Royalty.sol";
+import "@openzeppelin/contracts/token/ERC721/extensions/ERC721Burnable.sol";
+import "@openzeppelin/contracts/access/Ownable.sol";
 import "@openzeppelin/contracts/utils/Counters.sol";
 
-contract MyERC721 is ERC721{
+contract MyERC721 is ERC721URIStorage, ERC721Royalty, ERC721Burnable, Ownable {
     using Counters for Counters.Counter;
     Counters.Counter private _tokenIds;
+    string private _baseTokenURI;
 
-    constructor() ERC721("MyERC721", "M721") {}
+    // An empty base URI leaves token URIs as minted; a zero receiver sets no royalty
+    constructor(
+        string memory name_,
+        string memory symbol_,
+        string memory baseURI_,
+        address royaltyReceiver,
+        uint96 royaltyFee
+    ) ERC721(name_, symbol_) {
+        _baseTokenURI = baseURI_;
+        if (royaltyReceiver != address(0)) {
+            _setDefaultRoyalty(royaltyReceiver, royaltyFee);
+        }
+    }
+
+    // The call eth::mint sends: `amount` tokens with the same URI. Returns the first id.
+    function mint(address recipient, string memory uri, uint8 amount) public onlyOwner returns (uint256) {
+        require(amount > 0, "MyERC721: amount is zero");
+        uint256 firstItemId = _tokenIds.current() + 1;
+        for (uint8 i = 0; i < amount; i++) {
+            _tokenIds.increment();
+            uint256 newItemId = _tokenIds.current();
+            _mint(recipient, newItemId);
+            _setTokenURI(newItemId, uri);
+        }
+        return firstItemId;
+    }
+
+    function setDefaultRoyalty(address receiver, uint96 feeNumerator) public onlyOwner {
+        _setDefaultRoyalty(receiver, feeNumerator);
+    }
+
+    function deleteDefaultRoyalty() public onlyOwner {
+        _deleteDefaultRoyalty();
+    }
+
+    function setTokenRoyalty(uint256 tokenId, address receiver, uint96 feeNumerator) public onlyOwner {
+        _setTokenRoyalty(tokenId, receiver, feeNumerator);
+    }
+
+    function resetTokenRoyalty(uint256 tokenId) public onlyOwner {
+        _resetTokenRoyalty(tokenId);
+    }
+
+    function _baseURI() internal view override returns (string memory) {
+        return _baseTokenURI;
+    }
+
+    function tokenURI(uint256 tokenId) public view override(ERC721, ERC721URIStorage) returns (string memory) {
+        return super.tokenURI(tokenId);
+    }
+
+    function supportsInterface(bytes4 interfaceId)
+        public
+        view
+        override(ERC721, ERC721URIStorage, ERC721Royalty)
+        returns (bool)
+    {
+        return super.supportsInterface(interfaceId);
+    }
 
-    function mint(address recipient) public returns (uint256) {
-        _tokenIds.increment();
-        uint256 newItemId = _tokenIds.current();
-        _mint(recipient, newItemId);
-        return newItemId;
+    function _burn(uint256 tokenId) internal override(ERC721, ERC721URIStorage, ERC721Royalty) {
+        super._burn(tokenId);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,77 @@
+// Rebuilds abi/artifacts from the contract sources with the vendored
+// OpenZeppelin tree, in the Remix layout deploy.rs reads.
+//
+//     npm install && npm run build:contracts
+const fs = require("fs");
+const path = require("path");
+const solc = require("solc");
+
+const root = path.join(__dirname, "..");
+const contracts = {
+  "abi/ERC721.sol": "MyERC721",
+  "abi/ERC1155.sol": "MyERC1155",
+};
+const networks = ["VM:-", "main:1", "ropsten:3", "rinkeby:4", "kovan:42", "goerli:5", "Custom"];
+
+const input = {
+  language: "Solidity",
+  sources: {},
+  settings: {
+    evmVersion: "paris",
+    optimizer: { enabled: false, runs: 200 },
+    outputSelection: {
+      "*": {
+        "*": [
+          "abi",
+          "metadata",
+          "evm.bytecode",
+          "evm.deployedBytecode",
+          "evm.gasEstimates",
+          "evm.methodIdentifiers",
+        ],
+      },
+    },
+  },
+};
+for (const file of Object.keys(contracts)) {
+  input.sources[file] = { content: fs.readFileSync(path.join(root, file), "utf8") };
+}
+
+function findImports(importPath) {
+  try {
+    return { contents: fs.readFileSync(path.join(root, "node_modules", importPath), "utf8") };
+  } catch (e) {
+    return { error: `${importPath} not found` };
+  }
+}
+
+const output = JSON.parse(solc.compile(JSON.stringify(input), { import: findImports }));
+const errors = (output.errors || []).filter((error) => error.severity === "error");
+for (const error of output.errors || []) {
+  console.error(error.formattedMessage);
+}
+if (errors.length > 0) {
+  process.exit(1);
+}
+
+const write = (file, value) =>
+  fs.writeFileSync(path.join(root, "abi/artifacts", file), JSON.stringify(value, null, "\t"));
+for (const [file, name] of Object.entries(contracts)) {
+  const contract = output.contracts[file][name];
+  const deploy = {};
+  for (const network of networks) {
+    deploy[network] = { linkReferences: {}, autoDeployLib: true };
+  }
+  write(`${name}.json`, {
+    deploy,
+    data: {
+      bytecode: contract.evm.bytecode,
+      deployedBytecode: contract.evm.deployedBytecode,
+      gasEstimates: contract.evm.gasEstimates,
+      methodIdentifiers: contract.evm.methodIdentifiers,
+    },
+    abi: contract.abi,
+  });
+  write(`${name}_metadata.json`, JSON.parse(contract.metadata));
+  console.log(`abi/artifacts/${name}.json`);
+}
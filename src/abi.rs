@@ -0,0 +1,366 @@
+use crate::collections::Collection;
+use crate::metadata::TokenStandard;
+use serde_json::Value;
+use std::str::FromStr;
+use web3::ethabi::{Contract, Function, ParamType, Token};
+use web3::types::{H160, U256};
+
+// What a collection is called with when it was registered without an ABI
+fn bundled(standard: TokenStandard) -> Result<Value, String> {
+    let files: &[&[u8]] = match standard {
+        TokenStandard::Erc721 => &[
+            &include_bytes!("../abi/ERC721.json")[..],
+            &include_bytes!("../abi/ERC721Enumerable.json")[..],
+            &include_bytes!("../abi/ERC721Royalty.json")[..],
+        ],
+        TokenStandard::Erc1155 => &[&include_bytes!("../abi/ERC1155.json")[..]],
+    };
+    let mut entries: Vec<Value> = Vec::new();
+    for file in files {
+        let abi: Vec<Value> = serde_json::from_slice(file).map_err(|e| e.to_string())?;
+        for entry in abi {
+            // supportsInterface and friends appear in more than one file
+            let duplicate = entries.iter().any(|known| {
+                known["type"] == entry["type"]
+                    && known["name"] == entry["name"]
+                    && known["inputs"] == entry["inputs"]
+            });
+            if !duplicate {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(Value::Array(entries))
+}
+
+pub fn load(abi: &Value) -> Result<Contract, String> {
+    Contract::load(abi.to_string().as_bytes()).map_err(|e| format!("invalid ABI: {}", e))
+}
+
+// The collection's stored ABI, or the bundled one for its standard
+pub fn collection_abi(collection: &Collection) -> Result<Value, String> {
+    match &collection.abi {
+        Some(abi) => Ok(abi.clone()),
+        None => bundled(collection.standard),
+    }
+}
+
+pub fn signature(function: &Function) -> String {
+    let inputs: Vec<String> = function
+        .inputs
+        .iter()
+        .map(|param| param.kind.to_string())
+        .collect();
+    format!("{}({})", function.name, inputs.join(","))
+}
+
+// `name` is a function name, or a full signature such as
+// `safeTransferFrom(address,address,uint256)` to pick one overload
+pub fn find_function<'a>(
+    contract: &'a Contract,
+    name: &str,
+    arity: usize,
+) -> Result<&'a Function, String> {
+    let wanted: String = name.chars().filter(|c| !c.is_whitespace()).collect();
+    let function_name = wanted.split('(').next().unwrap_or(&wanted);
+    let overloads = contract
+        .functions_by_name(function_name)
+        .map_err(|_| format!("the ABI has no function {}", function_name))?;
+    let candidates: Vec<&Function> = overloads
+        .iter()
+        .filter(|function| {
+            if wanted.contains('(') {
+                signature(function) == wanted
+            } else {
+                function.inputs.len() == arity
+            }
+        })
+        .collect();
+    match candidates.as_slice() {
+        [function] => Ok(*function),
+        [] => Err(format!(
+            "no overload of {} matches {} arguments",
+            function_name, arity
+        )),
+        _ => Err(format!(
+            "{} is overloaded, name it by its signature",
+            function_name
+        )),
+    }
+}
+
+// View and pure functions can only be simulated. ethabi 13 doesn't keep
+// `stateMutability`, so the ABI entry itself is consulted.
+pub fn read_only(abi: &Value, function: &Function) -> bool {
+    let entries = match abi.as_array() {
+        Some(entries) => entries,
+        None => return false,
+    };
+    entries.iter().any(|entry| {
+        let inputs = entry["inputs"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        entry["type"] == "function"
+            && entry["name"] == function.name.as_str()
+            && inputs.len() == function.inputs.len()
+            && inputs.iter().zip(&function.inputs).all(|(input, param)| {
+                let kind = input["type"].as_str().unwrap_or("");
+                kind.starts_with("tuple") || kind == param.kind.to_string()
+            })
+            && (entry["constant"] == true
+                || entry["stateMutability"] == "view"
+                || entry["stateMutability"] == "pure")
+    })
+}
+
+// (negative, magnitude) of a JSON number or a decimal or 0x-hex string
+fn number(value: &Value) -> Result<(bool, U256), String> {
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_u64() {
+                Ok((false, U256::from(number)))
+            } else if let Some(number) = number.as_i64() {
+                Ok((true, U256::from(number.unsigned_abs())))
+            } else {
+                Err(format!(
+                    "{} is not an integer; large values go in strings",
+                    number
+                ))
+            }
+        }
+        Value::String(text) => {
+            let text = text.trim();
+            let (negative, digits) = match text.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, text),
+            };
+            let magnitude = match digits.strip_prefix("0x") {
+                Some(hex) => U256::from_str(hex).map_err(|e| e.to_string()),
+                None => U256::from_dec_str(digits).map_err(|e| format!("{:?}", e)),
+            }
+            .map_err(|e| format!("{} is not a number: {}", text, e))?;
+            Ok((negative, magnitude))
+        }
+        _ => Err(format!("expected a number, got {}", value)),
+    }
+}
+
+// 0x-prefixed hex, anything else is read as base64
+fn bytes(value: &Value) -> Result<Vec<u8>, String> {
+    let text = value
+        .as_str()
+        .ok_or_else(|| format!("expected hex or base64 bytes, got {}", value))?;
+    match text.strip_prefix("0x") {
+        Some(hex) => hex::decode(hex).map_err(|e| e.to_string()),
+        None => base64::decode(text).map_err(|e| e.to_string()),
+    }
+}
+
+fn items(value: &Value) -> Result<&Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| format!("expected an array, got {}", value))
+}
+
+// Turns a JSON argument into the ABI type the function expects
+pub fn coerce(kind: &ParamType, value: &Value) -> Result<Token, String> {
+    match kind {
+        ParamType::Address => {
+            let text = value
+                .as_str()
+                .ok_or_else(|| format!("expected an address, got {}", value))?;
+            H160::from_str(text)
+                .map(Token::Address)
+                .map_err(|_| format!("{} is not an address", text))
+        }
+        ParamType::Uint(size) => {
+            let (negative, magnitude) = number(value)?;
+            if negative || magnitude.bits() > *size {
+                return Err(format!("{} is out of range for uint{}", value, size));
+            }
+            Ok(Token::Uint(magnitude))
+        }
+        ParamType::Int(size) => {
+            let (negative, magnitude) = number(value)?;
+            let limit = U256::one() << (size - 1);
+            if magnitude > limit || (!negative && magnitude == limit) {
+                return Err(format!("{} is out of range for int{}", value, size));
+            }
+            // Two's complement over the full word, as the ABI encodes it
+            if negative {
+                Ok(Token::Int((!magnitude).overflowing_add(U256::one()).0))
+            } else {
+                Ok(Token::Int(magnitude))
+            }
+        }
+        ParamType::Bool => match value {
+            Value::Bool(flag) => Ok(Token::Bool(*flag)),
+            Value::String(text) if text == "true" || text == "false" => {
+                Ok(Token::Bool(text == "true"))
+            }
+            _ => Err(format!("expected a bool, got {}", value)),
+        },
+        ParamType::String => value
+            .as_str()
+            .map(|text| Token::String(text.to_string()))
+            .ok_or_else(|| format!("expected a string, got {}", value)),
+        ParamType::Bytes => bytes(value).map(Token::Bytes),
+        ParamType::FixedBytes(size) => {
+            let bytes = bytes(value)?;
+            if bytes.len() != *size {
+                return Err(format!("expected {} bytes, got {}", size, bytes.len()));
+            }
+            Ok(Token::FixedBytes(bytes))
+        }
+        ParamType::Array(kind) => items(value)?
+            .iter()
+            .map(|item| coerce(kind, item))
+            .collect::<Result<_, _>>()
+            .map(Token::Array),
+        ParamType::FixedArray(kind, size) => {
+            let items = items(value)?;
+            if items.len() != *size {
+                return Err(format!("expected {} items, got {}", size, items.len()));
+            }
+            items
+                .iter()
+                .map(|item| coerce(kind, item))
+                .collect::<Result<_, _>>()
+                .map(Token::FixedArray)
+        }
+        // Tuples are given positionally
+        ParamType::Tuple(kinds) => {
+            let items = items(value)?;
+            if items.len() != kinds.len() {
+                return Err(format!(
+                    "expected a tuple of {} items, got {}",
+                    kinds.len(),
+                    items.len()
+                ));
+            }
+            kinds
+                .iter()
+                .zip(items)
+                .map(|(kind, item)| coerce(kind, item))
+                .collect::<Result<_, _>>()
+                .map(Token::Tuple)
+        }
+    }
+}
+
+// Calldata for `function` with JSON arguments in order
+pub fn encode(function: &Function, args: &[Value]) -> Result<Vec<u8>, String> {
+    if args.len() != function.inputs.len() {
+        return Err(format!(
+            "{} takes {} arguments, got {}",
+            signature(function),
+            function.inputs.len(),
+            args.len()
+        ));
+    }
+    let tokens = function
+        .inputs
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| coerce(&param.kind, arg).map_err(|e| format!("{}: {}", param.name, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    function.encode_input(&tokens).map_err(|e| e.to_string())
+}
+
+// Return values as JSON: numbers as decimal strings, bytes as 0x hex
+pub fn to_json(token: &Token) -> Value {
+    match token {
+        Token::Address(address) => Value::String(format!("{:?}", address)),
+        Token::Uint(number) => Value::String(number.to_string()),
+        Token::Int(number) if number.bit(255) => {
+            Value::String(format!("-{}", (!*number).overflowing_add(U256::one()).0))
+        }
+        Token::Int(number) => Value::String(number.to_string()),
+        Token::Bool(flag) => Value::Bool(*flag),
+        Token::String(text) => Value::String(text.clone()),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        Token::Array(tokens) | Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+            Value::Array(tokens.iter().map(to_json).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn erc721() -> (Value, Contract) {
+        let abi = bundled(TokenStandard::Erc721).unwrap();
+        let contract = load(&abi).unwrap();
+        (abi, contract)
+    }
+
+    #[test]
+    fn ints_are_twos_complement_within_bounds() {
+        let kind = ParamType::Int(8);
+        assert_eq!(
+            coerce(&kind, &json!(127)).unwrap(),
+            Token::Int(U256::from(127))
+        );
+        assert_eq!(coerce(&kind, &json!(-1)).unwrap(), Token::Int(U256::MAX));
+        let min = coerce(&kind, &json!("-128")).unwrap();
+        assert_eq!(min, Token::Int(U256::MAX - U256::from(127)));
+        assert_eq!(to_json(&min), json!("-128"));
+        assert!(coerce(&kind, &json!(128)).is_err());
+        assert!(coerce(&kind, &json!(-129)).is_err());
+    }
+
+    #[test]
+    fn uints_are_checked_against_their_width() {
+        let kind = ParamType::Uint(8);
+        assert_eq!(
+            coerce(&kind, &json!("0xff")).unwrap(),
+            Token::Uint(U256::from(255))
+        );
+        assert!(coerce(&kind, &json!(256)).is_err());
+        assert!(coerce(&kind, &json!(-1)).is_err());
+        assert!(coerce(&kind, &json!(1.5)).is_err());
+        let large =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        assert_eq!(
+            coerce(&ParamType::Uint(256), &json!(large)).unwrap(),
+            Token::Uint(U256::MAX)
+        );
+    }
+
+    #[test]
+    fn bytes_are_hex_or_base64() {
+        assert_eq!(bytes(&json!("0x0102")).unwrap(), vec![1, 2]);
+        assert_eq!(bytes(&json!("AQI=")).unwrap(), vec![1, 2]);
+        assert!(bytes(&json!("0xzz")).is_err());
+        assert!(bytes(&json!(12)).is_err());
+        let fixed = ParamType::FixedBytes(2);
+        assert_eq!(
+            coerce(&fixed, &json!("0x0102")).unwrap(),
+            Token::FixedBytes(vec![1, 2])
+        );
+        assert!(coerce(&fixed, &json!("0x010203")).is_err());
+    }
+
+    #[test]
+    fn overloads_are_picked_by_arity_or_signature() {
+        let (_, contract) = erc721();
+        let function = find_function(&contract, "safeTransferFrom", 4).unwrap();
+        assert_eq!(function.inputs.len(), 4);
+        let signature = "safeTransferFrom(address, address, uint256)";
+        let function = find_function(&contract, signature, 4).unwrap();
+        assert_eq!(function.inputs.len(), 3);
+        assert!(find_function(&contract, "safeTransferFrom", 2).is_err());
+        assert!(find_function(&contract, "burn", 1).is_err());
+    }
+
+    #[test]
+    fn view_functions_are_read_only() {
+        let (abi, contract) = erc721();
+        let balance_of = find_function(&contract, "balanceOf", 1).unwrap();
+        assert!(read_only(&abi, balance_of));
+        let approve = find_function(&contract, "approve", 2).unwrap();
+        assert!(!read_only(&abi, approve));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::config::Config;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::Outcome;
+
+// Guard for admin endpoints: `Authorization: Bearer <admin_token>`. With no
+// token configured the admin API is off and every admin request is refused.
+pub struct Admin;
+
+// Compares in constant time so the token can't be guessed byte by byte
+fn same_token(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Admin {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let admin_token = Config::get_admin_token();
+        if admin_token.is_empty() {
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if same_token(token.as_bytes(), admin_token.as_bytes()) => {
+                Outcome::Success(Admin)
+            }
+            _ => Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
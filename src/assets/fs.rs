@@ -0,0 +1,68 @@
+use super::AssetStore;
+use crate::config::Config;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_BASE_URL: &str = "http://localhost:8000/assets";
+
+// Local directory for development and tests. Files are served by the API
+// itself, so `base_url` is where clients reach GET /assets.
+pub struct FilesystemStore {
+    dir: PathBuf,
+    base_url: String,
+}
+
+// Stored names are content hashes plus an extension; anything else could
+// escape the directory
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+}
+
+impl FilesystemStore {
+    pub fn open(dir: &str, base_url: &str) -> Result<FilesystemStore, String> {
+        let dir = if dir.is_empty() {
+            Path::new(&Config::get_data_dir()).join("assets")
+        } else {
+            PathBuf::from(dir)
+        };
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let base_url = if base_url.is_empty() {
+            DEFAULT_BASE_URL
+        } else {
+            base_url
+        };
+        Ok(FilesystemStore {
+            dir,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+impl AssetStore for FilesystemStore {
+    fn put(&self, name: &str, _content_type: &str, content: &[u8]) -> Result<String, String> {
+        if !is_valid_name(name) {
+            return Err(format!("invalid asset name: {}", name));
+        }
+        let path = self.dir.join(name);
+        if !path.exists() {
+            // Written aside and renamed so a reader never sees half a file
+            let partial = self.dir.join(format!(".{}.partial", name));
+            fs::write(&partial, content).map_err(|e| e.to_string())?;
+            fs::rename(&partial, &path).map_err(|e| e.to_string())?;
+        }
+        Ok(format!("{}/{}", self.base_url, name))
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>, String> {
+        if !is_valid_name(name) {
+            return Ok(None);
+        }
+        match fs::read(self.dir.join(name)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
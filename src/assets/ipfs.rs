@@ -0,0 +1,53 @@
+use super::AssetStore;
+use reqwest::blocking::multipart::{Form, Part};
+use serde::Deserialize;
+use std::time::Duration;
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Pins through a Kubo node's HTTP API. The URI is ipfs://<cid>, which the
+// metadata service resolves through the configured gateway.
+pub struct IpfsStore {
+    api_url: String,
+}
+
+#[derive(Deserialize)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+impl IpfsStore {
+    pub fn new(api_url: &str) -> Result<IpfsStore, String> {
+        if !api_url.starts_with("http://") && !api_url.starts_with("https://") {
+            return Err(format!("unsupported IPFS API URL: {}", api_url));
+        }
+        Ok(IpfsStore {
+            api_url: api_url.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+impl AssetStore for IpfsStore {
+    fn put(&self, name: &str, content_type: &str, content: &[u8]) -> Result<String, String> {
+        let part = Part::bytes(content.to_vec())
+            .file_name(name.to_string())
+            .mime_str(content_type)
+            .map_err(|e| e.to_string())?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(UPLOAD_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let added: AddResponse = client
+            .post(&format!(
+                "{}/api/v0/add?pin=true&cid-version=1",
+                self.api_url
+            ))
+            .multipart(Form::new().part("file", part))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .map_err(|e| format!("IPFS add failed: {}", e))?;
+        Ok(format!("ipfs://{}", added.hash))
+    }
+}
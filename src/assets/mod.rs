@@ -0,0 +1,212 @@
+mod fs;
+mod ipfs;
+mod s3;
+
+use crate::config::{AssetStoreConfig, Config};
+use crate::metadata;
+use crate::types::AssetRequest;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub use fs::FilesystemStore;
+pub use ipfs::IpfsStore;
+pub use s3::S3Store;
+
+const MAX_MEDIA_SIZE: usize = 32 << 20;
+// Media types accepted for upload and the extension they are stored under
+const MEDIA_TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/svg+xml", "svg"),
+    ("video/mp4", "mp4"),
+    ("video/webm", "webm"),
+    ("audio/mpeg", "mp3"),
+    ("audio/wav", "wav"),
+    ("model/gltf-binary", "glb"),
+    ("model/gltf+json", "gltf"),
+    ("application/json", "json"),
+];
+
+lazy_static! {
+    static ref STORE: Mutex<Option<Box<dyn AssetStore>>> = Mutex::new(None);
+}
+
+// A place to pin uploaded files. Objects are named after their content hash,
+// so putting the same name twice stores the same bytes.
+pub trait AssetStore: Send {
+    // Stores `content` and returns the URI it can be fetched from
+    fn put(&self, name: &str, content_type: &str, content: &[u8]) -> Result<String, String>;
+
+    // Reads a stored object back; only stores without a public endpoint of
+    // their own serve files through the API
+    fn get(&self, _name: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Asset {
+    // sha256 of the media file
+    pub content_hash: String,
+    pub content_type: String,
+    pub size: usize,
+    pub media_uri: String,
+    // Pass this as `token_uri` to POST /mint
+    pub token_uri: String,
+    pub metadata: Value,
+}
+
+// A request whose media decoded and whose metadata passed validation
+pub struct Upload {
+    media: Vec<u8>,
+    content_type: String,
+    extension: &'static str,
+    metadata: Value,
+}
+
+pub fn open(config: Option<AssetStoreConfig>) -> Result<Box<dyn AssetStore>, String> {
+    match config {
+        Some(AssetStoreConfig::Ipfs { api_url }) => Ok(Box::new(IpfsStore::new(&api_url)?)),
+        Some(AssetStoreConfig::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+            public_url,
+        }) => Ok(Box::new(S3Store::new(
+            &endpoint,
+            &bucket,
+            &region,
+            &access_key,
+            &secret_key,
+            &public_url,
+        )?)),
+        Some(AssetStoreConfig::Filesystem { dir, base_url }) => {
+            Ok(Box::new(FilesystemStore::open(&dir, &base_url)?))
+        }
+        None => Ok(Box::new(FilesystemStore::open("", "")?)),
+    }
+}
+
+pub fn init() -> Result<(), String> {
+    let store = open(Config::get_asset_store())?;
+    *STORE.lock().unwrap() = Some(store);
+    Ok(())
+}
+
+fn with<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce(&dyn AssetStore) -> Result<T, String>,
+{
+    let store = STORE.lock().unwrap();
+    match store.as_ref() {
+        Some(store) => f(store.as_ref()),
+        None => Err("asset store is not initialized".into()),
+    }
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+pub fn media_type(name: &str) -> Option<&'static str> {
+    let extension = Path::new(name).extension()?.to_str()?;
+    MEDIA_TYPES
+        .iter()
+        .find(|(_, known)| *known == extension)
+        .map(|(content_type, _)| *content_type)
+}
+
+// Decodes the media and builds its metadata, rejecting anything a marketplace
+// couldn't display
+pub fn prepare(request: &AssetRequest) -> Result<Upload, String> {
+    let content_type = request.content_type.to_ascii_lowercase();
+    let extension = MEDIA_TYPES
+        .iter()
+        .find(|(known, _)| *known == content_type)
+        .map(|(_, extension)| *extension)
+        .ok_or_else(|| format!("unsupported media type: {}", request.content_type))?;
+    let media = base64::decode(&request.media).map_err(|e| format!("media: {}", e))?;
+    if media.is_empty() {
+        return Err("media is empty".into());
+    }
+    if media.len() > MAX_MEDIA_SIZE {
+        return Err("media is larger than 32 MiB".into());
+    }
+    if request.name.trim().is_empty() {
+        return Err("a name is required".into());
+    }
+
+    let mut metadata = json!({
+        "name": request.name,
+        "attributes": request.attributes,
+    });
+    if !request.description.is_empty() {
+        metadata["description"] = json!(request.description);
+    }
+    if let Some(external_url) = &request.external_url {
+        metadata["external_url"] = json!(external_url);
+    }
+    if let Some(background_color) = &request.background_color {
+        metadata["background_color"] = json!(background_color);
+    }
+    let errors = metadata::validate(&metadata);
+    if !errors.is_empty() {
+        return Err(errors.join("; "));
+    }
+    Ok(Upload {
+        media,
+        content_type,
+        extension,
+        metadata,
+    })
+}
+
+// Pins the media, then the metadata pointing at it. Images go in `image`;
+// anything else in `animation_url`, as marketplaces expect.
+pub fn upload(upload: Upload) -> Result<Asset, String> {
+    let Upload {
+        media,
+        content_type,
+        extension,
+        mut metadata,
+    } = upload;
+    let content_hash = sha256_hex(&media);
+    let media_name = format!("{}.{}", content_hash, extension);
+    let media_uri = with(|store| store.put(&media_name, &content_type, &media))?;
+
+    let field = if content_type.starts_with("image/") {
+        "image"
+    } else {
+        "animation_url"
+    };
+    metadata[field] = json!(media_uri);
+    metadata["properties"] = json!({
+        "content_hash": format!("sha256:{}", content_hash),
+        "content_type": content_type,
+        "size": media.len(),
+    });
+    let document = serde_json::to_vec(&metadata).map_err(|e| e.to_string())?;
+    let metadata_name = format!("{}.json", sha256_hex(&document));
+    let token_uri = with(|store| store.put(&metadata_name, "application/json", &document))?;
+
+    Ok(Asset {
+        content_hash,
+        content_type,
+        size: media.len(),
+        media_uri,
+        token_uri,
+        metadata,
+    })
+}
+
+pub fn get(name: &str) -> Result<Option<Vec<u8>>, String> {
+    with(|store| store.get(name))
+}
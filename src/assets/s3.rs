@@ -0,0 +1,138 @@
+use super::{sha256_hex, AssetStore};
+use crate::market::now;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use std::time::Duration;
+
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+const SIGNED_HEADERS: &str = "content-type;host;x-amz-content-sha256;x-amz-date";
+
+// Uploads with a SigV4-signed PUT, so it works against AWS, MinIO, R2 and
+// other S3-compatible services
+pub struct S3Store {
+    endpoint: String,
+    host: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    public_url: String,
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC takes keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// (YYYYMMDD, YYYYMMDDTHHMMSSZ) in UTC
+fn amz_date(timestamp: u64) -> (String, String) {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+    (date, time)
+}
+
+impl S3Store {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+        public_url: &str,
+    ) -> Result<S3Store, String> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let host = endpoint
+            .strip_prefix("https://")
+            .or_else(|| endpoint.strip_prefix("http://"))
+            .ok_or_else(|| format!("unsupported S3 endpoint: {}", endpoint))?;
+        if bucket.is_empty() {
+            return Err("an S3 bucket is required".into());
+        }
+        Ok(S3Store {
+            endpoint: endpoint.to_string(),
+            host: host.split('/').next().unwrap_or(host).to_string(),
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            public_url: public_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn authorization(
+        &self,
+        path: &str,
+        content_type: &str,
+        payload_hash: &str,
+        time: &str,
+        date: &str,
+    ) -> String {
+        let canonical_request = format!(
+            "PUT\n{}\n\ncontent-type:{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            path, content_type, self.host, payload_hash, time, SIGNED_HEADERS, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            time,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+        let key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date);
+        let key = hmac_sha256(&key, &self.region);
+        let key = hmac_sha256(&key, "s3");
+        let key = hmac_sha256(&key, "aws4_request");
+        let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, SIGNED_HEADERS, signature
+        )
+    }
+}
+
+impl AssetStore for S3Store {
+    fn put(&self, name: &str, content_type: &str, content: &[u8]) -> Result<String, String> {
+        let path = format!("/{}/{}", self.bucket, name);
+        let payload_hash = sha256_hex(content);
+        let (date, time) = amz_date(now());
+        let authorization = self.authorization(&path, content_type, &payload_hash, &time, &date);
+        let client = reqwest::blocking::Client::builder()
+            .timeout(UPLOAD_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        client
+            .put(&format!("{}{}", self.endpoint, path))
+            .header("Content-Type", content_type)
+            .header("X-Amz-Content-Sha256", &payload_hash)
+            .header("X-Amz-Date", &time)
+            .header("Authorization", authorization)
+            .body(content.to_vec())
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("S3 upload failed: {}", e))?;
+        if self.public_url.is_empty() {
+            Ok(format!("{}{}", self.endpoint, path))
+        } else {
+            Ok(format!("{}/{}", self.public_url, name))
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use crate::abi;
+use crate::config::Config;
+use crate::indexer;
+use crate::market::now;
+use crate::metadata::{probe_standard, TokenStandard};
+use crate::storage;
+use crate::types::{CollectionRequest, CollectionUpdate};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use web3::types::H160;
+
+// eth.rs reaches the chain through Infura's Goerli endpoint only
+pub const NETWORK: &str = "goerli";
+const MAX_FEE: u16 = 10000;
+
+lazy_static! {
+    static ref COLLECTIONS: Mutex<BTreeMap<u64, Collection>> = Mutex::new(BTreeMap::new());
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Collection {
+    pub id: u64,
+    pub contract_address: H160,
+    pub standard: TokenStandard,
+    pub name: String,
+    pub network: String,
+    pub start_block: u64,
+    // Basis points; unset falls back to the configured platform fee
+    pub platform_fee: Option<u16>,
+    // Royalty set on tokens minted through the collection unless the request names one
+    pub royalty_receiver: Option<H160>,
+    pub royalty_fee: Option<u16>,
+    // ABI for calling functions by name; unset uses the bundled one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<Value>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug)]
+pub enum CollectionError {
+    NotFound,
+    Conflict(String),
+    InvalidRequest(String),
+    Internal(String),
+}
+
+fn check_settings(
+    name: &str,
+    platform_fee: Option<u16>,
+    royalty_receiver: Option<H160>,
+    royalty_fee: Option<u16>,
+) -> Result<(), CollectionError> {
+    if name.trim().is_empty() {
+        return Err(CollectionError::InvalidRequest("a name is required".into()));
+    }
+    if platform_fee.map_or(false, |fee| fee > MAX_FEE)
+        || royalty_fee.map_or(false, |fee| fee > MAX_FEE)
+    {
+        return Err(CollectionError::InvalidRequest(
+            "fees are basis points and can't exceed 10000".into(),
+        ));
+    }
+    if royalty_receiver.is_some() != royalty_fee.is_some() {
+        return Err(CollectionError::InvalidRequest(
+            "royalty_receiver and royalty_fee go together".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn save(collection: &Collection) -> Result<(), CollectionError> {
+    storage::with(|storage| storage.upsert_collection(collection))
+        .map_err(CollectionError::Internal)
+}
+
+// Registers a contract. Without an explicit standard the contract is probed
+// over ERC-165.
+pub async fn create_collection(request: CollectionRequest) -> Result<Collection, CollectionError> {
+    check_settings(
+        &request.name,
+        request.platform_fee,
+        request.royalty_receiver,
+        request.royalty_fee,
+    )?;
+    if let Some(abi) = &request.abi {
+        abi::load(abi).map_err(CollectionError::InvalidRequest)?;
+    }
+    let network = request.network.unwrap_or_else(|| NETWORK.to_string());
+    if network != NETWORK {
+        return Err(CollectionError::InvalidRequest(format!(
+            "this deployment only follows {}",
+            NETWORK
+        )));
+    }
+    if by_address(request.contract_address).is_some() {
+        return Err(CollectionError::Conflict(
+            "contract is already registered".into(),
+        ));
+    }
+    let standard = match request.standard {
+        Some(standard) => standard,
+        None => probe_standard(request.contract_address).await,
+    };
+
+    let mut collections = COLLECTIONS.lock().unwrap();
+    if collections
+        .values()
+        .any(|collection| collection.contract_address == request.contract_address)
+    {
+        return Err(CollectionError::Conflict(
+            "contract is already registered".into(),
+        ));
+    }
+    let time = now();
+    let collection = Collection {
+        id: collections.keys().next_back().map_or(1, |id| id + 1),
+        contract_address: request.contract_address,
+        standard,
+        name: request.name.trim().to_string(),
+        network,
+        start_block: request.start_block,
+        platform_fee: request.platform_fee,
+        royalty_receiver: request.royalty_receiver,
+        royalty_fee: request.royalty_fee,
+        abi: request.abi,
+        created_at: time,
+        updated_at: time,
+    };
+    save(&collection)?;
+    collections.insert(collection.id, collection.clone());
+    drop(collections);
+    indexer::add_contract(collection.contract_address, collection.start_block)
+        .map_err(CollectionError::Internal)?;
+    Ok(collection)
+}
+
+// Only the name and fee settings change; the contract, its standard and
+// where indexing starts are fixed once registered
+pub fn update_collection(id: u64, update: CollectionUpdate) -> Result<Collection, CollectionError> {
+    check_settings(
+        &update.name,
+        update.platform_fee,
+        update.royalty_receiver,
+        update.royalty_fee,
+    )?;
+    let mut collections = COLLECTIONS.lock().unwrap();
+    let collection = collections.get_mut(&id).ok_or(CollectionError::NotFound)?;
+    let mut updated = collection.clone();
+    updated.name = update.name.trim().to_string();
+    updated.platform_fee = update.platform_fee;
+    updated.royalty_receiver = update.royalty_receiver;
+    updated.royalty_fee = update.royalty_fee;
+    updated.updated_at = now();
+    save(&updated)?;
+    *collection = updated.clone();
+    Ok(updated)
+}
+
+// Replaces the ABI functions are called through
+pub fn set_abi(id: u64, abi: Value) -> Result<Collection, CollectionError> {
+    abi::load(&abi).map_err(CollectionError::InvalidRequest)?;
+    let mut collections = COLLECTIONS.lock().unwrap();
+    let collection = collections.get_mut(&id).ok_or(CollectionError::NotFound)?;
+    let mut updated = collection.clone();
+    updated.abi = Some(abi);
+    updated.updated_at = now();
+    save(&updated)?;
+    *collection = updated.clone();
+    Ok(updated)
+}
+
+// Stops following the contract. Indexed history and orders are kept.
+pub fn delete_collection(id: u64) -> Result<bool, String> {
+    let mut collections = COLLECTIONS.lock().unwrap();
+    let collection = match collections.remove(&id) {
+        Some(collection) => collection,
+        None => return Ok(false),
+    };
+    storage::with(|storage| storage.delete_collection(id))?;
+    drop(collections);
+    indexer::remove_contract(collection.contract_address)?;
+    Ok(true)
+}
+
+pub fn list_collections() -> Vec<Collection> {
+    COLLECTIONS.lock().unwrap().values().cloned().collect()
+}
+
+pub fn get_collection(id: u64) -> Option<Collection> {
+    COLLECTIONS.lock().unwrap().get(&id).cloned()
+}
+
+pub fn by_address(contract_address: H160) -> Option<Collection> {
+    COLLECTIONS
+        .lock()
+        .unwrap()
+        .values()
+        .find(|collection| collection.contract_address == contract_address)
+        .cloned()
+}
+
+// Contracts outside the registry, like the configured one, are taken as ERC-721
+pub fn standard(contract_address: H160) -> TokenStandard {
+    by_address(contract_address).map_or(TokenStandard::Erc721, |collection| collection.standard)
+}
+
+// Routes address a collection by its id or its contract address
+pub fn lookup(id: &str) -> Option<Collection> {
+    match id.parse::<u64>() {
+        Ok(id) => get_collection(id),
+        Err(_) => H160::from_str(id).ok().and_then(by_address),
+    }
+}
+
+pub fn contracts() -> Vec<H160> {
+    COLLECTIONS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|collection| collection.contract_address)
+        .collect()
+}
+
+// Where a fresh index starts: the earliest collection's start block
+pub fn start_block() -> u64 {
+    COLLECTIONS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|collection| collection.start_block)
+        .min()
+        .unwrap_or_else(Config::get_start_block)
+}
+
+pub fn platform_fee(contract_address: H160) -> u16 {
+    by_address(contract_address)
+        .and_then(|collection| collection.platform_fee)
+        .unwrap_or_else(|| Config::get_platform_fee(contract_address))
+}
+
+// The configured contract becomes the first collection, so deployments from
+// before the registry keep working unchanged
+pub fn load() -> Result<(), String> {
+    let mut collections = storage::with(|storage| storage.collections())?;
+    if collections.is_empty() {
+        if let Ok(contract_address) = Config::get_contract_address() {
+            let time = now();
+            let collection = Collection {
+                id: 1,
+                contract_address,
+                standard: TokenStandard::Erc721,
+                name: "default".into(),
+                network: NETWORK.into(),
+                start_block: Config::get_start_block(),
+                platform_fee: None,
+                royalty_receiver: None,
+                royalty_fee: None,
+                abi: None,
+                created_at: time,
+                updated_at: time,
+            };
+            storage::with(|storage| storage.upsert_collection(&collection))?;
+            collections.push(collection);
+        }
+    }
+    *COLLECTIONS.lock().unwrap() = collections
+        .into_iter()
+        .map(|collection| (collection.id, collection))
+        .collect();
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::default::Default;
 use std::fs::File;
 use std::io::Read;
@@ -11,12 +12,100 @@ lazy_static! {
     static ref CONFIG: Mutex<Config> = Mutex::new(Config::default());
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AllowedCurrency {
+    pub symbol: String,
+    pub address: String,
+    pub decimals: u8,
+}
+
+// Where uploaded media and metadata are pinned
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AssetStoreConfig {
+    // A Kubo (go-ipfs) node's HTTP API, e.g. http://127.0.0.1:5001
+    Ipfs {
+        api_url: String,
+    },
+    // Any S3-compatible service; objects are addressed path-style
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        #[serde(default)]
+        public_url: String,
+    },
+    // Files on local disk, served back from GET /assets/<name>
+    Filesystem {
+        #[serde(default)]
+        dir: String,
+        #[serde(default)]
+        base_url: String,
+    },
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub infura_apikey: String,
     pub contract_address: String,
     pub account_address: String,
     pub private_key: String,
+    #[serde(default)]
+    pub fee_recipient: String,
+    #[serde(default)]
+    pub default_platform_fee: u16,
+    #[serde(default)]
+    pub platform_fees: HashMap<String, u16>,
+    #[serde(default)]
+    pub currencies: Vec<AllowedCurrency>,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+    #[serde(default)]
+    pub start_block: u64,
+    #[serde(default = "default_log_chunk_size")]
+    pub log_chunk_size: u64,
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    #[serde(default)]
+    pub database_url: String,
+    #[serde(default = "default_job_workers")]
+    pub job_workers: usize,
+    #[serde(default = "default_job_max_attempts")]
+    pub job_max_attempts: u32,
+    #[serde(default)]
+    pub ws_url: String,
+    #[serde(default = "default_ipfs_gateway")]
+    pub ipfs_gateway: String,
+    #[serde(default)]
+    pub asset_store: Option<AssetStoreConfig>,
+    #[serde(default)]
+    pub admin_token: String,
+}
+
+fn default_data_dir() -> String {
+    String::from("data")
+}
+
+fn default_log_chunk_size() -> u64 {
+    2000
+}
+
+fn default_confirmations() -> u64 {
+    12
+}
+
+fn default_job_workers() -> usize {
+    4
+}
+
+fn default_job_max_attempts() -> u32 {
+    5
+}
+
+fn default_ipfs_gateway() -> String {
+    String::from("https://ipfs.io/ipfs/")
 }
 
 impl Default for Config {
@@ -26,6 +115,21 @@ impl Default for Config {
             contract_address: String::from("contract_address"),
             account_address: String::from("account_address"),
             private_key: String::from("private_key"),
+            fee_recipient: String::new(),
+            default_platform_fee: 0,
+            platform_fees: HashMap::new(),
+            currencies: Vec::new(),
+            data_dir: default_data_dir(),
+            start_block: 0,
+            log_chunk_size: default_log_chunk_size(),
+            confirmations: default_confirmations(),
+            database_url: String::new(),
+            job_workers: default_job_workers(),
+            job_max_attempts: default_job_max_attempts(),
+            ws_url: String::new(),
+            ipfs_gateway: default_ipfs_gateway(),
+            asset_store: None,
+            admin_token: String::new(),
         }
     }
 }
@@ -70,4 +174,90 @@ impl Config {
         // private_key = SecretKey::from_str(&config_lock.private_key)?;
         config_lock.private_key.clone()
     }
+
+    pub fn get_fee_recipient() -> Result<Address, Box<dyn std::error::Error>> {
+        let config_lock = CONFIG.lock().unwrap();
+        let address = H160::from_str(&config_lock.fee_recipient)?;
+        Ok(address.into())
+    }
+
+    // Platform fee in basis points for a collection, keyed by contract address
+    pub fn get_platform_fee(contract_address: Address) -> u16 {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock
+            .platform_fees
+            .iter()
+            .find(|(address, _)| H160::from_str(address).ok() == Some(contract_address))
+            .map(|(_, fee)| *fee)
+            .unwrap_or(config_lock.default_platform_fee)
+    }
+
+    pub fn get_currencies() -> Vec<AllowedCurrency> {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.currencies.clone()
+    }
+
+    pub fn get_data_dir() -> String {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.data_dir.clone()
+    }
+
+    pub fn get_start_block() -> u64 {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.start_block
+    }
+
+    pub fn get_log_chunk_size() -> u64 {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.log_chunk_size.max(1)
+    }
+
+    // Blocks on top of a transaction's block before it is reported as final
+    pub fn get_confirmations() -> u64 {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.confirmations.max(1)
+    }
+
+    pub fn get_database_url() -> String {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.database_url.clone()
+    }
+
+    pub fn get_job_workers() -> usize {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.job_workers.max(1)
+    }
+
+    // Attempts a job gets before it is failed for good
+    pub fn get_job_max_attempts() -> u32 {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.job_max_attempts.max(1)
+    }
+
+    // ws:// or wss:// endpoint for eth_subscribe; empty means polling only
+    pub fn get_ws_url() -> String {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.ws_url.clone()
+    }
+
+    // Always ends with a slash so an IPFS path can be appended directly
+    pub fn get_ipfs_gateway() -> String {
+        let config_lock = CONFIG.lock().unwrap();
+        let gateway = config_lock.ipfs_gateway.trim_end_matches('/');
+        if gateway.is_empty() {
+            return default_ipfs_gateway();
+        }
+        format!("{}/", gateway)
+    }
+
+    pub fn get_asset_store() -> Option<AssetStoreConfig> {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.asset_store.clone()
+    }
+
+    // Bearer token for the admin endpoints; empty disables them
+    pub fn get_admin_token() -> String {
+        let config_lock = CONFIG.lock().unwrap();
+        config_lock.admin_token.clone()
+    }
 }
@@ -0,0 +1,175 @@
+use crate::config::Config;
+use crate::eth::erc20_decimals;
+use futures::executor::block_on;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use web3::types::{H160, U256};
+
+pub const ETH_DECIMALS: u8 = 18;
+
+// A payment currency: ETH when `address` is None, otherwise an allow-listed ERC-20
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Currency {
+    pub symbol: String,
+    pub address: Option<H160>,
+    pub decimals: u8,
+}
+
+impl Currency {
+    pub fn eth() -> Currency {
+        Currency {
+            symbol: String::from("ETH"),
+            address: None,
+            decimals: ETH_DECIMALS,
+        }
+    }
+
+    pub fn parse(&self, amount: &str) -> Result<U256, String> {
+        parse_units(amount, self.decimals)
+    }
+
+    pub fn format(&self, amount: U256) -> String {
+        format!("{} {}", format_units(amount, self.decimals), self.symbol)
+    }
+}
+
+// Looks up a currency by symbol or token address in the allow-list; None and "ETH" mean ETH
+pub fn resolve(currency: Option<&str>) -> Result<Currency, String> {
+    let currency = match currency {
+        None => return Ok(Currency::eth()),
+        Some(currency) if currency.eq_ignore_ascii_case("ETH") => return Ok(Currency::eth()),
+        Some(currency) => currency,
+    };
+    let address = H160::from_str(currency).ok();
+    for allowed in Config::get_currencies() {
+        let allowed_address = H160::from_str(&allowed.address).map_err(|e| e.to_string())?;
+        if allowed.symbol.eq_ignore_ascii_case(currency) || Some(allowed_address) == address {
+            return Ok(Currency {
+                symbol: allowed.symbol,
+                address: Some(allowed_address),
+                decimals: allowed.decimals,
+            });
+        }
+    }
+    Err(format!("currency {} is not allowed", currency))
+}
+
+// Amounts are parsed and formatted with the configured decimals, so they must
+// match the token's. Tokens that can't be reached are checked again next start.
+pub fn check_decimals() -> Result<(), String> {
+    for allowed in Config::get_currencies() {
+        let address = H160::from_str(&allowed.address).map_err(|e| e.to_string())?;
+        match block_on(erc20_decimals(address)) {
+            Ok(decimals) if decimals != allowed.decimals => {
+                return Err(format!(
+                    "{} has {} decimals on chain, not {} as configured",
+                    allowed.symbol, decimals, allowed.decimals
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!(
+                "Warning: couldn't check the decimals of {}: {}",
+                allowed.symbol, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+// Parses "1.5" into base units; a 0x-prefixed value is taken as base units already
+pub fn parse_units(amount: &str, decimals: u8) -> Result<U256, String> {
+    let amount = amount.trim();
+    if let Some(hex) = amount.strip_prefix("0x") {
+        return U256::from_str(hex).map_err(|e| e.to_string());
+    }
+
+    let (integer, fraction) = match amount.find('.') {
+        Some(index) => (&amount[..index], &amount[index + 1..]),
+        None => (amount, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(format!("invalid amount {}", amount));
+    }
+    if !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(format!("invalid amount {}", amount));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(format!(
+            "amount {} has more than {} decimals",
+            amount, decimals
+        ));
+    }
+
+    let digits = format!(
+        "{}{}{}",
+        integer,
+        fraction,
+        "0".repeat(decimals as usize - fraction.len())
+    );
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_dec_str(digits).map_err(|e| format!("invalid amount {}: {:?}", amount, e))
+}
+
+pub fn format_units(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        integer.to_string()
+    } else {
+        format!("{}.{}", integer, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimal_amounts() {
+        assert_eq!(parse_units("1.5", 18), Ok(U256::exp10(17) * 15));
+        assert_eq!(parse_units("2", 6), Ok(U256::from(2_000_000)));
+        assert_eq!(parse_units(".25", 2), Ok(U256::from(25)));
+        assert_eq!(parse_units("3.", 2), Ok(U256::from(300)));
+        assert_eq!(parse_units(" 0.000 ", 18), Ok(U256::zero()));
+        assert_eq!(parse_units("0x10", 18), Ok(U256::from(16)));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        assert!(parse_units("", 18).is_err());
+        assert!(parse_units(".", 18).is_err());
+        assert!(parse_units("-1", 18).is_err());
+        assert!(parse_units("1e18", 18).is_err());
+        assert!(parse_units("1.2.3", 18).is_err());
+        assert!(parse_units("0.001", 2).is_err());
+        assert!(parse_units("1", 78).is_err());
+    }
+
+    #[test]
+    fn formats_base_units() {
+        assert_eq!(format_units(U256::exp10(17) * 15, 18), "1.5");
+        assert_eq!(format_units(U256::from(2_000_000), 6), "2");
+        assert_eq!(format_units(U256::from(5), 3), "0.005");
+        assert_eq!(format_units(U256::zero(), 18), "0");
+        assert_eq!(format_units(U256::from(42), 0), "42");
+    }
+
+    #[test]
+    fn formatting_round_trips() {
+        for amount in &["0", "1", "0.000000000000000001", "123.456", "1000000"] {
+            let units = parse_units(amount, 18).unwrap();
+            assert_eq!(format_units(units, 18), *amount);
+        }
+        assert_eq!(Currency::eth().format(U256::exp10(18)), "1 ETH");
+    }
+}
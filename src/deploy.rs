@@ -0,0 +1,137 @@
+use crate::metadata::TokenStandard;
+use crate::types::DeployRequest;
+use serde::Deserialize;
+use web3::ethabi::{Contract, ParamType, Token};
+use web3::types::U256;
+
+// The parts of a Remix build artifact needed to deploy it
+#[derive(Deserialize)]
+struct Artifact {
+    abi: serde_json::Value,
+    data: ArtifactData,
+}
+
+#[derive(Deserialize)]
+struct ArtifactData {
+    bytecode: Bytecode,
+}
+
+#[derive(Deserialize)]
+struct Bytecode {
+    object: String,
+}
+
+pub struct Deployment {
+    // Bytecode followed by the encoded constructor arguments
+    pub code: Vec<u8>,
+    // The constructor doesn't take the royalty, so it is set once deployed
+    pub set_royalty: bool,
+    // Stored with the collection so its functions can be called by name
+    pub abi: serde_json::Value,
+}
+
+fn artifact(standard: TokenStandard) -> (&'static str, &'static [u8]) {
+    match standard {
+        TokenStandard::Erc721 => (
+            "MyERC721",
+            &include_bytes!("../abi/artifacts/MyERC721.json")[..],
+        ),
+        TokenStandard::Erc1155 => (
+            "MyERC1155",
+            &include_bytes!("../abi/artifacts/MyERC1155.json")[..],
+        ),
+    }
+}
+
+// Constructor parameters are matched by name. A base URI and royalty that
+// weren't requested are passed as empty, which the bundled contracts ignore.
+fn argument(
+    request: &DeployRequest,
+    name: &str,
+    kind: &ParamType,
+) -> Result<Option<Token>, String> {
+    let name = name.trim_matches('_').to_ascii_lowercase();
+    let missing = |what: &str| format!("the contract's constructor needs {}", what);
+    let token = match kind {
+        ParamType::String if name == "name" => Token::String(request.name.clone()),
+        ParamType::String if name == "symbol" => {
+            Token::String(request.symbol.clone().ok_or_else(|| missing("a symbol"))?)
+        }
+        ParamType::String if name == "baseuri" || name == "uri" => {
+            Token::String(request.base_uri.clone().unwrap_or_default())
+        }
+        ParamType::Address if name == "royaltyreceiver" || name == "receiver" => {
+            Token::Address(request.royalty_receiver.unwrap_or_default())
+        }
+        ParamType::Uint(_) if name == "royaltyfee" || name == "feenumerator" => {
+            Token::Uint(U256::from(request.royalty_fee.unwrap_or_default()))
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(token))
+}
+
+// Builds the creation code for the bundled artifact of the requested standard.
+// Parameters the artifact can't take are refused rather than dropped.
+pub fn prepare(request: &DeployRequest) -> Result<Deployment, String> {
+    if request.royalty_receiver.is_some() != request.royalty_fee.is_some() {
+        return Err("royalty_receiver and royalty_fee go together".into());
+    }
+    if request.royalty_fee.map_or(false, |fee| fee > 10000) {
+        return Err("royalty fee is above 10000 basis points".into());
+    }
+    let (contract_name, json) = artifact(request.standard);
+    let artifact: Artifact = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    let abi = Contract::load(artifact.abi.to_string().as_bytes()).map_err(|e| e.to_string())?;
+    let bytecode = hex::decode(artifact.data.bytecode.object.trim_start_matches("0x"))
+        .map_err(|e| format!("{} bytecode is unusable: {}", contract_name, e))?;
+    if bytecode.is_empty() {
+        return Err(format!("{} has no bytecode", contract_name));
+    }
+
+    let inputs = abi
+        .constructor
+        .as_ref()
+        .map_or(&[][..], |constructor| &constructor.inputs[..]);
+    let mut tokens = Vec::new();
+    for input in inputs {
+        let token = argument(request, &input.name, &input.kind)?.ok_or_else(|| {
+            format!(
+                "{} takes an unsupported constructor parameter {}",
+                contract_name, input.name
+            )
+        })?;
+        tokens.push(token);
+    }
+    let takes = |names: &[&str]| {
+        inputs.iter().any(|input| {
+            names.contains(&input.name.trim_matches('_').to_ascii_lowercase().as_str())
+        })
+    };
+    if !takes(&["name"]) {
+        return Err(format!("{} has a fixed name", contract_name));
+    }
+    if request.symbol.is_some() && !takes(&["symbol"]) {
+        return Err(format!("{} has a fixed symbol", contract_name));
+    }
+    if request.base_uri.is_some() && !takes(&["baseuri", "uri"]) {
+        return Err(format!("{} doesn't take a base URI", contract_name));
+    }
+    let set_royalty =
+        request.royalty_receiver.is_some() && !takes(&["royaltyreceiver", "receiver"]);
+    if set_royalty && abi.function("setDefaultRoyalty").is_err() {
+        return Err(format!("{} doesn't support royalties", contract_name));
+    }
+
+    let code = match &abi.constructor {
+        Some(constructor) => constructor
+            .encode_input(bytecode, &tokens)
+            .map_err(|e| e.to_string())?,
+        None => bytecode,
+    };
+    Ok(Deployment {
+        code,
+        set_royalty,
+        abi: artifact.abi,
+    })
+}
@@ -1,9 +1,68 @@
 use crate::config;
+use crate::jobs;
+use crate::journal;
+use crate::receipts;
 
-use crate::types::NftBalance;
+use crate::types::{BlockHeader, NftBalance};
 use std::{str::FromStr, time::Duration};
 use web3::contract::Options;
-use web3::types::{Address, TransactionReceipt, H160, H256, U256};
+use web3::ethabi::{decode, ParamType, Token};
+use web3::signing::keccak256;
+use web3::types::{
+    Address, BlockId, BlockNumber, Bytes, CallRequest, FilterBuilder, Log, TransactionReceipt,
+    TransactionRequest, H160, H256, U256, U64,
+};
+
+pub const INTERFACE_ID_ERC165: [u8; 4] = [0x01, 0xff, 0xc9, 0xa7];
+pub const INTERFACE_ID_ERC721: [u8; 4] = [0x80, 0xac, 0x58, 0xcd];
+pub const INTERFACE_ID_ERC721_METADATA: [u8; 4] = [0x5b, 0x5e, 0x13, 0x9f];
+pub const INTERFACE_ID_ERC2981: [u8; 4] = [0x2a, 0x55, 0x20, 0x5a];
+pub const INTERFACE_ID_ERC721_ENUMERABLE: [u8; 4] = [0x78, 0x0e, 0x9d, 0x63];
+pub const INTERFACE_ID_ERC1155: [u8; 4] = [0xd9, 0xb6, 0x7a, 0x26];
+pub const INTERFACE_ID_ERC1155_METADATA_URI: [u8; 4] = [0x0e, 0x89, 0x34, 0x1c];
+
+// Returned instead of a receipt when a job has sent its transaction
+pub const NOT_MINED: &str = "the transaction was sent and is not mined yet";
+// Seconds a request waits for its transaction to be mined
+const RECEIPT_TIMEOUT: u64 = 600;
+
+// Result of an eth_call that reached the node
+pub enum CallOutcome {
+    Returned(Vec<u8>),
+    // The node's error, usually a revert
+    Reverted(String),
+}
+
+// Records a transaction as soon as it is sent, before waiting for the receipt
+fn sent(tx_hash: H256) {
+    journal::note_transaction(tx_hash);
+    jobs::note_transaction(tx_hash);
+}
+
+// Job workers don't wait: the job is queued again and picks the receipt up
+// with the hash noted in sent(). Other callers wait up to RECEIPT_TIMEOUT.
+async fn wait_for_receipt(
+    web3: &web3::Web3<web3::transports::Http>,
+    tx_hash: H256,
+) -> Result<TransactionReceipt, String> {
+    if jobs::in_job() {
+        return Err(NOT_MINED.into());
+    }
+    for _ in 0..RECEIPT_TIMEOUT {
+        match web3.eth().transaction_receipt(tx_hash).await {
+            Ok(Some(receipt)) => {
+                receipts::track(&receipt);
+                return Ok(receipt);
+            }
+            Ok(None) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Err(format!(
+        "transaction {:?} was not mined within {} seconds",
+        tx_hash, RECEIPT_TIMEOUT
+    ))
+}
 
 pub async fn get_balance(address: &str) -> Result<NftBalance, String> {
     let infura_apikey = config::Config::get_infura_apikey();
@@ -22,7 +81,7 @@ pub async fn get_balance(address: &str) -> Result<NftBalance, String> {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
-        .unwrap();
+        .map_err(|e| e.to_string())?;
     let balance: U256 = rt
         .block_on(contract.query("balanceOf", (user_address,), None, options, None))
         .map_err(|e| e.to_string())?;
@@ -70,25 +129,13 @@ pub async fn mint(
         Err(e) => {
             // 处理错误，可以打印错误信息或者进行其他处理
             eprintln!("Error: {}", e);
-            // 在这里返回一个默认值或者使用 panic!() 中止程序
-            // 或者选择其他恰当的错误处理方法
-            panic!()
+            return Err(e.to_string());
         }
     };
 
-    // Now we need to wait for the transaction to be mined
-    let receipt: TransactionReceipt = loop {
-        match web3.eth().transaction_receipt(tx_hash).await {
-            Ok(Some(receipt)) => break Ok(receipt),
-            Ok(None) => {
-                // Wait for a while before trying again
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => break Err(e.to_string()),
-        }
-    }?;
+    sent(tx_hash);
 
-    Ok(receipt)
+    wait_for_receipt(&web3, tx_hash).await
 }
 
 pub async fn approve(
@@ -130,22 +177,13 @@ pub async fn approve(
         Ok(result) => result,
         Err(e) => {
             eprintln!("Error: {}", e);
-            panic!()
+            return Err(e.to_string());
         }
     };
 
-    // Wait for the transaction to be mined
-    let receipt: TransactionReceipt = loop {
-        match web3.eth().transaction_receipt(tx_hash).await {
-            Ok(Some(receipt)) => break Ok(receipt),
-            Ok(None) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => break Err(e.to_string()),
-        }
-    }?;
+    sent(tx_hash);
 
-    Ok(receipt)
+    wait_for_receipt(&web3, tx_hash).await
 }
 
 pub async fn safe_transfer_from(
@@ -190,22 +228,13 @@ pub async fn safe_transfer_from(
         Ok(result) => result,
         Err(e) => {
             eprintln!("Error: {}", e);
-            panic!()
+            return Err(e.to_string());
         }
     };
 
-    // Wait for the transaction to be mined
-    let receipt: TransactionReceipt = loop {
-        match web3.eth().transaction_receipt(tx_hash).await {
-            Ok(Some(receipt)) => break Ok(receipt),
-            Ok(None) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => break Err(e.to_string()),
-        }
-    }?;
+    sent(tx_hash);
 
-    Ok(receipt)
+    wait_for_receipt(&web3, tx_hash).await
 }
 
 pub async fn safe_transfer_from_with_data(
@@ -251,22 +280,13 @@ pub async fn safe_transfer_from_with_data(
         Ok(result) => result,
         Err(e) => {
             eprintln!("Error: {}", e);
-            panic!()
+            return Err(e.to_string());
         }
     };
 
-    // Wait for the transaction to be mined
-    let receipt: TransactionReceipt = loop {
-        match web3.eth().transaction_receipt(tx_hash).await {
-            Ok(Some(receipt)) => break Ok(receipt),
-            Ok(None) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => break Err(e.to_string()),
-        }
-    }?;
+    sent(tx_hash);
 
-    Ok(receipt)
+    wait_for_receipt(&web3, tx_hash).await
 }
 
 pub async fn set_approval_for_all(
@@ -310,22 +330,13 @@ pub async fn set_approval_for_all(
         Ok(result) => result,
         Err(e) => {
             eprintln!("Error: {}", e);
-            panic!()
+            return Err(e.to_string());
         }
     };
 
-    // Wait for the transaction to be mined
-    let receipt: TransactionReceipt = loop {
-        match web3.eth().transaction_receipt(tx_hash).await {
-            Ok(Some(receipt)) => break Ok(receipt),
-            Ok(None) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => break Err(e.to_string()),
-        }
-    }?;
+    sent(tx_hash);
 
-    Ok(receipt)
+    wait_for_receipt(&web3, tx_hash).await
 }
 
 pub async fn transfer_from(
@@ -370,20 +381,795 @@ pub async fn transfer_from(
         Ok(result) => result,
         Err(e) => {
             eprintln!("Error: {}", e);
-            panic!()
+            return Err(e.to_string());
         }
     };
 
-    // Wait for the transaction to be mined
-    let receipt: TransactionReceipt = loop {
-        match web3.eth().transaction_receipt(tx_hash).await {
-            Ok(Some(receipt)) => break Ok(receipt),
-            Ok(None) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Err(e) => break Err(e.to_string()),
-        }
-    }?;
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn burn(
+    contract_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    token_id: U256,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721Burnable.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // ERC721Burnable burn, allowed for the owner and approved accounts
+    let params = (token_id,);
+
+    // Send the transaction
+    let tx_hash: H256 = contract
+        .call("burn", params, my_account, options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn erc1155_burn(
+    contract_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    account: H160,
+    token_id: U256,
+    amount: U256,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC1155Burnable.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // ERC1155Burnable burn of `amount` of the account's tokens
+    let params = (account, token_id, amount);
+
+    // Send the transaction
+    let tx_hash: H256 = contract
+        .call("burn", params, my_account, options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn send_value(
+    my_account: Address,
+    my_private_key: &str,
+    to: H160,
+    value: U256,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // Plain value transfer
+    let tx = TransactionRequest {
+        from: my_account,
+        to: Some(to),
+        value: Some(value),
+        ..Default::default()
+    };
+
+    // Send the transaction
+    let tx_hash: H256 = web3
+        .eth()
+        .send_transaction(tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+// Proves the caller holds the account's key without sending anything
+pub async fn verify_account(account: Address, private_key: &str) -> Result<(), String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+
+    let unlock = web3
+        .personal()
+        .unlock_account(account, private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+    Ok(())
+}
+
+// Sends already encoded calldata to a contract, with optional ether attached
+pub async fn send_data(
+    contract_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    data: Vec<u8>,
+    value: Option<U256>,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    let tx = TransactionRequest {
+        from: my_account,
+        to: Some(contract_address),
+        value,
+        data: Some(Bytes(data)),
+        ..Default::default()
+    };
+
+    // Send the transaction
+    let tx_hash: H256 = web3
+        .eth()
+        .send_transaction(tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+// Sends a contract creation transaction; `code` is the bytecode followed by
+// the encoded constructor arguments
+pub async fn deploy_contract(
+    my_account: Address,
+    my_private_key: &str,
+    code: Vec<u8>,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // No recipient makes it a contract creation
+    let tx = TransactionRequest {
+        from: my_account,
+        to: None,
+        data: Some(Bytes(code)),
+        ..Default::default()
+    };
+
+    // Send the transaction
+    let tx_hash: H256 = web3
+        .eth()
+        .send_transaction(tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn supports_interface(contract_address: H160, interface_id: [u8; 4]) -> Result<bool, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721Royalty.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let supported: bool = contract
+        .query("supportsInterface", (interface_id,), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(supported)
+}
+
+pub async fn royalty_info(
+    contract_address: H160,
+    token_id: U256,
+    sale_price: U256,
+) -> Result<Option<(H160, U256)>, String> {
+    // Contracts without ERC-165 revert here, which simply means no royalty
+    let supported = supports_interface(contract_address, INTERFACE_ID_ERC2981)
+        .await
+        .unwrap_or(false);
+    if !supported {
+        return Ok(None);
+    }
 
-    Ok(receipt)
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721Royalty.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let (receiver, amount): (H160, U256) = contract
+        .query("royaltyInfo", (token_id, sale_price), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Some((receiver, amount)))
+}
+
+pub async fn set_default_royalty(
+    contract_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    receiver: H160,
+    fee_numerator: u128,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721Royalty.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // setDefaultRoyalty operation, fee is in basis points of the sale price
+    let params = (receiver, fee_numerator);
+
+    // Send the transaction
+    let tx_hash: H256 = contract
+        .call("setDefaultRoyalty", params, my_account, options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn set_token_royalty(
+    contract_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    token_id: U256,
+    receiver: H160,
+    fee_numerator: u128,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721Royalty.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // setTokenRoyalty operation, fee is in basis points of the sale price
+    let params = (token_id, receiver, fee_numerator);
+
+    // Send the transaction
+    let tx_hash: H256 = contract
+        .call("setTokenRoyalty", params, my_account, options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub fn minted_token_id(receipt: &TransactionReceipt) -> Option<U256> {
+    let transfer_topic = H256::from(keccak256(b"Transfer(address,address,uint256)"));
+    receipt
+        .logs
+        .iter()
+        .find(|log| {
+            log.topics.len() == 4 && log.topics[0] == transfer_topic && log.topics[1].is_zero()
+        })
+        .map(|log| U256::from(log.topics[3].as_bytes()))
+}
+
+pub async fn erc20_balance_of(token_address: H160, owner: H160) -> Result<U256, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        token_address,
+        include_bytes!("../abi/ERC20.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let balance: U256 = contract
+        .query("balanceOf", (owner,), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(balance)
+}
+
+pub async fn erc20_allowance(token_address: H160, owner: H160, spender: H160) -> Result<U256, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        token_address,
+        include_bytes!("../abi/ERC20.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let allowance: U256 = contract
+        .query("allowance", (owner, spender), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(allowance)
+}
+
+pub async fn erc20_decimals(token_address: H160) -> Result<u8, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        token_address,
+        include_bytes!("../abi/ERC20.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let decimals: u8 = contract
+        .query("decimals", (), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(decimals)
+}
+
+pub async fn erc20_approve(
+    token_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    spender: H160,
+    amount: U256,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        token_address,
+        include_bytes!("../abi/ERC20.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // ERC-20 approve operation
+    let params = (spender, amount);
+
+    // Send the transaction
+    let tx_hash: H256 = contract
+        .call("approve", params, my_account, options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn erc20_transfer_from(
+    token_address: H160,
+    my_account: Address,
+    my_private_key: &str,
+    from: H160,
+    to: H160,
+    amount: U256,
+) -> Result<TransactionReceipt, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        token_address,
+        include_bytes!("../abi/ERC20.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+
+    let unlock = web3
+        .personal()
+        .unlock_account(my_account, my_private_key, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !unlock {
+        return Err("Failed to unlock account".into());
+    }
+
+    // ERC-20 transferFrom operation
+    let params = (from, to, amount);
+
+    // Send the transaction
+    let tx_hash: H256 = contract
+        .call("transferFrom", params, my_account, options)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    sent(tx_hash);
+
+    wait_for_receipt(&web3, tx_hash).await
+}
+
+pub async fn block_number() -> Result<u64, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let number = web3.eth().block_number().await.map_err(|e| e.to_string())?;
+    Ok(number.as_u64())
+}
+
+pub async fn block_header(number: u64) -> Result<Option<BlockHeader>, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let block = web3
+        .eth()
+        .block(BlockId::Number(BlockNumber::Number(U64::from(number))))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(block.and_then(|block| {
+        Some(BlockHeader {
+            number: block.number?.as_u64(),
+            hash: block.hash?,
+            parent_hash: block.parent_hash,
+            timestamp: block.timestamp.as_u64(),
+        })
+    }))
+}
+
+pub async fn transaction_receipt(tx_hash: H256) -> Result<Option<TransactionReceipt>, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    web3.eth()
+        .transaction_receipt(tx_hash)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+pub async fn code_at(address: H160) -> Result<Vec<u8>, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let code = web3
+        .eth()
+        .code(address, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(code.0)
+}
+
+// Runs `data` against the contract without sending a transaction
+pub async fn eth_call(
+    contract_address: H160,
+    from: Option<H160>,
+    data: Vec<u8>,
+    value: Option<U256>,
+) -> Result<CallOutcome, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let request = CallRequest {
+        from,
+        to: Some(contract_address),
+        data: Some(Bytes(data)),
+        value,
+        ..Default::default()
+    };
+    match web3.eth().call(request, None).await {
+        Ok(output) => Ok(CallOutcome::Returned(output.0)),
+        Err(web3::Error::Rpc(e)) => Ok(CallOutcome::Reverted(revert_reason(e.message, e.data))),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// The reason string of `Error(string)` or the code of `Panic(uint256)` when the
+// node returns the revert data, otherwise the node's message
+fn revert_reason(message: String, data: Option<serde_json::Value>) -> String {
+    let data = data
+        .as_ref()
+        .and_then(|data| data.as_str())
+        .and_then(|data| hex::decode(data.trim_start_matches("0x")).ok())
+        .unwrap_or_default();
+    if data.len() < 4 {
+        return message;
+    }
+    let (selector, payload) = data.split_at(4);
+    if selector == [0x08, 0xc3, 0x79, 0xa0] {
+        if let Ok(tokens) = decode(&[ParamType::String], payload) {
+            if let Some(Token::String(reason)) = tokens.into_iter().next() {
+                return reason;
+            }
+        }
+    } else if selector == [0x4e, 0x48, 0x7b, 0x71] && payload.len() == 32 {
+        return format!("panic 0x{:x}", U256::from_big_endian(payload));
+    }
+    message
+}
+
+// Runs a transaction with eth_call at the pending block and estimates its gas
+// without sending it. `to` is None for a contract creation.
+pub async fn simulate(
+    from: H160,
+    to: Option<H160>,
+    data: Vec<u8>,
+    value: Option<U256>,
+) -> Result<(CallOutcome, Option<U256>), String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let request = CallRequest {
+        from: Some(from),
+        to,
+        data: Some(Bytes(data)),
+        value,
+        ..Default::default()
+    };
+    let pending = BlockId::Number(BlockNumber::Pending);
+    let output = match web3.eth().call(request.clone(), Some(pending)).await {
+        Ok(output) => output,
+        Err(web3::Error::Rpc(e)) => {
+            let reason = revert_reason(e.message, e.data);
+            return Ok((CallOutcome::Reverted(reason), None));
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    // The estimate only fails here when the pending state moved in between
+    let gas = match web3
+        .eth()
+        .estimate_gas(request, Some(BlockNumber::Pending))
+        .await
+    {
+        Ok(gas) => Some(gas),
+        Err(web3::Error::Rpc(_)) => None,
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok((CallOutcome::Returned(output.0), gas))
+}
+
+pub async fn get_logs(
+    contract_addresses: Vec<H160>,
+    topics: Vec<H256>,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<Log>, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+
+    // Any of `topics` in the first position, i.e. any of the given events
+    let filter = FilterBuilder::default()
+        .address(contract_addresses)
+        .topics(Some(topics), None, None, None)
+        .from_block(BlockNumber::Number(U64::from(from_block)))
+        .to_block(BlockNumber::Number(U64::from(to_block)))
+        .build();
+    let logs = web3.eth().logs(filter).await.map_err(|e| e.to_string())?;
+    Ok(logs.into_iter().filter(|log| !log.is_removed()).collect())
+}
+
+pub async fn balance_of(contract_address: H160, owner: H160) -> Result<U256, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let balance: U256 = contract
+        .query("balanceOf", (owner,), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(balance)
+}
+
+pub async fn token_of_owner_by_index(
+    contract_address: H160,
+    owner: H160,
+    index: U256,
+) -> Result<U256, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721Enumerable.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let token_id: U256 = contract
+        .query("tokenOfOwnerByIndex", (owner, index), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(token_id)
+}
+
+pub async fn token_uri(contract_address: H160, token_id: U256) -> Result<String, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC721.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let uri: String = contract
+        .query("tokenURI", (token_id,), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(uri)
+}
+
+// ERC-1155 metadata URI; clients substitute `{id}` themselves
+pub async fn erc1155_uri(contract_address: H160, token_id: U256) -> Result<String, String> {
+    let infura_apikey = config::Config::get_infura_apikey();
+    let transport_url = format!("https://goerli.infura.io/v3/{}", infura_apikey);
+    let transport = web3::transports::Http::new(&transport_url).map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let contract = web3::contract::Contract::from_json(
+        web3.eth(),
+        contract_address,
+        include_bytes!("../abi/ERC1155.json"),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let options = Options::default();
+    let uri: String = contract
+        .query("uri", (token_id,), None, options, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(uri)
 }
@@ -1,19 +1,120 @@
+use crate::abi;
+use crate::admin::Admin;
+use crate::assets::{self, media_type, Asset};
+use crate::collections::{
+    create_collection, delete_collection, get_collection, list_collections, lookup, set_abi,
+    by_address, standard, update_collection, Collection, CollectionError,
+};
 use crate::config::Config;
-use crate::eth::{
-    approve, get_balance, mint, safe_transfer_from, safe_transfer_from_with_data,
-    set_approval_for_all, transfer_from,
+use crate::currency::{resolve, Currency};
+use crate::eth::{erc20_allowance, erc20_balance_of, eth_call, get_balance, CallOutcome};
+use crate::indexer::{status, token_state, IndexerStatus, TokenState};
+use crate::interfaces::{self, Capabilities};
+use crate::jobs::{enqueue, get_job, list_jobs, Job, JobRequest, JobStatus};
+use crate::journal::{self, get_entry, IdempotencyKey, JournalRecord};
+use crate::ledger::{payouts, to_csv, Payout};
+use crate::market::{
+    cancel_listing, cancel_offer, create_dutch_auction, create_fixed_price, create_offer,
+    finalize, get_listing, get_offer, list_listings, list_offers, now, purchase, DutchAuction,
+    Fill, FixedPrice, Listing, MarketError, Offer,
 };
+use crate::media::thumbnail;
+use crate::metadata::{token_metadata, TokenMetadata, TokenStandard};
+use crate::orderbook::{best_offer, floor, order_book, trigger_match, Ask, Bid, OrderBook};
+use crate::preflight::{self, PreflightError};
+use crate::receipts::{get_transaction, TrackedTx};
+use crate::signatures;
+use crate::simulation;
+use crate::stream::{set_workers, subscribe, EventStream, Filter};
+use crate::tokens::{owned_tokens, token_history, HistoryEntry};
 use crate::types::{
-    ApproveResponse, MintResponse, NftBalance, SetApprovalForAllResponse, TransferFormDataResponse,
-    TransferFormResponse, TransferFromResponse,
+    ApproveResponse, AssetRequest, BurnRequest, CancelRequest, CollectionRequest, CollectionUpdate,
+    ContractCallJob, ContractCallRequest, ContractCallResult, DeployRequest, DryRun,
+    DutchAuctionResponse, Erc20ApproveResponse, Erc20Balance, FixedPriceResponse, MintResponse,
+    NftBalance, OfferResponse, OwnedTokens, PurchaseResponse, RoyaltyResponse,
+    SetApprovalForAllResponse, TransferFormDataResponse, TransferFormResponse,
+    TransferFromResponse, WebhookRequest,
+};
+use crate::webhooks::{
+    create_webhook, delete_webhook, deliveries, get_webhook, list_webhooks, redeliver, Delivery,
+    Webhook,
 };
-use base64::decode;
 use futures::executor::block_on;
-use rocket::http::Status;
-use rocket::{get, post, routes};
+use rocket::http::{ContentType, Status};
+use rocket::request::Request;
+use rocket::response::content::Content;
+use rocket::response::status::{Accepted, Custom};
+use rocket::response::{self, Responder};
+use rocket::{delete, get, post, put, routes};
 use rocket_contrib::json::Json;
+use serde::Serialize;
+use serde_json::json;
 use std::str::FromStr;
-use web3::types::{Address, H160};
+use web3::ethabi::Function;
+use web3::types::{Address, H160, H256, U256};
+
+// A failed request. Operations the contract doesn't implement get a 422,
+// arguments that can't be encoded a 400 and transfers that would revert a
+// 4xx, all with the reason in the body. An order with a bad signature gets a
+// 403.
+pub enum Rejection {
+    Status(Status),
+    Unsupported(String),
+    Invalid(String),
+    Forbidden(String),
+    Preflight(PreflightError),
+}
+
+impl From<Status> for Rejection {
+    fn from(status: Status) -> Rejection {
+        Rejection::Status(status)
+    }
+}
+
+impl<'r> Responder<'r> for Rejection {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            Rejection::Status(status) => Err(status),
+            Rejection::Unsupported(error) => {
+                Custom(Status::UnprocessableEntity, Json(json!({ "error": error })))
+                    .respond_to(request)
+            }
+            Rejection::Invalid(error) => {
+                Custom(Status::BadRequest, Json(json!({ "error": error }))).respond_to(request)
+            }
+            Rejection::Forbidden(error) => {
+                Custom(Status::Forbidden, Json(json!({ "error": error }))).respond_to(request)
+            }
+            Rejection::Preflight(e) => {
+                let (status, error) = match e {
+                    PreflightError::NotFound(error) => (Status::NotFound, error),
+                    PreflightError::NotOwner(error) => (Status::Conflict, error),
+                    PreflightError::NotAuthorized(error) => (Status::Forbidden, error),
+                    PreflightError::BadRecipient(error) => (Status::UnprocessableEntity, error),
+                    PreflightError::Invalid(error) => (Status::BadRequest, error),
+                    PreflightError::Chain(e) => {
+                        eprintln!("Error: {}", e);
+                        return Err(Status::BadGateway);
+                    }
+                };
+                Custom(status, Json(json!({ "error": error }))).respond_to(request)
+            }
+        }
+    }
+}
+
+// Refuses an operation the contract doesn't implement
+fn require(
+    contract_address: H160,
+    check: fn(&Capabilities) -> Result<(), String>,
+) -> Result<(), Rejection> {
+    let capabilities =
+        block_on(interfaces::capabilities(contract_address, false)).map_err(|e| {
+            eprintln!("Error: {}", e);
+            Status::BadGateway
+        })?;
+    check(&capabilities).map_err(Rejection::Unsupported)
+}
 
 #[get("/nft_balance?<address>")]
 fn nft_balance(address: String) -> Result<Json<NftBalance>, Status> {
@@ -23,142 +124,1319 @@ fn nft_balance(address: String) -> Result<Json<NftBalance>, Status> {
     }
 }
 
-#[post("/mint", data = "<data>")]
-fn nft_mint(data: Json<MintResponse>) -> Result<Json<String>, Status> {
-    let contract_address =
-        Config::get_contract_address().map_err(|e| rocket::http::Status::InternalServerError)?;
-    let user_address: Address = H160::from_str(&data.account_address)
-        .map_err(|e| rocket::http::Status::InternalServerError)?;
-    let my_address: Address =
-        Config::get_my_account().map_err(|e| rocket::http::Status::InternalServerError)?;
+// Routes without a collection work on the configured contract
+fn default_contract() -> Result<H160, Status> {
+    Config::get_contract_address().map_err(|_| Status::InternalServerError)
+}
 
-    match block_on(mint(
-        contract_address,
-        user_address,
-        my_address,
-        &data.private_key,
-        &data.token_uri,
-        data.amount,
-    )) {
-        Ok(receipt) => Ok(Json(receipt.block_hash.unwrap_or_default().to_string())),
-        Err(_) => Err(Status::InternalServerError),
+// Chain writes are queued for the job workers. The response is the queued job,
+// which can be polled at /jobs/<id>.
+fn submit(contract_address: Option<H160>, request: JobRequest) -> Result<Json<Job>, Status> {
+    request.validate().map_err(|_| Status::BadRequest)?;
+    match enqueue(contract_address, request) {
+        Ok(job) => {
+            journal::note_job(job.id);
+            Ok(Json(job))
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+// A write's response: the queued job, or with `?dry_run=true` the simulated
+// transaction, which is never sent
+pub enum Submission {
+    Queued(Json<Job>),
+    Simulated(DryRun),
+}
+
+impl<'r> Responder<'r> for Submission {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        match self {
+            Submission::Queued(job) => Accepted(Some(job)).respond_to(request),
+            Submission::Simulated(dry_run) => Json(dry_run).respond_to(request),
+        }
+    }
+}
+
+fn simulate(contract_address: Option<H160>, request: &JobRequest) -> Result<Submission, Status> {
+    match block_on(simulation::dry_run(contract_address, request)) {
+        Ok(result) => Ok(Submission::Simulated(result)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::BadGateway)
+        }
+    }
+}
+
+// Token operations need the contract to implement the standard they use.
+// Minting goes through the contract's own mint, which no interface covers, and
+// ERC-20 approvals, deployments and raw calls don't touch the token contract's
+// interfaces.
+fn require_for(contract_address: H160, request: &JobRequest) -> Result<(), Rejection> {
+    match request {
+        JobRequest::Mint(_)
+        | JobRequest::Erc20Approve(_)
+        | JobRequest::Deploy(_)
+        | JobRequest::Call(_) => Ok(()),
+        JobRequest::Burn(_) => {
+            match standard(contract_address) {
+                TokenStandard::Erc721 => require(contract_address, Capabilities::check_erc721)?,
+                TokenStandard::Erc1155 => require(contract_address, Capabilities::check_erc1155)?,
+            }
+            require_burn(contract_address)
+        }
+        JobRequest::Royalty(_) => require(contract_address, Capabilities::check_royalties),
+        _ => require(contract_address, Capabilities::check_erc721),
+    }
+}
+
+// No interface covers burning. A collection registered with its ABI, such as
+// one deployed from artifacts built before burning was added, must have it.
+fn require_burn(contract_address: H160) -> Result<(), Rejection> {
+    let abi = match by_address(contract_address).and_then(|collection| collection.abi) {
+        Some(abi) => abi,
+        None => return Ok(()),
+    };
+    let contract = abi::load(&abi).map_err(Rejection::Invalid)?;
+    if contract.functions_by_name("burn").is_err() {
+        return Err(Rejection::Unsupported(
+            "the contract's ABI has no burn function".into(),
+        ));
+    }
+    Ok(())
+}
+
+// Operations the contract doesn't implement are refused and transfers are
+// checked against the chain first. Dry runs skip the journal, since they
+// change nothing.
+fn write<R: Serialize>(
+    key: &IdempotencyKey,
+    route: &str,
+    data: &R,
+    contract_address: Option<H160>,
+    request: JobRequest,
+    dry_run: Option<bool>,
+) -> Result<Submission, Rejection> {
+    request.validate().map_err(|_| Status::BadRequest)?;
+    if let Some(contract_address) = contract_address {
+        require_for(contract_address, &request)?;
+    }
+    let check = |request: &JobRequest| match contract_address {
+        Some(contract_address) => block_on(preflight::check(contract_address, request)),
+        None => Ok(()),
+    };
+    if dry_run.unwrap_or(false) {
+        check(&request).map_err(Rejection::Preflight)?;
+        return simulate(contract_address, &request).map_err(Rejection::from);
+    }
+    // Inside the journal, so a replayed request gets its stored response even
+    // though the transfer it made no longer passes the check
+    let mut refused = None;
+    let job = journal::run(key, route, data, || {
+        if let Err(e) = check(&request) {
+            refused = Some(e);
+            return Err(Status::BadRequest);
+        }
+        submit(contract_address, request)
+    });
+    match refused {
+        Some(e) => Err(Rejection::Preflight(e)),
+        None => Ok(Submission::Queued(job?)),
+    }
+}
+
+#[post("/mint?<dry_run>", data = "<data>")]
+fn nft_mint(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<MintResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Mint(data.clone());
+    write(&key, "mint", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/approve?<dry_run>", data = "<data>")]
+fn nft_approve(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<ApproveResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Approve(data.clone());
+    write(&key, "approve", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/safe_transfer_from?<dry_run>", data = "<data>")]
+fn nft_safe_transfer_from(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<TransferFormResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::SafeTransferFrom(data.clone());
+    write(&key, "safe_transfer_from", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/safe_transfer_from_data?<dry_run>", data = "<data>")]
+fn nft_safe_transfer_from_data(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<TransferFormDataResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::SafeTransferFromData(data.clone());
+    write(&key, "safe_transfer_from_data", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/set_approval_for_all?<dry_run>", data = "<data>")]
+fn nft_set_approval_for_all(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<SetApprovalForAllResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::SetApprovalForAll(data.clone());
+    write(&key, "set_approval_for_all", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/transfer_from?<dry_run>", data = "<data>")]
+fn nft_transfer_from(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<TransferFromResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::TransferFrom(data.clone());
+    write(&key, "transfer_from", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/royalty?<dry_run>", data = "<data>")]
+fn nft_royalty(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<RoyaltyResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Royalty(data.clone());
+    write(&key, "royalty", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[post("/burn?<dry_run>", data = "<data>")]
+fn nft_burn(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<BurnRequest>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Burn(data.clone());
+    write(&key, "burn", &*data, Some(default_contract()?), request, dry_run)
+}
+
+#[get("/currencies")]
+fn currencies() -> Json<Vec<Currency>> {
+    let mut currencies = vec![Currency::eth()];
+    for allowed in Config::get_currencies() {
+        if let Ok(currency) = resolve(Some(&allowed.symbol)) {
+            currencies.push(currency);
+        }
+    }
+    Json(currencies)
+}
+
+#[get("/erc20/balance?<currency>&<owner>")]
+fn erc20_balance(currency: String, owner: String) -> Result<Json<Erc20Balance>, Status> {
+    let currency = resolve(Some(&currency)).map_err(|_| Status::BadRequest)?;
+    let token_address = currency.address.ok_or(Status::BadRequest)?;
+    let owner = H160::from_str(&owner).map_err(|_| Status::BadRequest)?;
+    let operator: Address = Config::get_my_account().map_err(|_| Status::InternalServerError)?;
+    let balance =
+        block_on(erc20_balance_of(token_address, owner)).map_err(|_| Status::InternalServerError)?;
+    let allowance = block_on(erc20_allowance(token_address, owner, operator))
+        .map_err(|_| Status::InternalServerError)?;
+    Ok(Json(Erc20Balance {
+        formatted_balance: currency.format(balance),
+        formatted_allowance: currency.format(allowance),
+        currency,
+        balance,
+        allowance,
+    }))
+}
+
+// Lets a buyer approve the operator account to pull ERC-20 payments
+#[post("/erc20/approve?<dry_run>", data = "<data>")]
+fn erc20_approve_operator(
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<Erc20ApproveResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Erc20Approve(data.clone());
+    write(&key, "erc20/approve", &*data, Some(default_contract()?), request, dry_run)
+}
+
+fn market_status(e: MarketError) -> Status {
+    match e {
+        MarketError::NotFound => Status::NotFound,
+        MarketError::NotActive | MarketError::SoldOut => Status::Conflict,
+        MarketError::InvalidRequest(_) => Status::BadRequest,
+        MarketError::Forbidden(_) => Status::Forbidden,
+        MarketError::Chain(e) => {
+            eprintln!("Error: {}", e);
+            Status::InternalServerError
+        }
+        MarketError::Settlement(fill) => {
+            eprintln!("Error: {}", fill.receipt.error.unwrap_or_default());
+            Status::InternalServerError
+        }
+    }
+}
+
+// The hash of an order signed by `signer`, which identifies it once placed
+fn authorize(signer: H160, message: &str, signature: &str) -> Result<H256, Rejection> {
+    signatures::verify(signer, message, signature).map_err(Rejection::Forbidden)?;
+    Ok(signatures::message_hash(message))
+}
+
+// Runs a listing's chain checks inside the journal, like write(), so a
+// replayed request gets its stored response even if the tokens have moved
+fn place<R: Serialize>(
+    key: &IdempotencyKey,
+    route: &str,
+    data: &R,
+    contract_address: H160,
+    seller: H160,
+    token_ids: &[U256],
+    handle: impl FnOnce() -> Result<Json<Listing>, Status>,
+) -> Result<Json<Listing>, Rejection> {
+    let mut refused = None;
+    let listing = journal::run(key, route, data, || {
+        let check = preflight::check_listing(contract_address, seller, token_ids);
+        if let Err(e) = block_on(check) {
+            refused = Some(e);
+            return Err(Status::BadRequest);
+        }
+        handle()
+    });
+    match refused {
+        Some(e) => Err(Rejection::Preflight(e)),
+        None => Ok(listing?),
+    }
+}
+
+// `duration` seconds from now
+fn expiry(duration: Option<u64>) -> Result<Option<u64>, Status> {
+    match duration {
+        Some(duration) => now()
+            .checked_add(duration)
+            .map(Some)
+            .ok_or(Status::BadRequest),
+        None => Ok(None),
     }
 }
 
-#[post("/approve", data = "<data>")]
-fn nft_approve(data: Json<ApproveResponse>) -> Result<Json<String>, Status> {
-    let contract_address =
-        Config::get_contract_address().map_err(|e| rocket::http::Status::InternalServerError)?;
-    let my_address: Address =
-        Config::get_my_account().map_err(|e| rocket::http::Status::InternalServerError)?;
-    let address_to: H160 =
-        H160::from_str(&data.address_to).map_err(|e| rocket::http::Status::InternalServerError)?;
-    match block_on(approve(
+#[post("/listings/dutch_auction", data = "<data>")]
+fn listing_dutch_auction(
+    key: IdempotencyKey,
+    data: Json<DutchAuctionResponse>,
+) -> Result<Json<Listing>, Rejection> {
+    let contract_address = default_contract()?;
+    require(contract_address, Capabilities::check_erc721)?;
+    let message = signatures::dutch_auction(contract_address, &data);
+    let order_hash = authorize(data.seller, &message, &data.signature)?;
+    place(
+        &key,
+        "listings/dutch_auction",
+        &*data,
+        contract_address,
+        data.seller,
+        &data.token_ids,
+        || new_dutch_auction(contract_address, &data, order_hash),
+    )
+}
+
+fn new_dutch_auction(
+    contract_address: H160,
+    data: &DutchAuctionResponse,
+    order_hash: H256,
+) -> Result<Json<Listing>, Status> {
+    let currency = resolve(data.currency.as_deref()).map_err(|_| Status::BadRequest)?;
+    let start_price = currency
+        .parse(&data.start_price)
+        .map_err(|_| Status::BadRequest)?;
+    let floor_price = currency
+        .parse(&data.floor_price)
+        .map_err(|_| Status::BadRequest)?;
+    let start_time = data.start_time.unwrap_or_else(now);
+    let end_time = start_time
+        .checked_add(data.duration)
+        .ok_or(Status::BadRequest)?;
+    let auction = DutchAuction {
+        start_price,
+        floor_price,
+        start_time,
+        end_time,
+        curve: data.curve,
+        rebate: data.rebate,
+    };
+    let listing = create_dutch_auction(
         contract_address,
-        my_address,
-        &data.private_key,
-        address_to,
+        data.seller,
+        data.token_ids.clone(),
+        currency,
+        auction,
+        order_hash,
+    )
+    .map_err(market_status)?;
+    trigger_match();
+    Ok(Json(listing))
+}
+
+#[post("/listings/fixed_price", data = "<data>")]
+fn listing_fixed_price(
+    key: IdempotencyKey,
+    data: Json<FixedPriceResponse>,
+) -> Result<Json<Listing>, Rejection> {
+    let contract_address = default_contract()?;
+    require(contract_address, Capabilities::check_erc721)?;
+    let message = signatures::fixed_price(contract_address, &data);
+    let order_hash = authorize(data.seller, &message, &data.signature)?;
+    place(
+        &key,
+        "listings/fixed_price",
+        &*data,
+        contract_address,
+        data.seller,
+        &[data.token_id],
+        || new_fixed_price(contract_address, &data, order_hash),
+    )
+}
+
+fn new_fixed_price(
+    contract_address: H160,
+    data: &FixedPriceResponse,
+    order_hash: H256,
+) -> Result<Json<Listing>, Status> {
+    let currency = resolve(data.currency.as_deref()).map_err(|_| Status::BadRequest)?;
+    let price = currency.parse(&data.price).map_err(|_| Status::BadRequest)?;
+    let fixed = FixedPrice {
+        price,
+        expires_at: expiry(data.duration)?,
+    };
+    let listing = create_fixed_price(
+        contract_address,
+        data.seller,
         data.token_id,
-    )) {
-        Ok(receipt) => Ok(Json(receipt.block_hash.unwrap_or_default().to_string())),
-        Err(_) => Err(Status::InternalServerError),
-    }
+        currency,
+        fixed,
+        order_hash,
+    )
+    .map_err(market_status)?;
+    trigger_match();
+    Ok(Json(listing))
 }
 
-#[post("/safe_transfer_from", data = "<data>")]
-fn nft_safe_transfer_from(data: Json<TransferFormResponse>) -> Result<Json<String>, Status> {
-    let contract_address =
-        Config::get_contract_address().map_err(|e| rocket::http::Status::InternalServerError)?;
-    let my_address: Address =
-        Config::get_my_account().map_err(|e| rocket::http::Status::InternalServerError)?;
-    match block_on(safe_transfer_from(
+#[get("/listings")]
+fn listings() -> Json<Vec<Listing>> {
+    Json(list_listings())
+}
+
+#[get("/listings/<id>")]
+fn listing(id: u64) -> Result<Json<Listing>, Status> {
+    get_listing(id).map(Json).ok_or(Status::NotFound)
+}
+
+#[post("/listings/<id>/purchase", data = "<data>")]
+fn listing_purchase(
+    key: IdempotencyKey,
+    id: u64,
+    data: Json<PurchaseResponse>,
+) -> Result<Json<Fill>, Status> {
+    journal::run(&key, "listings/purchase", &(id, &*data), || {
+        block_on(purchase(id, data.buyer, &data.private_key))
+            .map(Json)
+            .map_err(market_status)
+    })
+}
+
+#[post("/listings/<id>/cancel", data = "<data>")]
+fn listing_cancel(
+    key: IdempotencyKey,
+    id: u64,
+    data: Json<CancelRequest>,
+) -> Result<Json<Listing>, Status> {
+    journal::run(&key, "listings/cancel", &(id, &*data), || {
+        cancel_listing(id, &data.signature)
+            .map(Json)
+            .map_err(market_status)
+    })
+}
+
+#[post("/offers", data = "<data>")]
+fn offer_create(key: IdempotencyKey, data: Json<OfferResponse>) -> Result<Json<Offer>, Rejection> {
+    let contract_address = default_contract()?;
+    require(contract_address, Capabilities::check_erc721)?;
+    let message = signatures::offer(contract_address, &data);
+    let order_hash = authorize(data.buyer, &message, &data.signature)?;
+    let offer = journal::run(&key, "offers", &*data, || {
+        new_offer(contract_address, &data, order_hash)
+    })?;
+    Ok(offer)
+}
+
+fn new_offer(
+    contract_address: H160,
+    data: &OfferResponse,
+    order_hash: H256,
+) -> Result<Json<Offer>, Status> {
+    let currency = resolve(Some(&data.currency)).map_err(|_| Status::BadRequest)?;
+    let price = currency.parse(&data.price).map_err(|_| Status::BadRequest)?;
+    let offer = create_offer(
         contract_address,
-        my_address,
-        &data.private_key,
-        data.from,
-        data.to,
         data.token_id,
-    )) {
-        Ok(receipt) => Ok(Json(receipt.block_hash.unwrap_or_default().to_string())),
-        Err(_) => Err(Status::InternalServerError),
+        data.buyer,
+        currency,
+        price,
+        expiry(data.duration)?,
+        order_hash,
+    )
+    .map_err(market_status)?;
+    trigger_match();
+    Ok(Json(offer))
+}
+
+#[get("/offers")]
+fn offers() -> Json<Vec<Offer>> {
+    Json(list_offers())
+}
+
+#[get("/offers/<id>")]
+fn offer(id: u64) -> Result<Json<Offer>, Status> {
+    get_offer(id).map(Json).ok_or(Status::NotFound)
+}
+
+#[post("/offers/<id>/cancel", data = "<data>")]
+fn offer_cancel(
+    key: IdempotencyKey,
+    id: u64,
+    data: Json<CancelRequest>,
+) -> Result<Json<Offer>, Status> {
+    journal::run(&key, "offers/cancel", &(id, &*data), || {
+        cancel_offer(id, &data.signature)
+            .map(Json)
+            .map_err(market_status)
+    })
+}
+
+fn parse_token_id(token_id: &str) -> Result<U256, Status> {
+    match token_id.strip_prefix("0x") {
+        Some(hex) => U256::from_str(hex).map_err(|_| Status::BadRequest),
+        None => U256::from_dec_str(token_id).map_err(|_| Status::BadRequest),
     }
 }
 
-#[post("/safe_transfer_from_data", data = "<data>")]
-fn nft_safe_transfer_from_data(
-    data: Json<TransferFormDataResponse>,
-) -> Result<Json<String>, Status> {
-    let contract_address =
-        Config::get_contract_address().map_err(|_| Status::InternalServerError)?;
-    let my_address: Address = Config::get_my_account().map_err(|_| Status::InternalServerError)?;
-    let mut original_data: Option<Vec<u8>> = None;
-
-    if let Ok(decoded_bytes) = decode(data.data.clone()) {
-        if let Ok(decoded_data) = String::from_utf8(decoded_bytes) {
-            original_data = Some(decoded_data.into_bytes());
-            println!("{:?}", original_data);
-        } else {
-            println!("解码为字符串失败");
-        }
-    } else {
-        println!("解码失败");
-    }
-
-    if let Some(_data) = original_data {
-        match block_on(safe_transfer_from_with_data(
-            contract_address,
-            my_address,
-            &data.private_key,
-            data.from,
-            data.to,
-            data.token_id,
-            _data,
-        )) {
-            Ok(receipt) => Ok(Json(receipt.block_hash.unwrap_or_default().to_string())),
-            Err(_) => Err(Status::InternalServerError),
-        }
-    } else {
-        Err(Status::InternalServerError)
-    }
-}
-
-#[post("/set_approval_for_all", data = "<data>")]
-fn nft_set_approval_for_all(data: Json<SetApprovalForAllResponse>) -> Result<Json<String>, Status> {
-    let contract_address =
-        Config::get_contract_address().map_err(|_| Status::InternalServerError)?;
-    let my_address: Address = Config::get_my_account().map_err(|_| Status::InternalServerError)?;
-    match block_on(set_approval_for_all(
+fn parse_currency(currency: Option<String>) -> Result<Option<Currency>, Status> {
+    match currency {
+        Some(currency) => resolve(Some(&currency))
+            .map(Some)
+            .map_err(|_| Status::BadRequest),
+        None => Ok(None),
+    }
+}
+
+#[get("/collections/<id>/floor?<currency>")]
+fn collection_floor(id: String, currency: Option<String>) -> Result<Json<Vec<Ask>>, Status> {
+    let contract_address = collection(&id)?.contract_address;
+    let currency = parse_currency(currency)?;
+    Ok(Json(floor(contract_address, currency.as_ref())))
+}
+
+#[get("/tokens/<token_id>/best-offer?<currency>")]
+fn token_best_offer(token_id: String, currency: Option<String>) -> Result<Json<Vec<Bid>>, Status> {
+    best_offers(default_contract()?, token_id, currency)
+}
+
+fn best_offers(
+    contract_address: H160,
+    token_id: String,
+    currency: Option<String>,
+) -> Result<Json<Vec<Bid>>, Status> {
+    let token_id = parse_token_id(&token_id)?;
+    let currency = parse_currency(currency)?;
+    Ok(Json(best_offer(contract_address, token_id, currency.as_ref())))
+}
+
+#[get("/orderbook?<contract>&<token_id>&<currency>")]
+fn orderbook(
+    contract: Option<String>,
+    token_id: Option<String>,
+    currency: Option<String>,
+) -> Result<Json<OrderBook>, Status> {
+    let contract_address = match parse_address(contract)? {
+        Some(contract_address) => contract_address,
+        None => default_contract()?,
+    };
+    book(contract_address, token_id, currency)
+}
+
+fn book(
+    contract_address: H160,
+    token_id: Option<String>,
+    currency: Option<String>,
+) -> Result<Json<OrderBook>, Status> {
+    let token_id = match token_id {
+        Some(token_id) => Some(parse_token_id(&token_id)?),
+        None => None,
+    };
+    let currency = parse_currency(currency)?;
+    Ok(Json(order_book(contract_address, token_id, currency.as_ref())))
+}
+
+#[post("/listings/<id>/finalize")]
+fn listing_finalize(key: IdempotencyKey, id: u64) -> Result<Json<Listing>, Status> {
+    journal::run(&key, "listings/finalize", &id, || {
+        finalize(id).map(Json).map_err(market_status)
+    })
+}
+
+fn parse_address(address: Option<String>) -> Result<Option<H160>, Status> {
+    match address {
+        Some(address) => H160::from_str(&address)
+            .map(Some)
+            .map_err(|_| Status::BadRequest),
+        None => Ok(None),
+    }
+}
+
+#[get("/payouts?<contract>&<seller>&<from>&<to>")]
+fn payout_report(
+    _admin: Admin,
+    contract: Option<String>,
+    seller: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Json<Vec<Payout>>, Status> {
+    let contract = parse_address(contract)?;
+    let seller = parse_address(seller)?;
+    payouts(contract, seller, from, to).map(Json).map_err(|e| {
+        eprintln!("Error: {}", e);
+        Status::InternalServerError
+    })
+}
+
+#[get("/payouts/csv?<contract>&<seller>&<from>&<to>")]
+fn payout_csv(
+    _admin: Admin,
+    contract: Option<String>,
+    seller: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Content<String>, Status> {
+    let contract = parse_address(contract)?;
+    let seller = parse_address(seller)?;
+    let report = payouts(contract, seller, from, to).map_err(|e| {
+        eprintln!("Error: {}", e);
+        Status::InternalServerError
+    })?;
+    Ok(Content(ContentType::CSV, to_csv(&report)))
+}
+
+#[post("/assets", data = "<data>")]
+fn asset_upload(_admin: Admin, data: Json<AssetRequest>) -> Result<Json<Asset>, Status> {
+    let upload = assets::prepare(&data).map_err(|_| Status::BadRequest)?;
+    match assets::upload(upload) {
+        Ok(asset) => Ok(Json(asset)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/assets/<name>")]
+fn asset_file(name: String) -> Result<Content<Vec<u8>>, Status> {
+    let content_type = media_type(&name)
+        .and_then(ContentType::parse_flexible)
+        .ok_or(Status::NotFound)?;
+    match assets::get(&name) {
+        Ok(Some(content)) => Ok(Content(content_type, content)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/owners/<address>/tokens?<contract>&<offset>&<limit>")]
+fn owner_tokens(
+    address: String,
+    contract: Option<String>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+) -> Result<Json<OwnedTokens>, Status> {
+    let contract_address = match parse_address(contract)? {
+        Some(contract_address) => contract_address,
+        None => default_contract()?,
+    };
+    tokens_of(contract_address, address, offset, limit)
+}
+
+fn tokens_of(
+    contract_address: H160,
+    address: String,
+    offset: Option<u64>,
+    limit: Option<u64>,
+) -> Result<Json<OwnedTokens>, Status> {
+    let owner = H160::from_str(&address).map_err(|_| Status::BadRequest)?;
+    let tokens = block_on(owned_tokens(
         contract_address,
-        my_address,
-        &data.private_key,
-        data.operator,
-        data.approved,
-    )) {
-        Ok(receipt) => Ok(Json(receipt.block_hash.unwrap_or_default().to_string())),
-        Err(_) => Err(Status::InternalServerError),
+        owner,
+        offset.unwrap_or(0),
+        limit.unwrap_or(20),
+    ));
+    match tokens {
+        Ok(tokens) => Ok(Json(tokens)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/tokens/<address>/<token_id>/history")]
+fn token_provenance(address: String, token_id: String) -> Result<Json<Vec<HistoryEntry>>, Status> {
+    let contract_address = H160::from_str(&address).map_err(|_| Status::BadRequest)?;
+    history(contract_address, token_id)
+}
+
+fn history(contract_address: H160, token_id: String) -> Result<Json<Vec<HistoryEntry>>, Status> {
+    let token_id = parse_token_id(&token_id)?;
+    Ok(Json(token_history(contract_address, token_id)))
+}
+
+#[get("/tokens/<address>/<token_id>/metadata?<refresh>")]
+fn token_metadata_document(
+    address: String,
+    token_id: String,
+    refresh: Option<bool>,
+) -> Result<Json<TokenMetadata>, Rejection> {
+    let contract_address = H160::from_str(&address).map_err(|_| Status::BadRequest)?;
+    metadata_document(contract_address, token_id, refresh)
+}
+
+fn metadata_document(
+    contract_address: H160,
+    token_id: String,
+    refresh: Option<bool>,
+) -> Result<Json<TokenMetadata>, Rejection> {
+    let token_id = parse_token_id(&token_id)?;
+    require(contract_address, Capabilities::check_metadata)?;
+    match block_on(token_metadata(contract_address, token_id, refresh.unwrap_or(false))) {
+        Ok(metadata) => Ok(Json(metadata)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::BadGateway.into())
+        }
+    }
+}
+
+fn interfaces_of(
+    contract_address: H160,
+    refresh: Option<bool>,
+) -> Result<Json<Capabilities>, Status> {
+    match block_on(interfaces::capabilities(contract_address, refresh.unwrap_or(false))) {
+        Ok(capabilities) => Ok(Json(capabilities)),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::BadGateway)
+        }
+    }
+}
+
+// What any contract implements, registered or not
+#[get("/contracts/<address>/interfaces?<refresh>")]
+fn contract_interfaces(
+    address: String,
+    refresh: Option<bool>,
+) -> Result<Json<Capabilities>, Status> {
+    let contract_address = H160::from_str(&address).map_err(|_| Status::BadRequest)?;
+    interfaces_of(contract_address, refresh)
+}
+
+#[get("/media/<content_hash>/<size>")]
+fn media_thumbnail(content_hash: String, size: u32) -> Result<Content<Vec<u8>>, Status> {
+    match thumbnail(&content_hash, size) {
+        Ok(Some((content_type, content))) => {
+            let content_type = ContentType::parse_flexible(content_type).ok_or(Status::NotFound)?;
+            Ok(Content(content_type, content))
+        }
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/indexer/status")]
+fn indexer_status() -> Json<IndexerStatus> {
+    Json(status())
+}
+
+#[get("/indexer/tokens/<address>/<token_id>")]
+fn indexer_token(address: String, token_id: String) -> Result<Json<TokenState>, Status> {
+    let contract_address = H160::from_str(&address).map_err(|_| Status::BadRequest)?;
+    let token_id = parse_token_id(&token_id)?;
+    Ok(Json(token_state(contract_address, token_id)))
+}
+
+#[get("/transactions/<tx_hash>")]
+fn transaction_status(tx_hash: String) -> Result<Json<TrackedTx>, Status> {
+    let tx_hash = H256::from_str(&tx_hash).map_err(|_| Status::BadRequest)?;
+    get_transaction(tx_hash).map(Json).ok_or(Status::NotFound)
+}
+
+// Server-sent events for a collection, token, address or transaction
+#[get("/stream?<contract>&<token_id>&<address>&<tx_hash>")]
+fn event_stream(
+    contract: Option<String>,
+    token_id: Option<String>,
+    address: Option<String>,
+    tx_hash: Option<String>,
+) -> Result<EventStream, Status> {
+    let filter = Filter {
+        contract_address: parse_address(contract)?,
+        token_id: match token_id {
+            Some(token_id) => Some(parse_token_id(&token_id)?),
+            None => None,
+        },
+        address: parse_address(address)?,
+        transaction_hash: match tx_hash {
+            Some(tx_hash) => Some(H256::from_str(&tx_hash).map_err(|_| Status::BadRequest)?),
+            None => None,
+        },
+    };
+    subscribe(filter).ok_or(Status::ServiceUnavailable)
+}
+
+#[get("/jobs/<id>")]
+fn job(id: u64) -> Result<Json<Job>, Status> {
+    get_job(id).map(Json).ok_or(Status::NotFound)
+}
+
+#[get("/jobs?<status>&<kind>&<offset>&<limit>")]
+fn jobs(
+    status: Option<String>,
+    kind: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Json<Vec<Job>>, Status> {
+    let status: Option<JobStatus> = match status {
+        Some(status) => Some(
+            serde_json::from_value(serde_json::Value::String(status))
+                .map_err(|_| Status::BadRequest)?,
+        ),
+        None => None,
+    };
+    Ok(Json(list_jobs(
+        status,
+        kind.as_deref(),
+        offset.unwrap_or(0),
+        limit.unwrap_or(50).min(500),
+    )))
+}
+
+#[post("/webhooks", data = "<data>")]
+fn webhook_create(_admin: Admin, data: Json<WebhookRequest>) -> Result<Json<Webhook>, Status> {
+    match create_webhook(&data.url, data.events.clone(), &data.secret) {
+        Ok(webhook) => Ok(Json(webhook.redacted())),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::BadRequest)
+        }
+    }
+}
+
+#[get("/webhooks")]
+fn webhooks(_admin: Admin) -> Json<Vec<Webhook>> {
+    Json(list_webhooks())
+}
+
+#[get("/webhooks/<id>")]
+fn webhook(_admin: Admin, id: u64) -> Result<Json<Webhook>, Status> {
+    get_webhook(id).map(Json).ok_or(Status::NotFound)
+}
+
+#[delete("/webhooks/<id>")]
+fn webhook_delete(_admin: Admin, id: u64) -> Status {
+    match delete_webhook(id) {
+        Ok(true) => Status::NoContent,
+        Ok(false) => Status::NotFound,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Status::InternalServerError
+        }
+    }
+}
+
+// Delivery log of a webhook, newest first, including dead letters
+#[get("/webhooks/<id>/deliveries")]
+fn webhook_deliveries(_admin: Admin, id: u64) -> Result<Json<Vec<Delivery>>, Status> {
+    deliveries(id).map(Json).map_err(|e| {
+        eprintln!("Error: {}", e);
+        Status::InternalServerError
+    })
+}
+
+#[post("/webhooks/deliveries/<id>/retry")]
+fn webhook_redeliver(_admin: Admin, id: u64) -> Result<Json<Delivery>, Status> {
+    match redeliver(id) {
+        Ok(Some(delivery)) => Ok(Json(delivery)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/idempotency/<key>")]
+fn idempotency_entry(key: String) -> Result<Json<JournalRecord>, Status> {
+    match get_entry(&key) {
+        Ok(Some(record)) => Ok(Json(record)),
+        Ok(None) => Err(Status::NotFound),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+fn collection_status(e: CollectionError) -> Status {
+    match e {
+        CollectionError::NotFound => Status::NotFound,
+        CollectionError::Conflict(_) => Status::Conflict,
+        CollectionError::InvalidRequest(_) => Status::BadRequest,
+        CollectionError::Internal(e) => {
+            eprintln!("Error: {}", e);
+            Status::InternalServerError
+        }
+    }
+}
+
+// `<id>` is the collection id or its contract address
+fn collection(id: &str) -> Result<Collection, Status> {
+    lookup(id).ok_or(Status::NotFound)
+}
+
+// Writes and orders go through the ERC-721 contract and market; ERC-1155
+// collections only support burning for now
+fn erc721_collection(id: &str) -> Result<Collection, Status> {
+    let collection = collection(id)?;
+    if collection.standard != TokenStandard::Erc721 {
+        return Err(Status::UnprocessableEntity);
+    }
+    Ok(collection)
+}
+
+#[post("/collections", data = "<data>")]
+fn collection_create(
+    _admin: Admin,
+    data: Json<CollectionRequest>,
+) -> Result<Json<Collection>, Status> {
+    block_on(create_collection(data.into_inner()))
+        .map(Json)
+        .map_err(collection_status)
+}
+
+#[get("/collections")]
+fn collections() -> Json<Vec<Collection>> {
+    Json(list_collections())
+}
+
+#[get("/collections/<id>")]
+fn collection_info(id: String) -> Result<Json<Collection>, Status> {
+    collection(&id).map(Json)
+}
+
+#[put("/collections/<id>", data = "<data>")]
+fn collection_update(
+    _admin: Admin,
+    id: u64,
+    data: Json<CollectionUpdate>,
+) -> Result<Json<Collection>, Status> {
+    update_collection(id, data.into_inner())
+        .map(Json)
+        .map_err(collection_status)
+}
+
+#[delete("/collections/<id>")]
+fn collection_delete(_admin: Admin, id: u64) -> Status {
+    match delete_collection(id) {
+        Ok(true) => Status::NoContent,
+        Ok(false) => Status::NotFound,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            Status::InternalServerError
+        }
     }
 }
 
-#[post("/transfer_from", data = "<data>")]
-fn nft_transfer_from(data: Json<TransferFromResponse>) -> Result<Json<String>, Status> {
-    let contract_address =
-        Config::get_contract_address().map_err(|_| Status::InternalServerError)?;
-    let my_address: Address = Config::get_my_account().map_err(|_| Status::InternalServerError)?;
-    match block_on(transfer_from(
+// Deploys a contract from the bundled artifacts. The job registers it as a
+// collection once its code is on chain.
+#[post("/collections/deploy?<dry_run>", data = "<data>")]
+fn collection_deploy(
+    _admin: Admin,
+    key: IdempotencyKey,
+    dry_run: Option<bool>,
+    data: Json<DeployRequest>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Deploy(data.clone());
+    write(&key, "collections/deploy", &*data, None, request, dry_run)
+}
+
+fn collection_submit<R: Serialize>(
+    key: &IdempotencyKey,
+    id: &str,
+    route: &str,
+    data: &R,
+    request: JobRequest,
+    dry_run: Option<bool>,
+) -> Result<Submission, Rejection> {
+    let collection = match &request {
+        JobRequest::Burn(_) => collection(id)?,
+        _ => erc721_collection(id)?,
+    };
+    let route = format!("collections/{}", route);
+    let data = &(collection.id, data);
+    write(key, &route, data, Some(collection.contract_address), request, dry_run)
+}
+
+// Tokens minted without a royalty get the collection's
+#[post("/collections/<id>/mint?<dry_run>", data = "<data>")]
+fn collection_mint(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<MintResponse>,
+) -> Result<Submission, Rejection> {
+    let collection = erc721_collection(&id)?;
+    let mut request = data.clone();
+    if request.royalty_receiver.is_none() && request.royalty_fee.is_none() {
+        request.royalty_receiver = collection.royalty_receiver;
+        request.royalty_fee = collection.royalty_fee;
+    }
+    let request = JobRequest::Mint(request);
+    collection_submit(&key, &id, "mint", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/approve?<dry_run>", data = "<data>")]
+fn collection_approve(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<ApproveResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Approve(data.clone());
+    collection_submit(&key, &id, "approve", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/safe_transfer_from?<dry_run>", data = "<data>")]
+fn collection_safe_transfer_from(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<TransferFormResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::SafeTransferFrom(data.clone());
+    collection_submit(&key, &id, "safe_transfer_from", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/safe_transfer_from_data?<dry_run>", data = "<data>")]
+fn collection_safe_transfer_from_data(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<TransferFormDataResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::SafeTransferFromData(data.clone());
+    collection_submit(&key, &id, "safe_transfer_from_data", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/set_approval_for_all?<dry_run>", data = "<data>")]
+fn collection_set_approval_for_all(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<SetApprovalForAllResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::SetApprovalForAll(data.clone());
+    collection_submit(&key, &id, "set_approval_for_all", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/transfer_from?<dry_run>", data = "<data>")]
+fn collection_transfer_from(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<TransferFromResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::TransferFrom(data.clone());
+    collection_submit(&key, &id, "transfer_from", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/royalty?<dry_run>", data = "<data>")]
+fn collection_royalty(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<RoyaltyResponse>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Royalty(data.clone());
+    collection_submit(&key, &id, "royalty", &*data, request, dry_run)
+}
+
+// Works for ERC-1155 collections too, burning `amount` of `owner`'s tokens
+#[post("/collections/<id>/burn?<dry_run>", data = "<data>")]
+fn collection_burn(
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<BurnRequest>,
+) -> Result<Submission, Rejection> {
+    let request = JobRequest::Burn(data.clone());
+    collection_submit(&key, &id, "burn", &*data, request, dry_run)
+}
+
+#[post("/collections/<id>/listings/dutch_auction", data = "<data>")]
+fn collection_dutch_auction(
+    key: IdempotencyKey,
+    id: String,
+    data: Json<DutchAuctionResponse>,
+) -> Result<Json<Listing>, Rejection> {
+    let collection = erc721_collection(&id)?;
+    let contract_address = collection.contract_address;
+    require(contract_address, Capabilities::check_erc721)?;
+    let message = signatures::dutch_auction(contract_address, &data);
+    let order_hash = authorize(data.seller, &message, &data.signature)?;
+    place(
+        &key,
+        "collections/listings/dutch_auction",
+        &(collection.id, &*data),
         contract_address,
-        my_address,
-        &data.private_key,
-        data.from,
-        data.to,
-        data.token_id,
-    )) {
-        Ok(receipt) => Ok(Json(receipt.block_hash.unwrap_or_default().to_string())),
-        Err(_) => Err(Status::InternalServerError),
+        data.seller,
+        &data.token_ids,
+        || new_dutch_auction(contract_address, &data, order_hash),
+    )
+}
+
+#[post("/collections/<id>/listings/fixed_price", data = "<data>")]
+fn collection_fixed_price(
+    key: IdempotencyKey,
+    id: String,
+    data: Json<FixedPriceResponse>,
+) -> Result<Json<Listing>, Rejection> {
+    let collection = erc721_collection(&id)?;
+    let contract_address = collection.contract_address;
+    require(contract_address, Capabilities::check_erc721)?;
+    let message = signatures::fixed_price(contract_address, &data);
+    let order_hash = authorize(data.seller, &message, &data.signature)?;
+    place(
+        &key,
+        "collections/listings/fixed_price",
+        &(collection.id, &*data),
+        contract_address,
+        data.seller,
+        &[data.token_id],
+        || new_fixed_price(contract_address, &data, order_hash),
+    )
+}
+
+#[post("/collections/<id>/offers", data = "<data>")]
+fn collection_offer(
+    key: IdempotencyKey,
+    id: String,
+    data: Json<OfferResponse>,
+) -> Result<Json<Offer>, Rejection> {
+    let collection = erc721_collection(&id)?;
+    require(collection.contract_address, Capabilities::check_erc721)?;
+    let message = signatures::offer(collection.contract_address, &data);
+    let order_hash = authorize(data.buyer, &message, &data.signature)?;
+    let request = (collection.id, &*data);
+    let offer = journal::run(&key, "collections/offers", &request, || {
+        new_offer(collection.contract_address, &data, order_hash)
+    })?;
+    Ok(offer)
+}
+
+#[get("/collections/<id>/orderbook?<token_id>&<currency>")]
+fn collection_orderbook(
+    id: String,
+    token_id: Option<String>,
+    currency: Option<String>,
+) -> Result<Json<OrderBook>, Status> {
+    book(collection(&id)?.contract_address, token_id, currency)
+}
+
+#[get("/collections/<id>/tokens/<token_id>/best-offer?<currency>")]
+fn collection_best_offer(
+    id: String,
+    token_id: String,
+    currency: Option<String>,
+) -> Result<Json<Vec<Bid>>, Status> {
+    best_offers(collection(&id)?.contract_address, token_id, currency)
+}
+
+#[get("/collections/<id>/tokens/<token_id>/history")]
+fn collection_token_history(
+    id: String,
+    token_id: String,
+) -> Result<Json<Vec<HistoryEntry>>, Status> {
+    history(collection(&id)?.contract_address, token_id)
+}
+
+#[get("/collections/<id>/tokens/<token_id>/metadata?<refresh>")]
+fn collection_token_metadata(
+    id: String,
+    token_id: String,
+    refresh: Option<bool>,
+) -> Result<Json<TokenMetadata>, Rejection> {
+    metadata_document(collection(&id)?.contract_address, token_id, refresh)
+}
+
+#[get("/collections/<id>/interfaces?<refresh>")]
+fn collection_interfaces(id: String, refresh: Option<bool>) -> Result<Json<Capabilities>, Status> {
+    interfaces_of(collection(&id)?.contract_address, refresh)
+}
+
+#[put("/collections/<id>/abi", data = "<data>")]
+fn collection_abi(
+    _admin: Admin,
+    id: u64,
+    data: Json<serde_json::Value>,
+) -> Result<Json<Collection>, Rejection> {
+    match set_abi(id, data.into_inner()) {
+        Ok(collection) => Ok(Json(collection)),
+        Err(CollectionError::InvalidRequest(e)) => Err(Rejection::Invalid(e)),
+        Err(e) => Err(collection_status(e).into()),
     }
 }
 
+// Calldata for the named function of the collection's ABI, and whether the
+// function only reads
+fn encode_call(
+    collection: &Collection,
+    data: &ContractCallRequest,
+) -> Result<(Function, Vec<u8>, bool), Rejection> {
+    let abi = abi::collection_abi(collection).map_err(|e| {
+        eprintln!("Error: {}", e);
+        Status::InternalServerError
+    })?;
+    let contract = abi::load(&abi).map_err(Rejection::Invalid)?;
+    let function = abi::find_function(&contract, &data.function, data.args.len())
+        .map_err(Rejection::Invalid)?;
+    let calldata = abi::encode(function, &data.args).map_err(Rejection::Invalid)?;
+    let read_only = abi::read_only(&abi, function);
+    Ok((function.clone(), calldata, read_only))
+}
+
+fn call_value(data: &ContractCallRequest) -> Result<Option<U256>, Rejection> {
+    match &data.value {
+        Some(value) => Ok(Some(Currency::eth().parse(value).map_err(Rejection::Invalid)?)),
+        None => Ok(None),
+    }
+}
+
+// Simulates a call with eth_call; a revert is reported, not an error
+#[post("/collections/<id>/call", data = "<data>")]
+fn collection_call(
+    _admin: Admin,
+    id: String,
+    data: Json<ContractCallRequest>,
+) -> Result<Json<ContractCallResult>, Rejection> {
+    let collection = collection(&id)?;
+    let (function, calldata, _) = encode_call(&collection, &data)?;
+    let value = call_value(&data)?;
+    let from = data.from.or_else(|| Config::get_my_account().ok());
+    let outcome = block_on(eth_call(
+        collection.contract_address,
+        from,
+        calldata.clone(),
+        value,
+    ))
+    .map_err(|e| {
+        eprintln!("Error: {}", e);
+        Status::BadGateway
+    })?;
+    let mut result = ContractCallResult {
+        signature: abi::signature(&function),
+        data: format!("0x{}", hex::encode(&calldata)),
+        outputs: Vec::new(),
+        reverted: false,
+        error: None,
+    };
+    match outcome {
+        CallOutcome::Returned(output) => match function.decode_output(&output) {
+            Ok(tokens) => result.outputs = tokens.iter().map(abi::to_json).collect(),
+            Err(e) => result.error = Some(format!("undecodable output: {}", e)),
+        },
+        CallOutcome::Reverted(reason) => {
+            result.reverted = true;
+            result.error = Some(reason);
+        }
+    }
+    Ok(Json(result))
+}
+
+// Sends a call as a queued transaction. View and pure functions can only be
+// simulated.
+#[post("/collections/<id>/send?<dry_run>", data = "<data>")]
+fn collection_send(
+    _admin: Admin,
+    key: IdempotencyKey,
+    id: String,
+    dry_run: Option<bool>,
+    data: Json<ContractCallRequest>,
+) -> Result<Submission, Rejection> {
+    let collection = collection(&id)?;
+    let (function, calldata, read_only) = encode_call(&collection, &data)?;
+    let signature = abi::signature(&function);
+    if read_only {
+        return Err(Rejection::Invalid(format!(
+            "{} doesn't change state, use /collections/{}/call",
+            signature, id
+        )));
+    }
+    let request = ContractCallJob {
+        private_key: data
+            .private_key
+            .clone()
+            .unwrap_or_else(Config::get_my_private_key),
+        signature,
+        data: format!("0x{}", hex::encode(&calldata)),
+        value: call_value(&data)?,
+    };
+    let contract_address = Some(collection.contract_address);
+    let request = JobRequest::Call(request);
+    write(&key, "collections/send", &(collection.id, &*data), contract_address, request, dry_run)
+}
+
+#[get("/collections/<id>/tokens/<token_id>")]
+fn collection_token(id: String, token_id: String) -> Result<Json<TokenState>, Status> {
+    let contract_address = collection(&id)?.contract_address;
+    let token_id = parse_token_id(&token_id)?;
+    Ok(Json(token_state(contract_address, token_id)))
+}
+
+#[get("/collections/<id>/owners/<address>/tokens?<offset>&<limit>")]
+fn collection_owner_tokens(
+    id: String,
+    address: String,
+    offset: Option<u64>,
+    limit: Option<u64>,
+) -> Result<Json<OwnedTokens>, Status> {
+    tokens_of(collection(&id)?.contract_address, address, offset, limit)
+}
+
 pub fn run_server() {
-    rocket::ignite()
+    let rocket = rocket::ignite();
+    set_workers(rocket.config().workers);
+    rocket
         .mount(
             "/",
             routes![
@@ -169,6 +1447,74 @@ pub fn run_server() {
                 nft_safe_transfer_from_data,
                 nft_set_approval_for_all,
                 nft_transfer_from,
+                nft_royalty,
+                nft_burn,
+                currencies,
+                erc20_balance,
+                erc20_approve_operator,
+                listing_dutch_auction,
+                listing_fixed_price,
+                listings,
+                listing,
+                listing_purchase,
+                listing_cancel,
+                listing_finalize,
+                offer_create,
+                offers,
+                offer,
+                offer_cancel,
+                collection_floor,
+                token_best_offer,
+                orderbook,
+                payout_report,
+                payout_csv,
+                asset_upload,
+                asset_file,
+                owner_tokens,
+                token_provenance,
+                token_metadata_document,
+                media_thumbnail,
+                indexer_status,
+                indexer_token,
+                transaction_status,
+                event_stream,
+                idempotency_entry,
+                job,
+                jobs,
+                webhook_create,
+                webhooks,
+                webhook,
+                webhook_delete,
+                webhook_deliveries,
+                webhook_redeliver,
+                collection_create,
+                collections,
+                collection_info,
+                collection_update,
+                collection_delete,
+                collection_deploy,
+                collection_mint,
+                collection_approve,
+                collection_safe_transfer_from,
+                collection_safe_transfer_from_data,
+                collection_set_approval_for_all,
+                collection_transfer_from,
+                collection_royalty,
+                collection_burn,
+                collection_dutch_auction,
+                collection_fixed_price,
+                collection_offer,
+                collection_orderbook,
+                collection_best_offer,
+                collection_token_history,
+                collection_token_metadata,
+                collection_token,
+                collection_owner_tokens,
+                collection_interfaces,
+                contract_interfaces,
+                collection_abi,
+                collection_call,
+                collection_send,
             ],
         )
         .launch();
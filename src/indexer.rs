@@ -0,0 +1,1181 @@
+use crate::collections;
+use crate::config::Config;
+use crate::eth::{block_header, block_number, get_logs};
+use crate::market;
+use crate::receipts;
+use crate::storage;
+use crate::stream::{self, Subject};
+use crate::types::BlockHeader;
+use crate::webhooks;
+use futures::executor::block_on;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use web3::ethabi::{decode as decode_data, ParamType, Token};
+use web3::signing::keccak256;
+use web3::types::{Log, H160, H256, U256};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(12);
+// Recent headers kept to find the fork point after a reorg
+const MAX_TRACKED_HEADERS: u64 = 128;
+const LAST_BLOCK_KEY: &str = "indexer.last_block";
+const HEADERS_KEY: &str = "indexer.headers";
+const BACKFILLS_KEY: &str = "indexer.backfills";
+
+lazy_static! {
+    static ref INDEX: Mutex<Index> = Mutex::new(Index::default());
+    static ref TRANSFER_TOPIC: H256 = H256::from(keccak256(b"Transfer(address,address,uint256)"));
+    static ref APPROVAL_TOPIC: H256 = H256::from(keccak256(b"Approval(address,address,uint256)"));
+    static ref APPROVAL_FOR_ALL_TOPIC: H256 =
+        H256::from(keccak256(b"ApprovalForAll(address,address,bool)"));
+    static ref TRANSFER_SINGLE_TOPIC: H256 =
+        H256::from(keccak256(b"TransferSingle(address,address,address,uint256,uint256)"));
+    static ref TRANSFER_BATCH_TOPIC: H256 =
+        H256::from(keccak256(b"TransferBatch(address,address,address,uint256[],uint256[])"));
+    // Work for the indexer thread, which is the only one that applies blocks
+    static ref UPDATES: (Mutex<Sender<Update>>, Mutex<Receiver<Update>>) = {
+        let (sender, receiver) = channel();
+        (Mutex::new(sender), Mutex::new(receiver))
+    };
+    // Events from the logs subscription for blocks whose header hasn't arrived yet
+    static ref LIVE_EVENTS: Mutex<BTreeMap<u64, Vec<TokenEvent>>> = Mutex::new(BTreeMap::new());
+}
+
+enum Update {
+    // Sync now instead of at the next poll
+    Wake,
+    Head(BlockHeader),
+    Log(Log),
+}
+
+// Ownership and approvals are derived from `events`; only the events, the
+// cursor and the tracked headers are stored.
+#[derive(Default)]
+struct Index {
+    last_block: Option<u64>,
+    owners: HashMap<H160, HashMap<U256, H160>>,
+    approvals: HashMap<H160, HashMap<U256, H160>>,
+    operators: HashMap<H160, HashMap<H160, Vec<H160>>>,
+    // ERC-1155 amounts by contract, token and holder
+    balances: HashMap<H160, HashMap<U256, HashMap<H160, U256>>>,
+    events: Vec<TokenEvent>,
+    headers: BTreeMap<u64, BlockHeader>,
+    head: Option<u64>,
+    backfills: Vec<Backfill>,
+    // Contracts the live subscription filters logs by, while it is connected
+    subscription: Option<Vec<H160>>,
+    error: Option<String>,
+}
+
+// History of a contract registered after the cursor passed its start block.
+// Blocks after `to_block` are read by the regular sync with every contract.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Backfill {
+    pub contract_address: H160,
+    pub next_block: u64,
+    pub to_block: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "event")]
+pub enum TokenEventKind {
+    Transfer {
+        from: H160,
+        to: H160,
+        token_id: U256,
+    },
+    Approval {
+        owner: H160,
+        approved: H160,
+        token_id: U256,
+    },
+    ApprovalForAll {
+        owner: H160,
+        operator: H160,
+        approved: bool,
+    },
+    // ERC-1155 TransferSingle or TransferBatch; mints come from and burns go
+    // to the zero address
+    Erc1155Transfer {
+        operator: H160,
+        from: H160,
+        to: H160,
+        token_ids: Vec<U256>,
+        amounts: Vec<U256>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenEvent {
+    pub contract_address: H160,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub transaction_hash: H256,
+    pub log_index: u64,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(flatten)]
+    pub kind: TokenEventKind,
+}
+
+impl TokenEvent {
+    fn stream_event(&self) -> (&'static str, Subject) {
+        let (name, token_ids, addresses) = match &self.kind {
+            TokenEventKind::Transfer { from, to, token_id } => {
+                ("token.transfer", vec![*token_id], vec![*from, *to])
+            }
+            TokenEventKind::Approval {
+                owner,
+                approved,
+                token_id,
+            } => ("token.approval", vec![*token_id], vec![*owner, *approved]),
+            TokenEventKind::ApprovalForAll {
+                owner, operator, ..
+            } => ("token.approval_for_all", Vec::new(), vec![*owner, *operator]),
+            TokenEventKind::Erc1155Transfer {
+                from, to, token_ids, ..
+            } => ("token.transfer", token_ids.clone(), vec![*from, *to]),
+        };
+        let subject = Subject {
+            contract_address: Some(self.contract_address),
+            token_ids,
+            addresses,
+            transaction_hash: Some(self.transaction_hash),
+        };
+        (name, subject)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenState {
+    pub contract_address: H160,
+    pub token_id: U256,
+    pub owner: Option<H160>,
+    pub approved: Option<H160>,
+    pub operators: Vec<H160>,
+    // Holders of an ERC-1155 token and their amounts
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub balances: BTreeMap<H160, U256>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexerStatus {
+    pub contracts: Vec<H160>,
+    pub start_block: u64,
+    pub last_block: Option<u64>,
+    pub head: Option<u64>,
+    pub confirmations: u64,
+    pub finalized_block: Option<u64>,
+    pub events: usize,
+    pub backfills: Vec<Backfill>,
+    pub subscribed: bool,
+    pub error: Option<String>,
+}
+
+fn address_topic(topic: &H256) -> H160 {
+    H160::from(*topic)
+}
+
+// Ids and amounts from the data of an ERC-1155 transfer log
+fn erc1155_amounts(topic: H256, data: &[u8]) -> Option<(Vec<U256>, Vec<U256>)> {
+    let (kinds, batch) = if topic == *TRANSFER_SINGLE_TOPIC {
+        (vec![ParamType::Uint(256), ParamType::Uint(256)], false)
+    } else {
+        let array = ParamType::Array(Box::new(ParamType::Uint(256)));
+        (vec![array.clone(), array], true)
+    };
+    let mut tokens = decode_data(&kinds, data).ok()?.into_iter();
+    let (ids, amounts) = (tokens.next()?, tokens.next()?);
+    let (ids, amounts) = if batch {
+        (ids.into_array()?, amounts.into_array()?)
+    } else {
+        (vec![ids], vec![amounts])
+    };
+    let ids: Option<Vec<U256>> = ids.into_iter().map(Token::into_uint).collect();
+    let amounts: Option<Vec<U256>> = amounts.into_iter().map(Token::into_uint).collect();
+    let (ids, amounts) = (ids?, amounts?);
+    if ids.len() != amounts.len() {
+        return None;
+    }
+    Some((ids, amounts))
+}
+
+// Decodes an ERC-721 or ERC-1155 event. ERC-20 logs share the Transfer and
+// Approval signatures but carry the amount in data rather than a third topic.
+fn decode(log: &Log) -> Option<TokenEvent> {
+    let topic = *log.topics.first()?;
+    let kind = if topic == *TRANSFER_TOPIC && log.topics.len() == 4 {
+        TokenEventKind::Transfer {
+            from: address_topic(&log.topics[1]),
+            to: address_topic(&log.topics[2]),
+            token_id: U256::from(log.topics[3].as_bytes()),
+        }
+    } else if topic == *APPROVAL_TOPIC && log.topics.len() == 4 {
+        TokenEventKind::Approval {
+            owner: address_topic(&log.topics[1]),
+            approved: address_topic(&log.topics[2]),
+            token_id: U256::from(log.topics[3].as_bytes()),
+        }
+    } else if (topic == *TRANSFER_SINGLE_TOPIC || topic == *TRANSFER_BATCH_TOPIC)
+        && log.topics.len() == 4
+    {
+        let (token_ids, amounts) = erc1155_amounts(topic, &log.data.0)?;
+        TokenEventKind::Erc1155Transfer {
+            operator: address_topic(&log.topics[1]),
+            from: address_topic(&log.topics[2]),
+            to: address_topic(&log.topics[3]),
+            token_ids,
+            amounts,
+        }
+    } else if topic == *APPROVAL_FOR_ALL_TOPIC && log.topics.len() == 3 {
+        TokenEventKind::ApprovalForAll {
+            owner: address_topic(&log.topics[1]),
+            operator: address_topic(&log.topics[2]),
+            approved: log.data.0.iter().any(|byte| *byte != 0),
+        }
+    } else {
+        return None;
+    };
+
+    Some(TokenEvent {
+        contract_address: log.address,
+        block_number: log.block_number?.as_u64(),
+        block_hash: log.block_hash?,
+        transaction_hash: log.transaction_hash?,
+        log_index: log.log_index?.as_u64(),
+        timestamp: None,
+        kind,
+    })
+}
+
+impl Index {
+    fn apply(&mut self, event: &TokenEvent) {
+        let contract_address = event.contract_address;
+        match &event.kind {
+            TokenEventKind::Transfer { to, token_id, .. } => {
+                let owners = self.owners.entry(contract_address).or_default();
+                if to.is_zero() {
+                    owners.remove(token_id);
+                } else {
+                    owners.insert(*token_id, *to);
+                }
+                // ERC-721 clears the single-token approval on every transfer
+                if let Some(approvals) = self.approvals.get_mut(&contract_address) {
+                    approvals.remove(token_id);
+                }
+            }
+            TokenEventKind::Approval {
+                approved, token_id, ..
+            } => {
+                let approvals = self.approvals.entry(contract_address).or_default();
+                if approved.is_zero() {
+                    approvals.remove(token_id);
+                } else {
+                    approvals.insert(*token_id, *approved);
+                }
+            }
+            TokenEventKind::ApprovalForAll {
+                owner,
+                operator,
+                approved,
+            } => {
+                let operators = self
+                    .operators
+                    .entry(contract_address)
+                    .or_default()
+                    .entry(*owner)
+                    .or_default();
+                operators.retain(|existing| existing != operator);
+                if *approved {
+                    operators.push(*operator);
+                }
+            }
+            TokenEventKind::Erc1155Transfer {
+                from,
+                to,
+                token_ids,
+                amounts,
+                ..
+            } => {
+                let balances = self.balances.entry(contract_address).or_default();
+                for (token_id, amount) in token_ids.iter().zip(amounts) {
+                    let holders = balances.entry(*token_id).or_default();
+                    if !from.is_zero() {
+                        let balance = holders.entry(*from).or_default();
+                        *balance = balance.saturating_sub(*amount);
+                        if balance.is_zero() {
+                            holders.remove(from);
+                        }
+                    }
+                    if !to.is_zero() {
+                        let balance = holders.entry(*to).or_default();
+                        *balance = balance.saturating_add(*amount);
+                    }
+                    if holders.is_empty() {
+                        balances.remove(token_id);
+                    }
+                }
+            }
+        }
+    }
+
+    // Replays the stored events from scratch, e.g. after some were rolled back
+    fn rebuild(&mut self) {
+        self.owners.clear();
+        self.approvals.clear();
+        self.operators.clear();
+        self.balances.clear();
+        let events = std::mem::take(&mut self.events);
+        for event in &events {
+            self.apply(event);
+        }
+        self.events = events;
+    }
+
+    // Forgets everything after `fork_block`, or everything when it is None.
+    // Returns the tokens whose transfers were dropped.
+    fn roll_back(&mut self, fork_block: Option<u64>) -> BTreeSet<(H160, U256)> {
+        let keep =
+            |block_number: u64| fork_block.map_or(false, |fork_block| block_number <= fork_block);
+        let touched =
+            transferred_tokens(self.events.iter().filter(|event| !keep(event.block_number)));
+        self.events.retain(|event| keep(event.block_number));
+        self.headers.retain(|block_number, _| keep(*block_number));
+        self.rebuild();
+        self.last_block = fork_block;
+        // The sync reads everything after the fork for every contract again
+        match fork_block {
+            Some(fork_block) => {
+                for backfill in self.backfills.iter_mut() {
+                    backfill.to_block = backfill.to_block.min(fork_block);
+                }
+                self.backfills
+                    .retain(|backfill| backfill.next_block <= backfill.to_block);
+            }
+            None => self.backfills.clear(),
+        }
+        touched
+    }
+}
+
+fn save_cursor(index: &Index) -> Result<(), String> {
+    let last_block = serde_json::to_string(&index.last_block).map_err(|e| e.to_string())?;
+    let headers = serde_json::to_string(&index.headers).map_err(|e| e.to_string())?;
+    let backfills = serde_json::to_string(&index.backfills).map_err(|e| e.to_string())?;
+    storage::with(|storage| {
+        storage.put_state(LAST_BLOCK_KEY, &last_block)?;
+        storage.put_state(HEADERS_KEY, &headers)?;
+        storage.put_state(BACKFILLS_KEY, &backfills)
+    })
+}
+
+pub fn load() -> Result<(), String> {
+    let events = storage::with(|storage| storage.events())?;
+    let last_block = storage::with(|storage| storage.state(LAST_BLOCK_KEY))?;
+    let headers = storage::with(|storage| storage.state(HEADERS_KEY))?;
+    let backfills = storage::with(|storage| storage.state(BACKFILLS_KEY))?;
+
+    let mut loaded = Index {
+        events,
+        ..Index::default()
+    };
+    loaded.rebuild();
+    if let Some(last_block) = last_block {
+        loaded.last_block = serde_json::from_str(&last_block).map_err(|e| e.to_string())?;
+    }
+    if let Some(headers) = headers {
+        loaded.headers = serde_json::from_str(&headers).map_err(|e| e.to_string())?;
+    }
+    if let Some(backfills) = backfills {
+        loaded.backfills = serde_json::from_str(&backfills).map_err(|e| e.to_string())?;
+    }
+    *INDEX.lock().unwrap() = loaded;
+    Ok(())
+}
+
+fn event_topics() -> Vec<H256> {
+    vec![
+        *TRANSFER_TOPIC,
+        *APPROVAL_TOPIC,
+        *APPROVAL_FOR_ALL_TOPIC,
+        *TRANSFER_SINGLE_TOPIC,
+        *TRANSFER_BATCH_TOPIC,
+    ]
+}
+
+fn indexed_contracts() -> Vec<H160> {
+    collections::contracts()
+}
+
+fn next_block() -> u64 {
+    let index = INDEX.lock().unwrap();
+    match index.last_block {
+        Some(last_block) => last_block + 1,
+        None => collections::start_block(),
+    }
+}
+
+// Starts following a newly registered contract. Its history up to the cursor
+// is read by a backfill; a fresh index reaches it from the start block anyway.
+pub fn add_contract(contract_address: H160, start_block: u64) -> Result<(), String> {
+    let mut index = INDEX.lock().unwrap();
+    if let Some(last_block) = index.last_block.filter(|last_block| start_block <= *last_block) {
+        index
+            .backfills
+            .retain(|backfill| backfill.contract_address != contract_address);
+        index.backfills.push(Backfill {
+            contract_address,
+            next_block: start_block,
+            to_block: last_block,
+        });
+        save_cursor(&index)?;
+    }
+    drop(index);
+    wake();
+    Ok(())
+}
+
+pub fn remove_contract(contract_address: H160) -> Result<(), String> {
+    let mut index = INDEX.lock().unwrap();
+    index
+        .backfills
+        .retain(|backfill| backfill.contract_address != contract_address);
+    save_cursor(&index)
+}
+
+fn record_error(error: Option<String>) {
+    INDEX.lock().unwrap().error = error;
+}
+
+// Re-checks open orders against the current owner of each token
+fn notify_market(index: &Index, touched: BTreeSet<(H160, U256)>) -> Vec<(H160, U256, H160)> {
+    touched
+        .into_iter()
+        .map(|(contract_address, token_id)| {
+            let owner = owner_in(index, contract_address, token_id).unwrap_or_default();
+            (contract_address, token_id, owner)
+        })
+        .collect()
+}
+
+fn transferred_tokens<'a, I>(events: I) -> BTreeSet<(H160, U256)>
+where
+    I: Iterator<Item = &'a TokenEvent>,
+{
+    events
+        .filter_map(|event| match &event.kind {
+            TokenEventKind::Transfer { token_id, .. } => Some((event.contract_address, *token_id)),
+            _ => None,
+        })
+        .collect()
+}
+
+// Applies the events of one block range and moves the cursor past it. Open
+// orders are checked against the final owner of every token the range touched,
+// so old history replayed during a backfill doesn't cancel current listings.
+fn apply_range(
+    events: Vec<TokenEvent>,
+    headers: Vec<BlockHeader>,
+    to_block: u64,
+    head: u64,
+) -> Result<(), String> {
+    storage::with(|storage| storage.insert_events(&events))?;
+    let touched = transferred_tokens(events.iter());
+    // Only events near the chain head are news; a backfill doesn't notify anyone
+    let oldest = head.saturating_sub(MAX_TRACKED_HEADERS - 1);
+    for event in events.iter().filter(|event| event.block_number >= oldest) {
+        let (name, subject) = event.stream_event();
+        stream::publish(name, subject, event);
+        match event.kind {
+            TokenEventKind::Transfer { .. } | TokenEventKind::Erc1155Transfer { .. } => {
+                webhooks::publish("token.transfer", event)
+            }
+            _ => {}
+        }
+    }
+    let mut index = INDEX.lock().unwrap();
+    for event in events {
+        index.apply(&event);
+        index.events.push(event);
+    }
+    for header in headers {
+        index.headers.insert(header.number, header);
+    }
+    index.headers = index.headers.split_off(&oldest);
+    index.last_block = Some(to_block);
+    save_cursor(&index)?;
+
+    let owners = notify_market(&index, touched);
+    drop(index);
+    for (contract_address, token_id, owner) in owners {
+        market::on_transfer(contract_address, owner, token_id);
+    }
+    Ok(())
+}
+
+// Drops everything indexed after `fork_block`, or the whole index when the fork
+// is older than the tracked headers, and rolls tracked transactions back with it.
+fn rollback(fork_block: Option<u64>) -> Result<(), String> {
+    storage::with(|storage| storage.delete_events_after(fork_block))?;
+    let mut index = INDEX.lock().unwrap();
+    let touched = index.roll_back(fork_block);
+    save_cursor(&index)?;
+
+    let owners = notify_market(&index, touched);
+    drop(index);
+    for (contract_address, token_id, owner) in owners {
+        market::on_transfer(contract_address, owner, token_id);
+    }
+    receipts::rollback(fork_block);
+    Ok(())
+}
+
+// Compares the newest tracked header with the chain and walks back until the
+// hashes agree again. Anything indexed past that block is rolled back.
+async fn check_reorg() -> Result<(), String> {
+    let tracked: Vec<BlockHeader> = INDEX
+        .lock()
+        .unwrap()
+        .headers
+        .values()
+        .rev()
+        .cloned()
+        .collect();
+    let latest = match tracked.first() {
+        Some(latest) => latest.number,
+        None => return Ok(()),
+    };
+
+    let mut fork_block = None;
+    for header in &tracked {
+        let canonical = block_header(header.number).await?;
+        if canonical.map(|canonical| canonical.hash) == Some(header.hash) {
+            fork_block = Some(header.number);
+            break;
+        }
+    }
+    match fork_block {
+        Some(fork_block) if fork_block == latest => return Ok(()),
+        Some(fork_block) => eprintln!(
+            "Warning: chain reorganization, rolling back from block {} to {}",
+            latest, fork_block
+        ),
+        None => eprintln!(
+            "Warning: chain reorganization deeper than {} blocks, reindexing from the start block",
+            tracked.len()
+        ),
+    }
+    rollback(fork_block)
+}
+
+async fn fetch_headers(from_block: u64, to_block: u64) -> Result<Vec<BlockHeader>, String> {
+    let mut headers = Vec::new();
+    for number in from_block..=to_block {
+        match block_header(number).await? {
+            Some(header) => headers.push(header),
+            None => return Err(format!("block {} not found", number)),
+        }
+    }
+    Ok(headers)
+}
+
+// Fills in block timestamps, reusing fetched headers and looking up any other
+// block once per block that has events
+async fn stamp(events: &mut [TokenEvent], headers: &[BlockHeader]) -> Result<(), String> {
+    let mut timestamps: HashMap<u64, u64> = headers
+        .iter()
+        .map(|header| (header.number, header.timestamp))
+        .collect();
+    for event in events.iter_mut() {
+        if !timestamps.contains_key(&event.block_number) {
+            if let Some(header) = block_header(event.block_number).await? {
+                timestamps.insert(header.number, header.timestamp);
+            }
+        }
+        event.timestamp = timestamps.get(&event.block_number).copied();
+    }
+    Ok(())
+}
+
+// Checks the new headers chain onto the tracked ones and that every log came
+// from the block the headers describe.
+fn verify_range(headers: &[BlockHeader], events: &[TokenEvent]) -> Result<(), String> {
+    let index = INDEX.lock().unwrap();
+    let mut parent = headers
+        .first()
+        .and_then(|first| first.number.checked_sub(1))
+        .and_then(|number| index.headers.get(&number))
+        .map(|header| header.hash);
+    for header in headers {
+        if parent.map_or(false, |parent| parent != header.parent_hash) {
+            return Err(format!("parent hash mismatch at block {}", header.number));
+        }
+        parent = Some(header.hash);
+    }
+    for event in events {
+        let header = headers
+            .iter()
+            .find(|header| header.number == event.block_number);
+        if header.map_or(false, |header| header.hash != event.block_hash) {
+            return Err(format!(
+                "log in block {} has a stale block hash",
+                event.block_number
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Reads the events of one chunk starting at `from_block`, ending no later than
+// `last_block`. The chunk is halved when the node rejects a range, e.g. for
+// returning too many logs. Returns the last block read and its events in order.
+async fn fetch_events(
+    contracts: &[H160],
+    topics: &[H256],
+    from_block: u64,
+    last_block: u64,
+    chunk_size: &mut u64,
+) -> Result<(u64, Vec<TokenEvent>), String> {
+    loop {
+        let to_block = last_block.min(from_block + *chunk_size - 1);
+        match get_logs(contracts.to_vec(), topics.to_vec(), from_block, to_block).await {
+            Ok(logs) => {
+                let mut events: Vec<TokenEvent> = logs.iter().filter_map(decode).collect();
+                events.sort_by_key(|event| (event.block_number, event.log_index));
+                return Ok((to_block, events));
+            }
+            Err(e) if *chunk_size > 1 => {
+                eprintln!(
+                    "Warning: eth_getLogs {}..{} failed, retrying with a smaller range: {}",
+                    from_block, to_block, e
+                );
+                *chunk_size /= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Applies one chunk of a contract's backfill. The events are older than much
+// of the index, so the history is put back in block order afterwards.
+fn apply_backfill(
+    contract_address: H160,
+    events: Vec<TokenEvent>,
+    to_block: u64,
+) -> Result<(), String> {
+    storage::with(|storage| storage.insert_events(&events))?;
+    let touched = transferred_tokens(events.iter());
+    let mut index = INDEX.lock().unwrap();
+    for event in events {
+        index.apply(&event);
+        index.events.push(event);
+    }
+    index
+        .events
+        .sort_by_key(|event| (event.block_number, event.log_index));
+    if let Some(position) = index
+        .backfills
+        .iter()
+        .position(|backfill| backfill.contract_address == contract_address)
+    {
+        if to_block >= index.backfills[position].to_block {
+            index.backfills.remove(position);
+        } else {
+            index.backfills[position].next_block = to_block + 1;
+        }
+    }
+    save_cursor(&index)?;
+
+    let owners = notify_market(&index, touched);
+    drop(index);
+    for (contract_address, token_id, owner) in owners {
+        market::on_transfer(contract_address, owner, token_id);
+    }
+    Ok(())
+}
+
+// Works through the backfills of newly registered contracts, one at a time
+async fn run_backfills(topics: &[H256]) -> Result<u64, String> {
+    let mut chunk_size = Config::get_log_chunk_size();
+    let mut indexed = 0;
+    loop {
+        let backfill = match INDEX.lock().unwrap().backfills.first().cloned() {
+            Some(backfill) => backfill,
+            None => return Ok(indexed),
+        };
+        let (to_block, mut events) = fetch_events(
+            &[backfill.contract_address],
+            topics,
+            backfill.next_block,
+            backfill.to_block,
+            &mut chunk_size,
+        )
+        .await?;
+        let stale = {
+            let index = INDEX.lock().unwrap();
+            events.iter().any(|event| {
+                index
+                    .headers
+                    .get(&event.block_number)
+                    .map_or(false, |header| header.hash != event.block_hash)
+            })
+        };
+        if stale {
+            check_reorg().await?;
+            return Err(format!(
+                "backfill of {:?} read a reorganized block",
+                backfill.contract_address
+            ));
+        }
+        stamp(&mut events, &[]).await?;
+        indexed += to_block - backfill.next_block + 1;
+        apply_backfill(backfill.contract_address, events, to_block)?;
+    }
+}
+
+// Backfills from the cursor up to the current head in chunks. Headers are
+// fetched for the most recent blocks only, where reorgs happen.
+async fn sync() -> Result<u64, String> {
+    let contracts = indexed_contracts();
+    let head = block_number().await?;
+    INDEX.lock().unwrap().head = Some(head);
+    if contracts.is_empty() {
+        return Ok(0);
+    }
+    check_reorg().await?;
+
+    let topics = event_topics();
+    // Registered contracts catch up before the cursor moves on
+    let mut indexed = run_backfills(&topics).await?;
+    let tail_start = head.saturating_sub(MAX_TRACKED_HEADERS - 1);
+    let mut chunk_size = Config::get_log_chunk_size();
+    let mut from_block = next_block();
+    while from_block <= head {
+        let (to_block, mut events) =
+            fetch_events(&contracts, &topics, from_block, head, &mut chunk_size).await?;
+
+        let headers = if to_block >= tail_start {
+            fetch_headers(from_block.max(tail_start), to_block).await?
+        } else {
+            Vec::new()
+        };
+        if let Err(e) = verify_range(&headers, &events) {
+            // The chain moved while the range was read; retry on the next poll
+            check_reorg().await?;
+            return Err(e);
+        }
+        stamp(&mut events, &headers).await?;
+
+        indexed += to_block - from_block + 1;
+        apply_range(events, headers, to_block, head)?;
+        from_block = to_block + 1;
+    }
+    receipts::refresh(head).await?;
+    Ok(indexed)
+}
+
+fn send(update: Update) {
+    // The receiver lives as long as the process
+    let _ = UPDATES.0.lock().unwrap().send(update);
+}
+
+// Runs a sync right away instead of at the next poll
+pub fn wake() {
+    send(Update::Wake);
+}
+
+// Heads and logs from the subscription are handed to the indexer thread, so
+// storage is never written from the subscription's runtime and a sync in
+// progress doesn't hold the subscription up
+pub fn notify_head(header: BlockHeader) {
+    send(Update::Head(header));
+}
+
+pub fn notify_log(log: Log) {
+    send(Update::Log(log));
+}
+
+// Syncs every POLL_INTERVAL or when woken, and applies subscription updates
+// in between
+pub fn spawn_indexer() {
+    thread::spawn(|| {
+        let updates = UPDATES.1.lock().unwrap();
+        let mut synced_at = None;
+        loop {
+            let elapsed = synced_at.map_or(POLL_INTERVAL, |at: Instant| at.elapsed());
+            if elapsed >= POLL_INTERVAL {
+                match block_on(sync()) {
+                    Ok(_) => record_error(None),
+                    Err(e) => {
+                        eprintln!("Error: indexer: {}", e);
+                        record_error(Some(e));
+                    }
+                }
+                synced_at = Some(Instant::now());
+            }
+            let elapsed = synced_at.map_or(Duration::default(), |at| at.elapsed());
+            match updates.recv_timeout(POLL_INTERVAL.saturating_sub(elapsed)) {
+                Ok(Update::Wake) => synced_at = None,
+                Ok(Update::Head(header)) => on_head(header),
+                Ok(Update::Log(log)) => on_log(log),
+                Err(_) => {}
+            }
+        }
+    });
+}
+
+// Contracts and topics for the logs subscription
+pub fn subscription_filter() -> (Vec<H160>, Vec<H256>) {
+    (indexed_contracts(), event_topics())
+}
+
+// The contracts a connected subscription filters by, or None when disconnected
+pub fn set_subscription(contracts: Option<Vec<H160>>) {
+    INDEX.lock().unwrap().subscription = contracts;
+}
+
+// A new head from the newHeads subscription. When it extends the indexed chain
+// the block is applied from the buffered subscription logs right away; a gap
+// after a reconnect, a reorg or a changed set of collections is left to
+// `sync`, which backfills with eth_getLogs.
+fn on_head(header: BlockHeader) {
+    INDEX.lock().unwrap().head = Some(header.number);
+    let mut events = {
+        let mut live = LIVE_EVENTS.lock().unwrap();
+        let events = live.remove(&header.number).unwrap_or_default();
+        *live = live.split_off(&header.number);
+        events
+    };
+    let contracts = indexed_contracts();
+    let extends = {
+        let index = INDEX.lock().unwrap();
+        // Logs are only complete when the subscription covers every contract
+        // and no contract is still catching up
+        index.subscription.as_ref() == Some(&contracts)
+            && index.backfills.is_empty()
+            && index.last_block.map(|last_block| last_block + 1) == Some(header.number)
+            && header
+                .number
+                .checked_sub(1)
+                .and_then(|parent| index.headers.get(&parent))
+                .map(|parent| parent.hash)
+                == Some(header.parent_hash)
+    };
+    if !extends || contracts.is_empty() {
+        return wake();
+    }
+
+    events.retain(|event| event.block_hash == header.hash);
+    events.sort_by_key(|event| event.log_index);
+    for event in events.iter_mut() {
+        event.timestamp = Some(header.timestamp);
+    }
+    let number = header.number;
+    if let Err(e) = apply_range(events, vec![header], number, number) {
+        eprintln!("Error: indexer: {}", e);
+        record_error(Some(e));
+        wake();
+    }
+}
+
+// A log from the logs subscription. Logs are held until their block's header
+// arrives. One that shows up after its block was applied means the block was
+// indexed without it, so the block is rolled back and read again.
+fn on_log(log: Log) {
+    if log.removed == Some(true) {
+        // The block was reorged out; header checks roll back anything applied
+        if let (Some(block_number), Some(block_hash)) = (log.block_number, log.block_hash) {
+            if let Some(events) = LIVE_EVENTS.lock().unwrap().get_mut(&block_number.as_u64()) {
+                events.retain(|event| event.block_hash != block_hash);
+            }
+        }
+        return;
+    }
+    let event = match decode(&log) {
+        Some(event) => event,
+        None => return,
+    };
+    let block_number = event.block_number;
+    let indexed_past = |index: &Index| {
+        index
+            .last_block
+            .map_or(false, |last_block| block_number <= last_block)
+    };
+    if !indexed_past(&INDEX.lock().unwrap()) {
+        LIVE_EVENTS
+            .lock()
+            .unwrap()
+            .entry(block_number)
+            .or_default()
+            .push(event);
+        return;
+    }
+
+    let missing = {
+        let index = INDEX.lock().unwrap();
+        indexed_past(&index)
+            && !index
+                .events
+                .iter()
+                .rev()
+                .take_while(|indexed| indexed.block_number >= event.block_number)
+                .any(|indexed| {
+                    indexed.block_number == event.block_number
+                        && indexed.log_index == event.log_index
+                        && indexed.block_hash == event.block_hash
+                })
+    };
+    if missing {
+        eprintln!(
+            "Warning: late log for block {}, indexing the block again",
+            event.block_number
+        );
+        if let Err(e) = rollback(event.block_number.checked_sub(1)) {
+            eprintln!("Error: indexer: {}", e);
+        }
+        wake();
+    }
+}
+
+fn owner_in(index: &Index, contract_address: H160, token_id: U256) -> Option<H160> {
+    index
+        .owners
+        .get(&contract_address)
+        .and_then(|owners| owners.get(&token_id))
+        .copied()
+}
+
+pub fn tokens_of_owner(contract_address: H160, owner: H160) -> Vec<U256> {
+    let index = INDEX.lock().unwrap();
+    let mut tokens: Vec<U256> = index
+        .owners
+        .get(&contract_address)
+        .map(|owners| {
+            owners
+                .iter()
+                .filter(|(_, token_owner)| **token_owner == owner)
+                .map(|(token_id, _)| *token_id)
+                .collect()
+        })
+        .unwrap_or_default();
+    // ERC-1155 tokens the owner holds any amount of
+    if let Some(balances) = index.balances.get(&contract_address) {
+        tokens.extend(
+            balances
+                .iter()
+                .filter(|(_, holders)| holders.contains_key(&owner))
+                .map(|(token_id, _)| *token_id),
+        );
+    }
+    tokens.sort();
+    tokens
+}
+
+pub fn token_events(contract_address: H160, token_id: U256) -> Vec<TokenEvent> {
+    let index = INDEX.lock().unwrap();
+    index
+        .events
+        .iter()
+        .filter(|event| event.contract_address == contract_address)
+        .filter(|event| match &event.kind {
+            TokenEventKind::Transfer { token_id: id, .. }
+            | TokenEventKind::Approval { token_id: id, .. } => *id == token_id,
+            TokenEventKind::ApprovalForAll { .. } => false,
+            TokenEventKind::Erc1155Transfer { token_ids, .. } => token_ids.contains(&token_id),
+        })
+        .cloned()
+        .collect()
+}
+
+// Owner and approvals of a token as seen by the index, without querying the chain
+pub fn token_state(contract_address: H160, token_id: U256) -> TokenState {
+    let index = INDEX.lock().unwrap();
+    let owner = owner_in(&index, contract_address, token_id);
+    let approved = index
+        .approvals
+        .get(&contract_address)
+        .and_then(|approvals| approvals.get(&token_id))
+        .copied();
+    let operators = owner
+        .and_then(|owner| {
+            index
+                .operators
+                .get(&contract_address)
+                .and_then(|operators| operators.get(&owner))
+                .cloned()
+        })
+        .unwrap_or_default();
+    let balances = index
+        .balances
+        .get(&contract_address)
+        .and_then(|balances| balances.get(&token_id))
+        .map(|holders| holders.iter().map(|(holder, amount)| (*holder, *amount)).collect())
+        .unwrap_or_default();
+    TokenState {
+        contract_address,
+        token_id,
+        owner,
+        approved,
+        operators,
+        balances,
+    }
+}
+
+pub fn status() -> IndexerStatus {
+    let index = INDEX.lock().unwrap();
+    IndexerStatus {
+        contracts: indexed_contracts(),
+        start_block: collections::start_block(),
+        last_block: index.last_block,
+        head: index.head,
+        confirmations: Config::get_confirmations(),
+        finalized_block: index
+            .head
+            .map(|head| (head + 1).saturating_sub(Config::get_confirmations())),
+        events: index.events.len(),
+        backfills: index.backfills.clone(),
+        subscribed: index.subscription.is_some(),
+        error: index.error.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contract() -> H160 {
+        H160::repeat_byte(1)
+    }
+
+    fn event(block_number: u64, kind: TokenEventKind) -> TokenEvent {
+        TokenEvent {
+            contract_address: contract(),
+            block_number,
+            block_hash: H256::from_low_u64_be(block_number),
+            transaction_hash: H256::from_low_u64_be(block_number),
+            log_index: 0,
+            timestamp: None,
+            kind,
+        }
+    }
+
+    fn transfer(block_number: u64, from: u8, to: u8) -> TokenEvent {
+        let kind = TokenEventKind::Transfer {
+            from: H160::repeat_byte(from),
+            to: H160::repeat_byte(to),
+            token_id: U256::from(7),
+        };
+        event(block_number, kind)
+    }
+
+    fn header(number: u64) -> BlockHeader {
+        BlockHeader {
+            number,
+            hash: H256::from_low_u64_be(number),
+            parent_hash: H256::from_low_u64_be(number - 1),
+            timestamp: number,
+        }
+    }
+
+    fn index(events: Vec<TokenEvent>, last_block: u64) -> Index {
+        let mut index = Index::default();
+        for event in events {
+            index.apply(&event);
+            index.events.push(event);
+        }
+        for number in 8..=last_block {
+            index.headers.insert(number, header(number));
+        }
+        index.last_block = Some(last_block);
+        index
+    }
+
+    fn owner(index: &Index) -> Option<H160> {
+        index
+            .owners
+            .get(&contract())
+            .and_then(|owners| owners.get(&U256::from(7)))
+            .copied()
+    }
+
+    #[test]
+    fn a_rollback_restores_the_owner_before_the_fork() {
+        let approval = event(
+            11,
+            TokenEventKind::Approval {
+                owner: H160::repeat_byte(2),
+                approved: H160::repeat_byte(4),
+                token_id: U256::from(7),
+            },
+        );
+        let mut index = index(vec![transfer(9, 0, 2), approval, transfer(12, 2, 3)], 12);
+        assert_eq!(owner(&index), Some(H160::repeat_byte(3)));
+
+        let touched = index.roll_back(Some(10));
+        assert_eq!(owner(&index), Some(H160::repeat_byte(2)));
+        let approvals = index.approvals.get(&contract());
+        assert!(approvals.map_or(true, |approvals| approvals.is_empty()));
+        assert!(touched.contains(&(contract(), U256::from(7))));
+        assert_eq!(index.events.len(), 1);
+        assert_eq!(index.last_block, Some(10));
+        assert_eq!(
+            index.headers.keys().copied().collect::<Vec<_>>(),
+            vec![8, 9, 10]
+        );
+    }
+
+    #[test]
+    fn a_rollback_past_the_tracked_headers_forgets_everything() {
+        let mut index = index(vec![transfer(9, 0, 2)], 9);
+        index.backfills.push(Backfill {
+            contract_address: contract(),
+            next_block: 1,
+            to_block: 9,
+        });
+        index.roll_back(None);
+        assert!(index.events.is_empty());
+        assert!(index.headers.is_empty());
+        assert!(index.backfills.is_empty());
+        assert_eq!(owner(&index), None);
+        assert_eq!(index.last_block, None);
+    }
+
+    #[test]
+    fn backfills_are_cut_back_to_the_fork() {
+        let mut index = index(Vec::new(), 12);
+        index.backfills = vec![
+            Backfill {
+                contract_address: contract(),
+                next_block: 5,
+                to_block: 12,
+            },
+            Backfill {
+                contract_address: H160::repeat_byte(2),
+                next_block: 11,
+                to_block: 12,
+            },
+        ];
+        index.roll_back(Some(10));
+        assert_eq!(index.backfills.len(), 1);
+        assert_eq!(index.backfills[0].to_block, 10);
+    }
+
+    #[test]
+    fn erc1155_balances_saturate() {
+        let mint = |amount| TokenEventKind::Erc1155Transfer {
+            operator: H160::repeat_byte(2),
+            from: H160::zero(),
+            to: H160::repeat_byte(2),
+            token_ids: vec![U256::from(7)],
+            amounts: vec![amount],
+        };
+        let events = vec![event(9, mint(U256::MAX)), event(10, mint(U256::one()))];
+        let index = index(events, 10);
+        let holders = &index.balances[&contract()][&U256::from(7)];
+        assert_eq!(holders[&H160::repeat_byte(2)], U256::MAX);
+    }
+}
@@ -0,0 +1,247 @@
+use crate::eth::{
+    code_at, eth_call, CallOutcome, INTERFACE_ID_ERC1155, INTERFACE_ID_ERC1155_METADATA_URI,
+    INTERFACE_ID_ERC165, INTERFACE_ID_ERC2981, INTERFACE_ID_ERC721, INTERFACE_ID_ERC721_ENUMERABLE,
+    INTERFACE_ID_ERC721_METADATA,
+};
+use crate::market::now;
+use crate::storage;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use web3::ethabi::{encode, Token};
+use web3::signing::keccak256;
+use web3::types::{H160, U256};
+
+// Owner used for heuristic calls; the zero address makes some contracts revert
+const PROBE_ADDRESS: H160 = H160([
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xde, 0xad,
+]);
+
+lazy_static! {
+    static ref CAPABILITIES: Mutex<HashMap<H160, Capabilities>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Capabilities {
+    pub contract_address: H160,
+    // Without working ERC-165 the rest is found by calling the functions
+    pub erc165: bool,
+    pub erc721: bool,
+    pub erc721_metadata: bool,
+    pub erc721_enumerable: bool,
+    pub erc1155: bool,
+    pub erc1155_metadata_uri: bool,
+    pub erc2981: bool,
+    pub probed_at: u64,
+}
+
+impl Capabilities {
+    // Transfers and approvals, which the market also settles with
+    pub fn check_erc721(&self) -> Result<(), String> {
+        if self.erc721 {
+            Ok(())
+        } else {
+            Err(format!(
+                "{:?} doesn't implement ERC-721",
+                self.contract_address
+            ))
+        }
+    }
+
+    pub fn check_erc1155(&self) -> Result<(), String> {
+        if self.erc1155 {
+            Ok(())
+        } else {
+            Err(format!(
+                "{:?} doesn't implement ERC-1155",
+                self.contract_address
+            ))
+        }
+    }
+
+    pub fn check_royalties(&self) -> Result<(), String> {
+        if self.erc2981 {
+            Ok(())
+        } else {
+            Err(format!(
+                "{:?} doesn't implement ERC-2981 royalties",
+                self.contract_address
+            ))
+        }
+    }
+
+    pub fn check_metadata(&self) -> Result<(), String> {
+        if self.erc721_metadata || self.erc1155_metadata_uri {
+            Ok(())
+        } else {
+            Err(format!(
+                "{:?} implements neither ERC721Metadata nor ERC1155MetadataURI",
+                self.contract_address
+            ))
+        }
+    }
+}
+
+fn selector(signature: &str) -> Vec<u8> {
+    keccak256(signature.as_bytes())[..4].to_vec()
+}
+
+// The return data, or None when the call reverted
+async fn call(
+    contract_address: H160,
+    signature: &str,
+    args: &[Token],
+) -> Result<Option<Vec<u8>>, String> {
+    let mut data = selector(signature);
+    data.extend(encode(args));
+    match eth_call(contract_address, None, data, None).await? {
+        CallOutcome::Returned(output) => Ok(Some(output)),
+        CallOutcome::Reverted(_) => Ok(None),
+    }
+}
+
+// At least `words` 32-byte words came back, e.g. 1 for a uint and 2 for a string
+async fn returns(
+    contract_address: H160,
+    signature: &str,
+    args: &[Token],
+    words: usize,
+) -> Result<bool, String> {
+    let output = call(contract_address, signature, args).await?;
+    Ok(output.map_or(false, |output| output.len() >= 32 * words))
+}
+
+async fn supports(contract_address: H160, interface_id: [u8; 4]) -> Result<bool, String> {
+    let args = [Token::FixedBytes(interface_id.to_vec())];
+    let output = call(contract_address, "supportsInterface(bytes4)", &args).await?;
+    Ok(output.map_or(false, |output| {
+        output.len() == 32 && U256::from_big_endian(&output) == U256::one()
+    }))
+}
+
+async fn by_erc165(capabilities: &mut Capabilities) -> Result<(), String> {
+    let contract_address = capabilities.contract_address;
+    capabilities.erc721 = supports(contract_address, INTERFACE_ID_ERC721).await?;
+    capabilities.erc721_metadata = supports(contract_address, INTERFACE_ID_ERC721_METADATA).await?;
+    capabilities.erc721_enumerable =
+        supports(contract_address, INTERFACE_ID_ERC721_ENUMERABLE).await?;
+    capabilities.erc1155 = supports(contract_address, INTERFACE_ID_ERC1155).await?;
+    capabilities.erc1155_metadata_uri =
+        supports(contract_address, INTERFACE_ID_ERC1155_METADATA_URI).await?;
+    capabilities.erc2981 = supports(contract_address, INTERFACE_ID_ERC2981).await?;
+    Ok(())
+}
+
+// ERC-20 tokens and plain ERC-721 contracts often have totalSupply() too, so
+// the first token is looked up by index. An empty collection can't answer
+// that and is taken as not enumerable.
+async fn enumerable(contract_address: H160) -> Result<bool, String> {
+    let supply = call(contract_address, "totalSupply()", &[]).await?;
+    let supply = match supply {
+        Some(output) if output.len() >= 32 => U256::from_big_endian(&output[..32]),
+        _ => return Ok(false),
+    };
+    if supply.is_zero() {
+        return Ok(false);
+    }
+    let zero = Token::Uint(U256::zero());
+    returns(contract_address, "tokenByIndex(uint256)", &[zero], 1).await
+}
+
+// For contracts predating ERC-165 or answering it wrongly: a function is taken
+// as present when calling it returns data of the right shape
+async fn by_heuristic(capabilities: &mut Capabilities) -> Result<(), String> {
+    let contract_address = capabilities.contract_address;
+    let owner = Token::Address(PROBE_ADDRESS);
+    let zero = Token::Uint(U256::zero());
+    let operators = [owner.clone(), owner.clone()];
+    let approvals = returns(
+        contract_address,
+        "isApprovedForAll(address,address)",
+        &operators,
+        1,
+    )
+    .await?;
+    let erc1155_balance = returns(
+        contract_address,
+        "balanceOf(address,uint256)",
+        &[owner.clone(), zero.clone()],
+        1,
+    )
+    .await?;
+    // ERC-20 tokens have balanceOf(address) too, but no operator approvals
+    let erc721_balance =
+        returns(contract_address, "balanceOf(address)", &[owner.clone()], 1).await?;
+    capabilities.erc1155 = approvals && erc1155_balance;
+    capabilities.erc721 = approvals && erc721_balance && !capabilities.erc1155;
+    if capabilities.erc721 {
+        capabilities.erc721_metadata = returns(contract_address, "name()", &[], 2).await?
+            && returns(contract_address, "symbol()", &[], 2).await?;
+        capabilities.erc721_enumerable = enumerable(contract_address).await?;
+    }
+    if capabilities.erc1155 {
+        capabilities.erc1155_metadata_uri =
+            returns(contract_address, "uri(uint256)", &[zero.clone()], 2).await?;
+    }
+    let sale_price = Token::Uint(U256::from(10000));
+    capabilities.erc2981 = returns(
+        contract_address,
+        "royaltyInfo(uint256,uint256)",
+        &[zero, sale_price],
+        2,
+    )
+    .await?;
+    Ok(())
+}
+
+// Asks the contract what it implements. ERC-165 compliance is checked the way
+// the EIP describes: true for its own id and false for 0xffffffff.
+pub async fn probe(contract_address: H160) -> Result<Capabilities, String> {
+    if code_at(contract_address).await?.is_empty() {
+        return Err(format!("no contract at {:?}", contract_address));
+    }
+    let erc165 = supports(contract_address, INTERFACE_ID_ERC165).await?
+        && !supports(contract_address, [0xff; 4]).await?;
+    let mut capabilities = Capabilities {
+        contract_address,
+        erc165,
+        erc721: false,
+        erc721_metadata: false,
+        erc721_enumerable: false,
+        erc1155: false,
+        erc1155_metadata_uri: false,
+        erc2981: false,
+        probed_at: now(),
+    };
+    if erc165 {
+        by_erc165(&mut capabilities).await?;
+    } else {
+        by_heuristic(&mut capabilities).await?;
+    }
+    Ok(capabilities)
+}
+
+// What the contract implements, probed once and remembered; `refresh` probes again
+pub async fn capabilities(contract_address: H160, refresh: bool) -> Result<Capabilities, String> {
+    if !refresh {
+        if let Some(capabilities) = CAPABILITIES.lock().unwrap().get(&contract_address) {
+            return Ok(capabilities.clone());
+        }
+        let stored = storage::with(|storage| storage.capabilities(contract_address))?;
+        if let Some(capabilities) = stored {
+            CAPABILITIES
+                .lock()
+                .unwrap()
+                .insert(contract_address, capabilities.clone());
+            return Ok(capabilities);
+        }
+    }
+    let capabilities = probe(contract_address).await?;
+    storage::with(|storage| storage.put_capabilities(&capabilities))?;
+    CAPABILITIES
+        .lock()
+        .unwrap()
+        .insert(contract_address, capabilities.clone());
+    Ok(capabilities)
+}
@@ -0,0 +1,750 @@
+use crate::collections::{create_collection, standard, CollectionError};
+use crate::config::Config;
+use crate::currency::resolve;
+use crate::deploy;
+use crate::eth::{
+    approve, burn, code_at, deploy_contract, erc1155_burn, erc20_approve, mint, minted_token_id,
+    safe_transfer_from, safe_transfer_from_with_data, send_data, set_approval_for_all,
+    set_default_royalty, set_token_royalty, transaction_receipt, transfer_from, NOT_MINED,
+};
+use crate::market::now;
+use crate::metadata::TokenStandard;
+use crate::receipts;
+use crate::storage;
+use crate::types::{
+    ApproveResponse, BurnRequest, CollectionRequest, ContractCallJob, DeployRequest,
+    Erc20ApproveResponse, MintResponse, RoyaltyResponse, SetApprovalForAllResponse,
+    TransferFormDataResponse, TransferFormResponse, TransferFromResponse,
+};
+use crate::webhooks;
+use base64::decode;
+use futures::executor::block_on;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+use web3::types::{TransactionReceipt, H160, H256, U256};
+
+const REDACTED: &str = "[redacted]";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const RETRY_DELAY: u64 = 5;
+const MAX_RETRY_DELAY: u64 = 300;
+// How long a sent transaction may stay unmined before the job gives up on it
+const MINING_TIMEOUT: u64 = 3600;
+
+lazy_static! {
+    static ref JOBS: Mutex<BTreeMap<u64, Job>> = Mutex::new(BTreeMap::new());
+    static ref WAKE: Condvar = Condvar::new();
+    // Signing keys of queued jobs. They are never written to storage.
+    static ref SECRETS: Mutex<HashMap<u64, String>> = Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    // Job being run on this worker thread
+    static CURRENT: Cell<Option<u64>> = Cell::new(None);
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobRequest {
+    Mint(MintResponse),
+    Approve(ApproveResponse),
+    SafeTransferFrom(TransferFormResponse),
+    SafeTransferFromData(TransferFormDataResponse),
+    SetApprovalForAll(SetApprovalForAllResponse),
+    TransferFrom(TransferFromResponse),
+    Royalty(RoyaltyResponse),
+    Erc20Approve(Erc20ApproveResponse),
+    Deploy(DeployRequest),
+    Call(ContractCallJob),
+    Burn(BurnRequest),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobResult {
+    pub transaction_hash: H256,
+    pub block_hash: Option<H256>,
+    pub block_number: Option<u64>,
+    pub token_id: Option<U256>,
+    // Address of a deployed contract
+    #[serde(default)]
+    pub contract_address: Option<H160>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Job {
+    pub id: u64,
+    // Collection contract the job writes to; jobs queued before the registry
+    // have none and use the configured contract
+    #[serde(default)]
+    pub contract_address: Option<H160>,
+    pub request: JobRequest,
+    pub status: JobStatus,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    pub tx_hashes: Vec<H256>,
+    pub result: Option<JobResult>,
+    pub error: Option<String>,
+    // Whether the job signs with the configured account, so it can resume after a restart
+    pub operator_key: bool,
+    pub run_at: u64,
+    pub submitted_at: Option<u64>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+enum Failure {
+    // Nothing was sent or the RPC call failed in a way worth retrying
+    Transient(String),
+    Permanent(String),
+    // A transaction is out and has no receipt yet
+    Pending,
+}
+
+impl JobRequest {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobRequest::Mint(_) => "mint",
+            JobRequest::Approve(_) => "approve",
+            JobRequest::SafeTransferFrom(_) => "safe_transfer_from",
+            JobRequest::SafeTransferFromData(_) => "safe_transfer_from_data",
+            JobRequest::SetApprovalForAll(_) => "set_approval_for_all",
+            JobRequest::TransferFrom(_) => "transfer_from",
+            JobRequest::Royalty(_) => "royalty",
+            JobRequest::Erc20Approve(_) => "erc20_approve",
+            JobRequest::Deploy(_) => "deploy",
+            JobRequest::Call(_) => "call",
+            JobRequest::Burn(_) => "burn",
+        }
+    }
+
+    fn private_key_mut(&mut self) -> &mut String {
+        match self {
+            JobRequest::Mint(data) => &mut data.private_key,
+            JobRequest::Approve(data) => &mut data.private_key,
+            JobRequest::SafeTransferFrom(data) => &mut data.private_key,
+            JobRequest::SafeTransferFromData(data) => &mut data.private_key,
+            JobRequest::SetApprovalForAll(data) => &mut data.private_key,
+            JobRequest::TransferFrom(data) => &mut data.private_key,
+            JobRequest::Royalty(data) => &mut data.private_key,
+            JobRequest::Erc20Approve(data) => &mut data.private_key,
+            JobRequest::Deploy(data) => &mut data.private_key,
+            JobRequest::Call(data) => &mut data.private_key,
+            JobRequest::Burn(data) => &mut data.private_key,
+        }
+    }
+
+    // Checks everything that doesn't need the chain, so bad requests are
+    // rejected up front instead of failing in a worker
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            JobRequest::Mint(data) => {
+                H160::from_str(&data.account_address).map_err(|e| e.to_string())?;
+                if data.royalty_fee.map_or(false, |fee| fee > 10000) {
+                    return Err("royalty fee is above 10000 basis points".into());
+                }
+            }
+            JobRequest::Approve(data) => {
+                H160::from_str(&data.address_to).map_err(|e| e.to_string())?;
+            }
+            JobRequest::SafeTransferFromData(data) => {
+                let bytes = decode(&data.data).map_err(|e| e.to_string())?;
+                String::from_utf8(bytes).map_err(|e| e.to_string())?;
+            }
+            JobRequest::Royalty(data) => {
+                if data.fee > 10000 {
+                    return Err("royalty fee is above 10000 basis points".into());
+                }
+            }
+            JobRequest::Erc20Approve(data) => {
+                let currency = resolve(Some(&data.currency))?;
+                if currency.address.is_none() {
+                    return Err(format!("{} is not an ERC-20 token", currency.symbol));
+                }
+                currency.parse(&data.amount)?;
+            }
+            JobRequest::Deploy(data) => {
+                if data.name.trim().is_empty() {
+                    return Err("a name is required".into());
+                }
+                if data.platform_fee.map_or(false, |fee| fee > 10000) {
+                    return Err("platform fee is above 10000 basis points".into());
+                }
+                deploy::prepare(data)?;
+            }
+            JobRequest::Call(data) => {
+                hex::decode(data.data.trim_start_matches("0x")).map_err(|e| e.to_string())?;
+            }
+            JobRequest::Burn(data) => {
+                if data.amount.map_or(false, |amount| amount.is_zero()) {
+                    return Err("amount must be positive".into());
+                }
+            }
+            JobRequest::SafeTransferFrom(_)
+            | JobRequest::SetApprovalForAll(_)
+            | JobRequest::TransferFrom(_) => {}
+        }
+        Ok(())
+    }
+}
+
+fn save(job: &Job) {
+    if let Err(e) = storage::with(|storage| storage.upsert_job(job)) {
+        eprintln!("Error: failed to save job {}: {}", job.id, e);
+    }
+}
+
+// Transport failures and rate limits go away on their own; reverts and bad
+// signatures don't
+fn is_transient(error: &str) -> bool {
+    [
+        "Transport error",
+        "Server is unreachable",
+        "IO error",
+        "429",
+        "-32005",
+        "timed out",
+    ]
+    .iter()
+    .any(|pattern| error.contains(pattern))
+}
+
+fn classify(error: String) -> Failure {
+    if error == NOT_MINED {
+        Failure::Pending
+    } else if is_transient(&error) {
+        Failure::Transient(error)
+    } else {
+        Failure::Permanent(error)
+    }
+}
+
+// Whether this thread is running a job
+pub fn in_job() -> bool {
+    CURRENT.with(|current| current.get().is_some())
+}
+
+// Called for every transaction the service sends. A job that has sent a
+// transaction is never submitted again, only watched until it is mined.
+pub fn note_transaction(tx_hash: H256) {
+    let id = match CURRENT.with(|current| current.get()) {
+        Some(id) => id,
+        None => return,
+    };
+    let mut jobs = JOBS.lock().unwrap();
+    if let Some(job) = jobs.get_mut(&id) {
+        if !job.tx_hashes.contains(&tx_hash) {
+            job.tx_hashes.push(tx_hash);
+            job.submitted_at = Some(now());
+            job.updated_at = now();
+            save(job);
+        }
+    }
+}
+
+// Callers validate the request first. Deployments have no contract yet.
+pub fn enqueue(contract_address: Option<H160>, mut request: JobRequest) -> Result<Job, String> {
+    let private_key = std::mem::replace(request.private_key_mut(), REDACTED.to_string());
+    let time = now();
+    let mut jobs = JOBS.lock().unwrap();
+    let id = jobs.keys().next_back().map_or(1, |id| id + 1);
+    let job = Job {
+        id,
+        contract_address,
+        request,
+        status: JobStatus::Queued,
+        attempts: 0,
+        max_attempts: Config::get_job_max_attempts(),
+        tx_hashes: Vec::new(),
+        result: None,
+        error: None,
+        operator_key: private_key == Config::get_my_private_key(),
+        run_at: time,
+        submitted_at: None,
+        created_at: time,
+        updated_at: time,
+    };
+    storage::with(|storage| storage.upsert_job(&job))?;
+    SECRETS.lock().unwrap().insert(id, private_key);
+    jobs.insert(id, job.clone());
+    WAKE.notify_one();
+    Ok(job)
+}
+
+fn private_key(job: &Job) -> Result<String, Failure> {
+    if let Some(private_key) = SECRETS.lock().unwrap().get(&job.id) {
+        return Ok(private_key.clone());
+    }
+    if job.operator_key {
+        return Ok(Config::get_my_private_key());
+    }
+    Err(Failure::Permanent(
+        "the signing key was not kept across a restart, submit the request again".into(),
+    ))
+}
+
+// Receipt of a transaction the job already sent
+async fn mined(tx_hash: H256) -> Result<TransactionReceipt, Failure> {
+    match transaction_receipt(tx_hash).await {
+        Ok(Some(receipt)) => {
+            receipts::track(&receipt);
+            Ok(receipt)
+        }
+        Ok(None) => Err(Failure::Pending),
+        Err(e) => Err(Failure::Transient(e)),
+    }
+}
+
+fn check_status(receipt: &TransactionReceipt) -> Result<(), Failure> {
+    match receipt.status {
+        Some(status) if status.is_zero() => Err(Failure::Permanent(format!(
+            "transaction {:?} reverted",
+            receipt.transaction_hash
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Deploys a contract from the bundled artifacts, checks that code landed at
+// the new address and registers it as a collection starting at its block
+async fn execute_deploy(job: &Job, data: &DeployRequest) -> Result<JobResult, Failure> {
+    let my_address = Config::get_my_account().map_err(|e| Failure::Permanent(e.to_string()))?;
+    let deployment = deploy::prepare(data).map_err(Failure::Permanent)?;
+    let receipt = match job.tx_hashes.first() {
+        Some(tx_hash) => mined(*tx_hash).await?,
+        None => deploy_contract(my_address, &private_key(job)?, deployment.code)
+            .await
+            .map_err(classify)?,
+    };
+    check_status(&receipt)?;
+    let contract_address = receipt
+        .contract_address
+        .ok_or_else(|| Failure::Permanent("no contract was created".into()))?;
+    let code = code_at(contract_address).await.map_err(Failure::Transient)?;
+    if code.is_empty() {
+        return Err(Failure::Permanent(format!(
+            "no code at {:?}",
+            contract_address
+        )));
+    }
+    let block_number = receipt
+        .block_number
+        .map(|block_number| block_number.as_u64());
+
+    // A royalty the constructor doesn't take is the contract's default royalty
+    if let (true, Some(receiver), Some(fee)) =
+        (deployment.set_royalty, data.royalty_receiver, data.royalty_fee)
+    {
+        let receipt = match job.tx_hashes.get(1) {
+            Some(tx_hash) => mined(*tx_hash).await?,
+            None => set_default_royalty(
+                contract_address,
+                my_address,
+                &private_key(job)?,
+                receiver,
+                fee as u128,
+            )
+            .await
+            .map_err(classify)?,
+        };
+        check_status(&receipt)?;
+    }
+
+    let request = CollectionRequest {
+        contract_address,
+        standard: Some(data.standard),
+        name: data.name.clone(),
+        network: None,
+        start_block: block_number.unwrap_or_default(),
+        platform_fee: data.platform_fee,
+        royalty_receiver: None,
+        royalty_fee: None,
+        abi: Some(deployment.abi),
+    };
+    match create_collection(request).await {
+        // Registered by an earlier attempt
+        Ok(_) | Err(CollectionError::Conflict(_)) => {}
+        Err(CollectionError::Internal(e)) => return Err(Failure::Transient(e)),
+        Err(CollectionError::InvalidRequest(e)) => return Err(Failure::Permanent(e)),
+        Err(CollectionError::NotFound) => {
+            return Err(Failure::Permanent("collection not found".into()))
+        }
+    }
+    Ok(JobResult {
+        transaction_hash: receipt.transaction_hash,
+        block_hash: receipt.block_hash,
+        block_number,
+        token_id: None,
+        contract_address: Some(contract_address),
+    })
+}
+
+// Sends the job's transaction, or picks up the one sent by an earlier attempt
+async fn execute(job: &Job) -> Result<JobResult, Failure> {
+    if let JobRequest::Deploy(data) = &job.request {
+        return execute_deploy(job, data).await;
+    }
+    let contract_address = match job.contract_address {
+        Some(contract_address) => contract_address,
+        None => Config::get_contract_address().map_err(|e| Failure::Permanent(e.to_string()))?,
+    };
+    let my_address = Config::get_my_account().map_err(|e| Failure::Permanent(e.to_string()))?;
+    let receipt = match job.tx_hashes.first() {
+        Some(tx_hash) => mined(*tx_hash).await?,
+        None => {
+            let private_key = private_key(job)?;
+            match &job.request {
+                JobRequest::Mint(data) => {
+                    let user_address = H160::from_str(&data.account_address)
+                        .map_err(|e| Failure::Permanent(e.to_string()))?;
+                    mint(
+                        contract_address,
+                        user_address,
+                        my_address,
+                        &private_key,
+                        &data.token_uri,
+                        data.amount,
+                    )
+                    .await
+                }
+                JobRequest::Approve(data) => {
+                    let address_to = H160::from_str(&data.address_to)
+                        .map_err(|e| Failure::Permanent(e.to_string()))?;
+                    approve(
+                        contract_address,
+                        my_address,
+                        &private_key,
+                        address_to,
+                        data.token_id,
+                    )
+                    .await
+                }
+                JobRequest::SafeTransferFrom(data) => {
+                    safe_transfer_from(
+                        contract_address,
+                        my_address,
+                        &private_key,
+                        data.from,
+                        data.to,
+                        data.token_id,
+                    )
+                    .await
+                }
+                JobRequest::SafeTransferFromData(data) => {
+                    let bytes =
+                        decode(&data.data).map_err(|e| Failure::Permanent(e.to_string()))?;
+                    safe_transfer_from_with_data(
+                        contract_address,
+                        my_address,
+                        &private_key,
+                        data.from,
+                        data.to,
+                        data.token_id,
+                        bytes,
+                    )
+                    .await
+                }
+                JobRequest::SetApprovalForAll(data) => {
+                    set_approval_for_all(
+                        contract_address,
+                        my_address,
+                        &private_key,
+                        data.operator,
+                        data.approved,
+                    )
+                    .await
+                }
+                JobRequest::TransferFrom(data) => {
+                    transfer_from(
+                        contract_address,
+                        my_address,
+                        &private_key,
+                        data.from,
+                        data.to,
+                        data.token_id,
+                    )
+                    .await
+                }
+                JobRequest::Royalty(data) => match data.token_id {
+                    Some(token_id) => {
+                        set_token_royalty(
+                            contract_address,
+                            my_address,
+                            &private_key,
+                            token_id,
+                            data.receiver,
+                            data.fee as u128,
+                        )
+                        .await
+                    }
+                    None => {
+                        set_default_royalty(
+                            contract_address,
+                            my_address,
+                            &private_key,
+                            data.receiver,
+                            data.fee as u128,
+                        )
+                        .await
+                    }
+                },
+                JobRequest::Erc20Approve(data) => {
+                    let currency = resolve(Some(&data.currency)).map_err(Failure::Permanent)?;
+                    let token_address = currency
+                        .address
+                        .ok_or_else(|| Failure::Permanent("not an ERC-20 token".into()))?;
+                    let amount = currency.parse(&data.amount).map_err(Failure::Permanent)?;
+                    erc20_approve(
+                        token_address,
+                        data.account_address,
+                        &private_key,
+                        my_address,
+                        amount,
+                    )
+                    .await
+                }
+                JobRequest::Call(data) => {
+                    let bytes = hex::decode(data.data.trim_start_matches("0x"))
+                        .map_err(|e| Failure::Permanent(e.to_string()))?;
+                    send_data(
+                        contract_address,
+                        my_address,
+                        &private_key,
+                        bytes,
+                        data.value,
+                    )
+                    .await
+                }
+                JobRequest::Burn(data) => match standard(contract_address) {
+                    TokenStandard::Erc721 => {
+                        burn(contract_address, my_address, &private_key, data.token_id).await
+                    }
+                    TokenStandard::Erc1155 => {
+                        erc1155_burn(
+                            contract_address,
+                            my_address,
+                            &private_key,
+                            data.owner.unwrap_or(my_address),
+                            data.token_id,
+                            data.amount.unwrap_or_else(U256::one),
+                        )
+                        .await
+                    }
+                },
+                JobRequest::Deploy(_) => unreachable!("deployments run in execute_deploy"),
+            }
+            .map_err(classify)?
+        }
+    };
+    check_status(&receipt)?;
+
+    let mut result = JobResult {
+        transaction_hash: receipt.transaction_hash,
+        block_hash: receipt.block_hash,
+        block_number: receipt
+            .block_number
+            .map(|block_number| block_number.as_u64()),
+        token_id: None,
+        contract_address: None,
+    };
+    // Minting with a royalty is a second transaction on the new token
+    if let JobRequest::Mint(data) = &job.request {
+        let token_id = minted_token_id(&receipt)
+            .ok_or_else(|| Failure::Permanent("no token was minted".into()))?;
+        result.token_id = Some(token_id);
+        if let (Some(receiver), Some(fee)) = (data.royalty_receiver, data.royalty_fee) {
+            let receipt = match job.tx_hashes.get(1) {
+                Some(tx_hash) => mined(*tx_hash).await?,
+                None => set_token_royalty(
+                    contract_address,
+                    my_address,
+                    &private_key(job)?,
+                    token_id,
+                    receiver,
+                    fee as u128,
+                )
+                .await
+                .map_err(classify)?,
+            };
+            check_status(&receipt)?;
+            result.transaction_hash = receipt.transaction_hash;
+            result.block_hash = receipt.block_hash;
+            result.block_number = receipt
+                .block_number
+                .map(|block_number| block_number.as_u64());
+        }
+    }
+    Ok(result)
+}
+
+fn retry_delay(attempts: u32) -> u64 {
+    (RETRY_DELAY << attempts.saturating_sub(1).min(6)).min(MAX_RETRY_DELAY)
+}
+
+// Claims the next due job, waiting until one is queued
+fn next_job() -> Job {
+    let mut jobs = JOBS.lock().unwrap();
+    loop {
+        let time = now();
+        let due = jobs
+            .values_mut()
+            .find(|job| job.status == JobStatus::Queued && job.run_at <= time);
+        if let Some(job) = due {
+            job.status = JobStatus::Running;
+            if job.tx_hashes.is_empty() {
+                job.attempts += 1;
+            }
+            job.updated_at = time;
+            save(job);
+            return job.clone();
+        }
+        jobs = WAKE.wait_timeout(jobs, POLL_INTERVAL).unwrap().0;
+    }
+}
+
+fn run_job(job: Job) {
+    CURRENT.with(|current| current.set(Some(job.id)));
+    let outcome = block_on(execute(&job));
+    CURRENT.with(|current| current.set(None));
+
+    let mut jobs = JOBS.lock().unwrap();
+    let job = match jobs.get_mut(&job.id) {
+        Some(job) => job,
+        None => return,
+    };
+    let time = now();
+    job.updated_at = time;
+    match outcome {
+        Ok(result) => {
+            job.status = JobStatus::Succeeded;
+            job.result = Some(result);
+            job.error = None;
+        }
+        Err(Failure::Permanent(e)) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(e);
+        }
+        Err(Failure::Transient(e)) if job.tx_hashes.is_empty() => {
+            if job.attempts >= job.max_attempts {
+                job.status = JobStatus::Failed;
+            } else {
+                job.status = JobStatus::Queued;
+                job.run_at = time + retry_delay(job.attempts);
+            }
+            job.error = Some(e);
+        }
+        Err(failure) => {
+            if job
+                .submitted_at
+                .map_or(false, |submitted_at| submitted_at + MINING_TIMEOUT < time)
+            {
+                job.status = JobStatus::Failed;
+                job.error = Some(format!(
+                    "transaction {:?} was not mined within {} seconds",
+                    job.tx_hashes.last().copied().unwrap_or_default(),
+                    MINING_TIMEOUT
+                ));
+            } else {
+                job.status = JobStatus::Queued;
+                job.run_at = time + RETRY_DELAY;
+                if let Failure::Transient(e) = failure {
+                    job.error = Some(e);
+                }
+            }
+        }
+    }
+    finish(job);
+}
+
+fn finish(job: &Job) {
+    save(job);
+    match job.status {
+        JobStatus::Succeeded => webhooks::publish("job.succeeded", job),
+        JobStatus::Failed => webhooks::publish("job.failed", job),
+        JobStatus::Queued | JobStatus::Running => return,
+    }
+    SECRETS.lock().unwrap().remove(&job.id);
+}
+
+// A panic is a bug, so the job fails instead of running again
+fn fail_panicked(id: u64, panic: Box<dyn Any + Send>) {
+    CURRENT.with(|current| current.set(None));
+    let message = if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown error".to_string()
+    };
+    let mut jobs = JOBS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(job) = jobs.get_mut(&id) {
+        job.status = JobStatus::Failed;
+        job.error = Some(format!("the worker panicked: {}", message));
+        job.updated_at = now();
+        finish(job);
+    }
+}
+
+// Jobs that were running when the service stopped are queued again; the ones
+// that already sent a transaction go back to waiting for its receipt
+pub fn load() -> Result<(), String> {
+    let stored = storage::with(|storage| storage.jobs())?;
+    let mut jobs = JOBS.lock().unwrap();
+    for mut job in stored {
+        if job.status == JobStatus::Running {
+            job.status = JobStatus::Queued;
+        }
+        jobs.insert(job.id, job);
+    }
+    Ok(())
+}
+
+pub fn spawn_workers() {
+    for _ in 0..Config::get_job_workers() {
+        thread::spawn(|| loop {
+            let job = next_job();
+            let id = job.id;
+            if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| run_job(job))) {
+                fail_panicked(id, panic);
+            }
+        });
+    }
+}
+
+pub fn get_job(id: u64) -> Option<Job> {
+    JOBS.lock().unwrap().get(&id).cloned()
+}
+
+// Newest first
+pub fn list_jobs(
+    status: Option<JobStatus>,
+    kind: Option<&str>,
+    offset: usize,
+    limit: usize,
+) -> Vec<Job> {
+    JOBS.lock()
+        .unwrap()
+        .values()
+        .rev()
+        .filter(|job| status.map_or(true, |status| job.status == status))
+        .filter(|job| kind.map_or(true, |kind| job.request.kind() == kind))
+        .skip(offset)
+        .take(limit)
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,339 @@
+use crate::jobs::{get_job, Job};
+use crate::market::now;
+use crate::receipts::{get_transaction, TrackedTx};
+use crate::storage;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::Outcome;
+use rocket_contrib::json::Json;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use web3::signing::keccak256;
+use web3::types::H256;
+
+const MAX_KEY_LENGTH: usize = 255;
+
+thread_local! {
+    // Journal entry of the write request being handled on this thread
+    static CURRENT: RefCell<Option<JournalEntry>> = RefCell::new(None);
+}
+
+// Value of the `Idempotency-Key` header, if the client sent one
+pub struct IdempotencyKey(pub Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for IdempotencyKey {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        match request.headers().get_one("Idempotency-Key") {
+            Some(key) if key.is_empty() || key.len() > MAX_KEY_LENGTH => {
+                Outcome::Failure((Status::BadRequest, ()))
+            }
+            key => Outcome::Success(IdempotencyKey(key.map(String::from))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JournalEntry {
+    pub key: String,
+    pub route: String,
+    pub request: Value,
+    pub request_hash: H256,
+    pub status: JournalStatus,
+    pub tx_hashes: Vec<H256>,
+    // Job the request queued; its transactions are sent on a worker thread
+    #[serde(default)]
+    pub job_id: Option<u64>,
+    pub response: Option<Value>,
+    pub http_status: Option<u16>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JournalRecord {
+    #[serde(flatten)]
+    pub entry: JournalEntry,
+    pub job: Option<Job>,
+    pub transactions: Vec<TrackedTx>,
+}
+
+// Signing keys are part of some requests; they count towards the request hash
+// but are never written to storage.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (name, field) in map.iter_mut() {
+                if name == "private_key" {
+                    *field = Value::String("[redacted]".into());
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+fn save(entry: &JournalEntry) {
+    if let Err(e) = storage::with(|storage| storage.update_journal_entry(entry)) {
+        eprintln!("Error: failed to save journal entry {}: {}", entry.key, e);
+    }
+}
+
+// Called for every transaction the service sends, so a request interrupted
+// while waiting on a receipt still has its tx hash on record.
+pub fn note_transaction(tx_hash: H256) {
+    CURRENT.with(|current| {
+        if let Some(entry) = current.borrow_mut().as_mut() {
+            if !entry.tx_hashes.contains(&tx_hash) {
+                entry.tx_hashes.push(tx_hash);
+                entry.updated_at = now();
+                save(entry);
+            }
+        }
+    });
+}
+
+// Called when the request queues a job, so the entry follows the job's
+// transactions and status instead of the copy returned when it was queued.
+pub fn note_job(id: u64) {
+    CURRENT.with(|current| {
+        if let Some(entry) = current.borrow_mut().as_mut() {
+            entry.job_id = Some(id);
+        }
+    });
+}
+
+fn replay<T: DeserializeOwned>(entry: JournalEntry, request_hash: H256) -> Result<Json<T>, Status> {
+    if entry.request_hash != request_hash {
+        return Err(Status::UnprocessableEntity);
+    }
+    match entry.status {
+        JournalStatus::InProgress => Err(Status::Conflict),
+        JournalStatus::Completed => {
+            let job = entry.job_id.and_then(get_job);
+            let response = match job {
+                Some(job) => serde_json::to_value(job).ok(),
+                None => entry.response,
+            };
+            let response = response.ok_or(Status::InternalServerError)?;
+            serde_json::from_value(response)
+                .map(Json)
+                .map_err(|_| Status::InternalServerError)
+        }
+        JournalStatus::Failed => Err(entry
+            .http_status
+            .and_then(Status::from_code)
+            .unwrap_or(Status::InternalServerError)),
+    }
+}
+
+// Runs a write request at most once per idempotency key. A replay with the same
+// key and body gets the stored result; a different body is rejected. Failures
+// that sent no transaction are forgotten so the client can retry with the key.
+pub fn run<T, R, F>(
+    key: &IdempotencyKey,
+    route: &str,
+    request: &R,
+    handle: F,
+) -> Result<Json<T>, Status>
+where
+    T: Serialize + DeserializeOwned,
+    R: Serialize,
+    F: FnOnce() -> Result<Json<T>, Status>,
+{
+    let key = match &key.0 {
+        Some(key) => key,
+        None => return handle(),
+    };
+    let mut request = serde_json::to_value(request).map_err(|_| Status::InternalServerError)?;
+    let request_hash = H256::from(keccak256(format!("{} {}", route, request).as_bytes()));
+    redact(&mut request);
+
+    let created_at = now();
+    let entry = JournalEntry {
+        key: key.clone(),
+        route: route.to_string(),
+        request,
+        request_hash,
+        status: JournalStatus::InProgress,
+        tx_hashes: Vec::new(),
+        job_id: None,
+        response: None,
+        http_status: None,
+        created_at,
+        updated_at: created_at,
+    };
+    let inserted = storage::with(|storage| storage.insert_journal_entry(&entry))
+        .map_err(|_| Status::InternalServerError)?;
+    if !inserted {
+        let stored = storage::with(|storage| storage.journal_entry(key))
+            .map_err(|_| Status::InternalServerError)?
+            .ok_or(Status::InternalServerError)?;
+        return replay(stored, request_hash);
+    }
+
+    CURRENT.with(|current| *current.borrow_mut() = Some(entry));
+    let result = handle();
+    let mut entry = match CURRENT.with(|current| current.borrow_mut().take()) {
+        Some(entry) => entry,
+        None => return result,
+    };
+
+    entry.updated_at = now();
+    match &result {
+        Ok(Json(response)) => {
+            entry.status = JournalStatus::Completed;
+            entry.response = serde_json::to_value(response).ok();
+            entry.http_status = Some(Status::Ok.code);
+        }
+        Err(status) if entry.tx_hashes.is_empty() => {
+            if let Err(e) = storage::with(|storage| storage.delete_journal_entry(&entry.key)) {
+                eprintln!(
+                    "Error: failed to release idempotency key {}: {}",
+                    entry.key, e
+                );
+            }
+            return Err(*status);
+        }
+        Err(status) => {
+            entry.status = JournalStatus::Failed;
+            entry.http_status = Some(status.code);
+        }
+    }
+    save(&entry);
+    result
+}
+
+pub fn get_entry(key: &str) -> Result<Option<JournalRecord>, String> {
+    let entry = storage::with(|storage| storage.journal_entry(key))?;
+    Ok(entry.map(|entry| {
+        let job = entry.job_id.and_then(get_job);
+        let job_txs = job.iter().flat_map(|job| job.tx_hashes.iter());
+        let mut tx_hashes: Vec<H256> = entry.tx_hashes.clone();
+        for tx_hash in job_txs {
+            if !tx_hashes.contains(tx_hash) {
+                tx_hashes.push(*tx_hash);
+            }
+        }
+        JournalRecord {
+            transactions: tx_hashes
+                .iter()
+                .filter_map(|tx_hash| get_transaction(*tx_hash))
+                .collect(),
+            job,
+            entry,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(key: &str) -> IdempotencyKey {
+        storage::init_in_memory();
+        IdempotencyKey(Some(key.to_string()))
+    }
+
+    #[test]
+    fn completed_requests_are_replayed() {
+        let key = key("completed");
+        let first = run(&key, "mint", &1, || Ok(Json(7u64)));
+        assert_eq!(first.unwrap().into_inner(), 7);
+        let replayed = run(&key, "mint", &1, || -> Result<Json<u64>, Status> {
+            panic!("a replay doesn't run the request")
+        });
+        assert_eq!(replayed.unwrap().into_inner(), 7);
+    }
+
+    #[test]
+    fn a_different_request_with_the_key_is_rejected() {
+        let key = key("different");
+        run(&key, "mint", &1, || Ok(Json(7u64))).unwrap();
+        let other = run(&key, "mint", &2, || Ok(Json(8u64)));
+        assert_eq!(other.unwrap_err(), Status::UnprocessableEntity);
+        let other = run(&key, "burn", &1, || Ok(Json(8u64)));
+        assert_eq!(other.unwrap_err(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn failures_without_a_transaction_release_the_key() {
+        let key = key("released");
+        let failed = run(&key, "mint", &1, || -> Result<Json<u64>, Status> {
+            Err(Status::BadRequest)
+        });
+        assert_eq!(failed.unwrap_err(), Status::BadRequest);
+        assert!(get_entry("released").unwrap().is_none());
+        let retried = run(&key, "mint", &1, || Ok(Json(7u64)));
+        assert_eq!(retried.unwrap().into_inner(), 7);
+    }
+
+    #[test]
+    fn failures_after_a_transaction_keep_the_key() {
+        let key = key("failed");
+        let tx_hash = H256::repeat_byte(1);
+        let failed = run(&key, "mint", &1, || -> Result<Json<u64>, Status> {
+            note_transaction(tx_hash);
+            Err(Status::InternalServerError)
+        });
+        assert_eq!(failed.unwrap_err(), Status::InternalServerError);
+        let entry = get_entry("failed").unwrap().unwrap().entry;
+        assert_eq!(entry.status, JournalStatus::Failed);
+        assert_eq!(entry.tx_hashes, vec![tx_hash]);
+        let replayed = run(&key, "mint", &1, || Ok(Json(7u64)));
+        assert_eq!(replayed.unwrap_err(), Status::InternalServerError);
+    }
+
+    #[test]
+    fn requests_in_progress_conflict() {
+        let key = key("in_progress");
+        let inner = IdempotencyKey(key.0.clone());
+        let result = run(&key, "mint", &1, || {
+            let nested = run(&inner, "mint", &1, || Ok(Json(8u64)));
+            assert_eq!(nested.unwrap_err(), Status::Conflict);
+            Ok(Json(7u64))
+        });
+        assert_eq!(result.unwrap().into_inner(), 7);
+    }
+
+    #[test]
+    fn queued_jobs_are_recorded() {
+        let key = key("queued");
+        run(&key, "mint", &1, || {
+            note_job(5);
+            Ok(Json(7u64))
+        })
+        .unwrap();
+        let record = get_entry("queued").unwrap().unwrap();
+        assert_eq!(record.entry.job_id, Some(5));
+        assert!(record.job.is_none());
+    }
+
+    #[test]
+    fn private_keys_are_redacted() {
+        let mut request = json!({
+            "buyer": "0x01",
+            "private_key": "secret",
+            "orders": [{ "private_key": "secret" }],
+        });
+        redact(&mut request);
+        assert_eq!(request["private_key"], "[redacted]");
+        assert_eq!(request["orders"][0]["private_key"], "[redacted]");
+        assert_eq!(request["buyer"], "0x01");
+    }
+}
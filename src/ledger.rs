@@ -0,0 +1,120 @@
+use crate::currency::Currency;
+use crate::settlement::SaleReceipt;
+use crate::storage;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use web3::types::{H160, H256, U256};
+
+lazy_static! {
+    // Id of the last recorded payout; payouts themselves live in storage
+    static ref LAST_ID: Mutex<u64> = Mutex::new(0);
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Payout {
+    pub id: u64,
+    pub listing_id: u64,
+    pub contract_address: H160,
+    pub token_id: U256,
+    pub seller: H160,
+    pub buyer: H160,
+    pub currency: Currency,
+    pub gross_price: U256,
+    pub fee: U256,
+    pub royalty: U256,
+    pub seller_proceeds: U256,
+    pub tx_hash: Option<H256>,
+    pub error: Option<String>,
+    pub timestamp: u64,
+}
+
+pub fn load() -> Result<(), String> {
+    let payouts = storage::with(|storage| storage.payouts())?;
+    *LAST_ID.lock().unwrap() = payouts.last().map_or(0, |payout| payout.id);
+    Ok(())
+}
+
+// A sale has already settled when this runs, so a payout that can't be saved
+// is logged rather than failing the purchase
+pub fn record(
+    listing_id: u64,
+    contract_address: H160,
+    token_id: U256,
+    seller: H160,
+    buyer: H160,
+    receipt: &SaleReceipt,
+    timestamp: u64,
+) -> Payout {
+    let mut last_id = LAST_ID.lock().unwrap();
+    *last_id += 1;
+    let payout = Payout {
+        id: *last_id,
+        listing_id,
+        contract_address,
+        token_id,
+        seller,
+        buyer,
+        currency: receipt.currency.clone(),
+        gross_price: receipt.price,
+        fee: receipt.fee,
+        royalty: receipt.royalty,
+        seller_proceeds: receipt.seller_proceeds,
+        tx_hash: receipt.payment_tx,
+        error: receipt.error.clone(),
+        timestamp,
+    };
+    if let Err(e) = storage::with(|storage| storage.insert_payout(&payout)) {
+        eprintln!("Error: failed to save payout {}: {}", payout.id, e);
+    }
+    payout
+}
+
+pub fn payouts(
+    contract_address: Option<H160>,
+    seller: Option<H160>,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Vec<Payout>, String> {
+    let payouts = storage::with(|storage| storage.payouts())?;
+    Ok(payouts
+        .into_iter()
+        .filter(|payout| contract_address.map_or(true, |c| payout.contract_address == c))
+        .filter(|payout| seller.map_or(true, |s| payout.seller == s))
+        .filter(|payout| from.map_or(true, |from| payout.timestamp >= from))
+        .filter(|payout| to.map_or(true, |to| payout.timestamp < to))
+        .collect())
+}
+
+pub fn to_csv(payouts: &[Payout]) -> String {
+    let mut csv = String::from(
+        "id,listing_id,contract_address,token_id,seller,buyer,currency,gross_price,fee,royalty,seller_proceeds,tx_hash,error,timestamp\n",
+    );
+    for payout in payouts {
+        csv.push_str(&format!(
+            "{},{},{:?},{},{:?},{:?},{},{},{},{},{},{},\"{}\",{}\n",
+            payout.id,
+            payout.listing_id,
+            payout.contract_address,
+            payout.token_id,
+            payout.seller,
+            payout.buyer,
+            payout.currency.symbol,
+            payout.gross_price,
+            payout.fee,
+            payout.royalty,
+            payout.seller_proceeds,
+            payout
+                .tx_hash
+                .map(|hash| format!("{:?}", hash))
+                .unwrap_or_default(),
+            payout
+                .error
+                .as_deref()
+                .unwrap_or_default()
+                .replace('"', "\"\""),
+            payout.timestamp,
+        ));
+    }
+    csv
+}
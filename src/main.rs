@@ -1,10 +1,50 @@
 #![feature(decl_macro)]
+mod abi;
+mod admin;
+mod assets;
+mod collections;
 mod config;
+mod currency;
+mod deploy;
 mod eth;
 mod http;
+mod indexer;
+mod interfaces;
+mod jobs;
+mod journal;
+mod ledger;
+mod market;
+mod media;
+mod metadata;
+mod orderbook;
+mod preflight;
+mod receipts;
+mod settlement;
+mod signatures;
+mod simulation;
+mod storage;
+mod stream;
+mod subscriptions;
+mod tokens;
 mod types;
+mod webhooks;
 
 fn main() {
     config::Config::from_file("config.json").unwrap();
+    currency::check_decimals().unwrap();
+    storage::init().unwrap();
+    assets::init().unwrap();
+    collections::load().unwrap();
+    market::load().unwrap();
+    ledger::load().unwrap();
+    receipts::load().unwrap();
+    indexer::load().unwrap();
+    jobs::load().unwrap();
+    webhooks::load().unwrap();
+    indexer::spawn_indexer();
+    subscriptions::spawn_subscriber();
+    orderbook::spawn_matcher();
+    jobs::spawn_workers();
+    webhooks::spawn_dispatcher();
     http::run_server();
 }
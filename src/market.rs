@@ -0,0 +1,852 @@
+use crate::currency::Currency;
+use crate::eth::verify_account;
+use crate::ledger;
+use crate::settlement::{quote, settle, SaleReceipt};
+use crate::signatures;
+use crate::storage;
+use crate::stream::{self, Subject};
+use crate::webhooks;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use web3::types::{H160, H256, U256};
+
+lazy_static! {
+    static ref MARKET: Mutex<Market> = Mutex::new(Market::default());
+}
+
+#[derive(Default)]
+struct Market {
+    next_id: u64,
+    listings: HashMap<u64, Listing>,
+    next_offer_id: u64,
+    offers: HashMap<u64, Offer>,
+}
+
+#[derive(Debug)]
+pub enum MarketError {
+    NotFound,
+    NotActive,
+    SoldOut,
+    InvalidRequest(String),
+    // The caller couldn't show they act for the buyer or seller
+    Forbidden(String),
+    Chain(String),
+    // The buyer paid but the token didn't move; the settlement is kept on the
+    // listing's `failed` for manual resolution
+    Settlement(Fill),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceCurve {
+    Linear,
+    Exponential,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DutchAuction {
+    pub start_price: U256,
+    pub floor_price: U256,
+    pub start_time: u64,
+    pub end_time: u64,
+    pub curve: PriceCurve,
+    pub rebate: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FixedPrice {
+    pub price: U256,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ListingKind {
+    DutchAuction(DutchAuction),
+    FixedPrice(FixedPrice),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingStatus {
+    Active,
+    SoldOut,
+    Ended,
+    Finalized,
+    Cancelled,
+    Invalidated,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Fill {
+    pub buyer: H160,
+    pub token_id: U256,
+    #[serde(flatten)]
+    pub receipt: SaleReceipt,
+    pub offer_id: Option<u64>,
+    pub rebate: U256,
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Listing {
+    pub id: u64,
+    pub contract_address: H160,
+    pub seller: H160,
+    pub kind: ListingKind,
+    pub token_ids: Vec<U256>,
+    pub pending: Vec<U256>,
+    pub fills: Vec<Fill>,
+    // Settlements whose token transfer failed after payment
+    #[serde(default)]
+    pub failed: Vec<Fill>,
+    pub status: ListingStatus,
+    pub currency: Currency,
+    pub current_price: U256,
+    pub current_price_formatted: String,
+    pub clearing_price: Option<U256>,
+    pub created_at: u64,
+    // Hash of the signed order, so it can't be placed twice
+    #[serde(default)]
+    pub order_hash: Option<H256>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferStatus {
+    Active,
+    Filling,
+    Filled,
+    Cancelled,
+    Expired,
+    Invalidated,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Offer {
+    pub id: u64,
+    pub contract_address: H160,
+    pub token_id: U256,
+    pub buyer: H160,
+    pub currency: Currency,
+    pub price: U256,
+    pub price_formatted: String,
+    pub expires_at: Option<u64>,
+    pub status: OfferStatus,
+    pub listing_id: Option<u64>,
+    pub error: Option<String>,
+    pub created_at: u64,
+    #[serde(default)]
+    pub order_hash: Option<H256>,
+}
+
+// A token taken off a listing while its sale is being settled
+pub struct Reservation {
+    pub listing_id: u64,
+    pub contract_address: H160,
+    pub seller: H160,
+    pub token_id: U256,
+    pub currency: Currency,
+    pub price: U256,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl DutchAuction {
+    pub fn price_at(&self, time: u64) -> U256 {
+        if time <= self.start_time {
+            return self.start_price;
+        }
+        if time >= self.end_time {
+            return self.floor_price;
+        }
+
+        let elapsed = time - self.start_time;
+        let duration = self.end_time - self.start_time;
+        match self.curve {
+            PriceCurve::Linear => {
+                // Creation rejects auctions where this can overflow; stored ones
+                // from before that check divide first instead
+                let range = self.start_price - self.floor_price;
+                let drop = match range.checked_mul(U256::from(elapsed)) {
+                    Some(scaled) => scaled / U256::from(duration),
+                    None => range / U256::from(duration) * U256::from(elapsed),
+                };
+                self.start_price - drop
+            }
+            PriceCurve::Exponential => {
+                // start * (floor / start) ^ (elapsed / duration), so the curve lands on the floor
+                let start = self.start_price.as_u128() as f64;
+                let floor = self.floor_price.as_u128() as f64;
+                let factor = (floor / start).powf(elapsed as f64 / duration as f64);
+                let price = U256::from((start * factor) as u128);
+                price.max(self.floor_price).min(self.start_price)
+            }
+        }
+    }
+}
+
+impl Listing {
+    fn price_at(&self, time: u64) -> U256 {
+        match &self.kind {
+            ListingKind::DutchAuction(auction) => auction.price_at(time),
+            ListingKind::FixedPrice(fixed) => fixed.price,
+        }
+    }
+
+    fn refresh(&mut self, time: u64) {
+        self.current_price = self.price_at(time);
+        self.current_price_formatted = self.currency.format(self.current_price);
+        if self.status != ListingStatus::Active {
+            return;
+        }
+        if self.token_ids.is_empty() && self.pending.is_empty() {
+            self.status = ListingStatus::SoldOut;
+            return;
+        }
+        let end_time = match &self.kind {
+            ListingKind::DutchAuction(auction) => Some(auction.end_time),
+            ListingKind::FixedPrice(fixed) => fixed.expires_at,
+        };
+        if end_time.map_or(false, |end_time| time >= end_time) && self.pending.is_empty() {
+            self.status = ListingStatus::Ended;
+        }
+    }
+
+    pub fn is_open(&self, time: u64) -> bool {
+        let started = match &self.kind {
+            ListingKind::DutchAuction(auction) => time >= auction.start_time,
+            ListingKind::FixedPrice(_) => true,
+        };
+        self.status == ListingStatus::Active && started && !self.token_ids.is_empty()
+    }
+}
+
+impl Offer {
+    fn refresh(&mut self, time: u64) {
+        if self.status == OfferStatus::Active
+            && self.expires_at.map_or(false, |expires_at| time >= expires_at)
+        {
+            self.status = OfferStatus::Expired;
+        }
+    }
+}
+
+fn save_listing(market: &Market, id: u64) {
+    if let Some(listing) = market.listings.get(&id) {
+        if let Err(e) = storage::with(|storage| storage.upsert_listing(listing)) {
+            eprintln!("Error: failed to save listing {}: {}", id, e);
+        }
+    }
+}
+
+fn save_offer(market: &Market, id: u64) {
+    if let Some(offer) = market.offers.get(&id) {
+        if let Err(e) = storage::with(|storage| storage.upsert_offer(offer)) {
+            eprintln!("Error: failed to save offer {}: {}", id, e);
+        }
+    }
+}
+
+// Rebuilds the order book from storage. Reservations that were in flight when the
+// service stopped may have moved funds already, so they are left for review.
+pub fn load() -> Result<(), String> {
+    let listings = storage::with(|storage| storage.listings())?;
+    let offers = storage::with(|storage| storage.offers())?;
+    let mut loaded = Market::default();
+    for listing in listings {
+        if !listing.pending.is_empty() {
+            eprintln!(
+                "Warning: listing {} has unsettled tokens {:?}",
+                listing.id, listing.pending
+            );
+        }
+        loaded.next_id = loaded.next_id.max(listing.id);
+        loaded.listings.insert(listing.id, listing);
+    }
+    for offer in offers {
+        if offer.status == OfferStatus::Filling {
+            eprintln!("Warning: offer {} was being filled", offer.id);
+        }
+        loaded.next_offer_id = loaded.next_offer_id.max(offer.id);
+        loaded.offers.insert(offer.id, offer);
+    }
+    let mut market = MARKET.lock().unwrap();
+    *market = loaded;
+    Ok(())
+}
+
+fn placed(market: &Market, order_hash: H256) -> bool {
+    market
+        .listings
+        .values()
+        .any(|listing| listing.order_hash == Some(order_hash))
+        || market
+            .offers
+            .values()
+            .any(|offer| offer.order_hash == Some(order_hash))
+}
+
+fn insert_listing(
+    contract_address: H160,
+    seller: H160,
+    kind: ListingKind,
+    token_ids: Vec<U256>,
+    currency: Currency,
+    order_hash: H256,
+) -> Result<Listing, MarketError> {
+    let mut market = MARKET.lock().unwrap();
+    if placed(&market, order_hash) {
+        return Err(MarketError::InvalidRequest(
+            "this signed order was already placed".into(),
+        ));
+    }
+    market.next_id += 1;
+    let created_at = now();
+    let mut listing = Listing {
+        id: market.next_id,
+        contract_address,
+        seller,
+        kind,
+        token_ids,
+        pending: Vec::new(),
+        fills: Vec::new(),
+        failed: Vec::new(),
+        status: ListingStatus::Active,
+        currency,
+        current_price: U256::zero(),
+        current_price_formatted: String::new(),
+        clearing_price: None,
+        created_at,
+        order_hash: Some(order_hash),
+    };
+    listing.refresh(created_at);
+    market.listings.insert(listing.id, listing.clone());
+    save_listing(&market, listing.id);
+    drop(market);
+    let subject = Subject {
+        contract_address: Some(listing.contract_address),
+        token_ids: listing.token_ids.clone(),
+        addresses: vec![listing.seller],
+        transaction_hash: None,
+    };
+    stream::publish("listing.created", subject, &listing);
+    Ok(listing)
+}
+
+pub fn create_dutch_auction(
+    contract_address: H160,
+    seller: H160,
+    token_ids: Vec<U256>,
+    currency: Currency,
+    auction: DutchAuction,
+    order_hash: H256,
+) -> Result<Listing, MarketError> {
+    if token_ids.is_empty() {
+        return Err(MarketError::InvalidRequest("token_ids is empty".into()));
+    }
+    if auction.end_time <= auction.start_time {
+        return Err(MarketError::InvalidRequest("duration must be positive".into()));
+    }
+    if auction.start_price < auction.floor_price {
+        return Err(MarketError::InvalidRequest(
+            "start_price is below floor_price".into(),
+        ));
+    }
+    let duration = U256::from(auction.end_time - auction.start_time);
+    if (auction.start_price - auction.floor_price)
+        .checked_mul(duration)
+        .is_none()
+    {
+        return Err(MarketError::InvalidRequest(
+            "price range is too large for the duration".into(),
+        ));
+    }
+    if auction.curve == PriceCurve::Exponential
+        && (auction.floor_price.is_zero() || auction.start_price > U256::from(u128::MAX))
+    {
+        return Err(MarketError::InvalidRequest(
+            "exponential curve needs a non-zero floor and a start price below 2^128".into(),
+        ));
+    }
+
+    insert_listing(
+        contract_address,
+        seller,
+        ListingKind::DutchAuction(auction),
+        token_ids,
+        currency,
+        order_hash,
+    )
+}
+
+pub fn create_fixed_price(
+    contract_address: H160,
+    seller: H160,
+    token_id: U256,
+    currency: Currency,
+    fixed: FixedPrice,
+    order_hash: H256,
+) -> Result<Listing, MarketError> {
+    if fixed.price.is_zero() {
+        return Err(MarketError::InvalidRequest("price must be positive".into()));
+    }
+    insert_listing(
+        contract_address,
+        seller,
+        ListingKind::FixedPrice(fixed),
+        vec![token_id],
+        currency,
+        order_hash,
+    )
+}
+
+pub fn create_offer(
+    contract_address: H160,
+    token_id: U256,
+    buyer: H160,
+    currency: Currency,
+    price: U256,
+    expires_at: Option<u64>,
+    order_hash: H256,
+) -> Result<Offer, MarketError> {
+    // The buyer isn't around when an offer fills, so funds must be pullable by the operator
+    if currency.address.is_none() {
+        return Err(MarketError::InvalidRequest(
+            "offers must be priced in an allow-listed ERC-20".into(),
+        ));
+    }
+    if price.is_zero() {
+        return Err(MarketError::InvalidRequest("price must be positive".into()));
+    }
+
+    let mut market = MARKET.lock().unwrap();
+    if placed(&market, order_hash) {
+        return Err(MarketError::InvalidRequest(
+            "this signed order was already placed".into(),
+        ));
+    }
+    market.next_offer_id += 1;
+    let offer = Offer {
+        id: market.next_offer_id,
+        contract_address,
+        token_id,
+        buyer,
+        price_formatted: currency.format(price),
+        currency,
+        price,
+        expires_at,
+        status: OfferStatus::Active,
+        listing_id: None,
+        error: None,
+        created_at: now(),
+        order_hash: Some(order_hash),
+    };
+    market.offers.insert(offer.id, offer.clone());
+    save_offer(&market, offer.id);
+    drop(market);
+    let subject = Subject {
+        contract_address: Some(offer.contract_address),
+        token_ids: vec![offer.token_id],
+        addresses: vec![offer.buyer],
+        transaction_hash: None,
+    };
+    stream::publish("offer.created", subject, &offer);
+    Ok(offer)
+}
+
+pub fn get_listing(id: u64) -> Option<Listing> {
+    let mut market = MARKET.lock().unwrap();
+    let listing = market.listings.get_mut(&id)?;
+    listing.refresh(now());
+    Some(listing.clone())
+}
+
+pub fn list_listings() -> Vec<Listing> {
+    let time = now();
+    let mut market = MARKET.lock().unwrap();
+    let mut listings: Vec<Listing> = market
+        .listings
+        .values_mut()
+        .map(|listing| {
+            listing.refresh(time);
+            listing.clone()
+        })
+        .collect();
+    listings.sort_by_key(|listing| listing.id);
+    listings
+}
+
+pub fn get_offer(id: u64) -> Option<Offer> {
+    let mut market = MARKET.lock().unwrap();
+    let offer = market.offers.get_mut(&id)?;
+    offer.refresh(now());
+    Some(offer.clone())
+}
+
+pub fn list_offers() -> Vec<Offer> {
+    let time = now();
+    let mut market = MARKET.lock().unwrap();
+    let mut offers: Vec<Offer> = market
+        .offers
+        .values_mut()
+        .map(|offer| {
+            offer.refresh(time);
+            offer.clone()
+        })
+        .collect();
+    offers.sort_by_key(|offer| offer.id);
+    offers
+}
+
+// Only the seller can cancel, by signing `signatures::cancel("listing", id)`
+pub fn cancel_listing(id: u64, signature: &str) -> Result<Listing, MarketError> {
+    let mut market = MARKET.lock().unwrap();
+    let listing = market.listings.get_mut(&id).ok_or(MarketError::NotFound)?;
+    let message = signatures::cancel("listing", id);
+    signatures::verify(listing.seller, &message, signature).map_err(MarketError::Forbidden)?;
+    listing.refresh(now());
+    if listing.status != ListingStatus::Active || !listing.pending.is_empty() {
+        return Err(MarketError::NotActive);
+    }
+    listing.status = ListingStatus::Cancelled;
+    let listing = listing.clone();
+    save_listing(&market, id);
+    Ok(listing)
+}
+
+// Only the buyer can cancel, by signing `signatures::cancel("offer", id)`
+pub fn cancel_offer(id: u64, signature: &str) -> Result<Offer, MarketError> {
+    let mut market = MARKET.lock().unwrap();
+    let offer = market.offers.get_mut(&id).ok_or(MarketError::NotFound)?;
+    let message = signatures::cancel("offer", id);
+    signatures::verify(offer.buyer, &message, signature).map_err(MarketError::Forbidden)?;
+    offer.refresh(now());
+    if offer.status != OfferStatus::Active {
+        return Err(MarketError::NotActive);
+    }
+    offer.status = OfferStatus::Cancelled;
+    let offer = offer.clone();
+    save_offer(&market, id);
+    Ok(offer)
+}
+
+// Takes `token_id` (or the next token in line) off the listing at the price in effect now
+pub fn reserve(id: u64, token_id: Option<U256>) -> Result<Reservation, MarketError> {
+    let time = now();
+    let mut market = MARKET.lock().unwrap();
+    let listing = market.listings.get_mut(&id).ok_or(MarketError::NotFound)?;
+    listing.refresh(time);
+    if listing.status == ListingStatus::SoldOut {
+        return Err(MarketError::SoldOut);
+    }
+    if !listing.is_open(time) {
+        return Err(MarketError::NotActive);
+    }
+    let index = match token_id {
+        Some(token_id) => listing
+            .token_ids
+            .iter()
+            .position(|listed| *listed == token_id)
+            .ok_or(MarketError::SoldOut)?,
+        None => 0,
+    };
+    let token_id = listing.token_ids.remove(index);
+    listing.pending.push(token_id);
+    let reservation = Reservation {
+        listing_id: id,
+        contract_address: listing.contract_address,
+        seller: listing.seller,
+        token_id,
+        currency: listing.currency.clone(),
+        price: listing.current_price,
+    };
+    save_listing(&market, id);
+    Ok(reservation)
+}
+
+// Settles a reservation. On failure before any funds moved the token goes back on sale.
+pub async fn execute(
+    reservation: Reservation,
+    buyer: H160,
+    buyer_private_key: Option<&str>,
+    price: U256,
+    offer_id: Option<u64>,
+) -> Result<Fill, MarketError> {
+    let Reservation {
+        listing_id,
+        contract_address,
+        seller,
+        token_id,
+        currency,
+        ..
+    } = reservation;
+
+    let settled = match quote(contract_address, token_id, &currency, price).await {
+        Ok(receipt) => {
+            settle(
+                contract_address,
+                seller,
+                buyer,
+                buyer_private_key,
+                token_id,
+                receipt,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    let receipt = match settled {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            let mut market = MARKET.lock().unwrap();
+            if let Some(listing) = market.listings.get_mut(&listing_id) {
+                listing.pending.retain(|pending| *pending != token_id);
+                listing.token_ids.insert(0, token_id);
+            }
+            save_listing(&market, listing_id);
+            return Err(MarketError::Chain(e));
+        }
+    };
+
+    // The buyer has paid at this point, so a failed transfer is kept on the
+    // listing for manual resolution instead of returning the token to sale.
+    let fill = Fill {
+        buyer,
+        token_id,
+        receipt,
+        offer_id,
+        rebate: U256::zero(),
+        timestamp: now(),
+    };
+    let sold = fill.receipt.transfer_tx.is_some();
+    if sold {
+        ledger::record(
+            listing_id,
+            contract_address,
+            token_id,
+            seller,
+            buyer,
+            &fill.receipt,
+            fill.timestamp,
+        );
+    }
+
+    let mut market = MARKET.lock().unwrap();
+    if let Some(listing) = market.listings.get_mut(&listing_id) {
+        listing.pending.retain(|pending| *pending != token_id);
+        if sold {
+            listing.fills.push(fill.clone());
+        } else {
+            listing.failed.push(fill.clone());
+        }
+        listing.refresh(now());
+    }
+    save_listing(&market, listing_id);
+    drop(market);
+    let sale = serde_json::json!({
+        "listing_id": listing_id,
+        "contract_address": contract_address,
+        "seller": seller,
+        "fill": fill,
+    });
+    let subject = Subject {
+        contract_address: Some(contract_address),
+        token_ids: vec![token_id],
+        addresses: vec![seller, buyer],
+        transaction_hash: fill.receipt.transfer_tx.or(fill.receipt.payment_tx),
+    };
+    if !sold {
+        stream::publish("listing.settlement_failed", subject, &sale);
+        webhooks::publish("listing.settlement_failed", &sale);
+        return Err(MarketError::Settlement(fill));
+    }
+    stream::publish("listing.sold", subject, &sale);
+    webhooks::publish("listing.sold", &sale);
+    Ok(fill)
+}
+
+// The buyer's key is checked before anything is reserved, since ERC-20
+// payments are pulled by the operator and never signed by the buyer
+pub async fn purchase(id: u64, buyer: H160, buyer_private_key: &str) -> Result<Fill, MarketError> {
+    verify_account(buyer, buyer_private_key)
+        .await
+        .map_err(MarketError::Forbidden)?;
+    let reservation = reserve(id, None)?;
+    let price = reservation.price;
+    execute(reservation, buyer, Some(buyer_private_key), price, None).await
+}
+
+pub fn finalize(id: u64) -> Result<Listing, MarketError> {
+    let mut market = MARKET.lock().unwrap();
+    let listing = market.listings.get_mut(&id).ok_or(MarketError::NotFound)?;
+    listing.refresh(now());
+    let rebate = match &listing.kind {
+        ListingKind::DutchAuction(auction) => auction.rebate,
+        ListingKind::FixedPrice(_) => return Err(MarketError::NotActive),
+    };
+    if listing.status == ListingStatus::Active {
+        return Err(MarketError::NotActive);
+    }
+    if listing.status == ListingStatus::Finalized {
+        return Ok(listing.clone());
+    }
+
+    // Prices only decline, so the clearing price is the lowest price paid.
+    // Fills whose transfer failed are left for manual resolution and don't count.
+    let clearing_price = listing
+        .fills
+        .iter()
+        .filter(|fill| fill.receipt.transfer_tx.is_some())
+        .map(|fill| fill.receipt.price)
+        .min();
+    if let (true, Some(clearing_price)) = (rebate, clearing_price) {
+        for fill in listing.fills.iter_mut() {
+            if fill.receipt.transfer_tx.is_some() {
+                fill.rebate = fill.receipt.price - clearing_price;
+            }
+        }
+    }
+    listing.clearing_price = clearing_price;
+    listing.status = ListingStatus::Finalized;
+    let listing = listing.clone();
+    save_listing(&market, id);
+    Ok(listing)
+}
+
+// Marks an offer as being filled so the matcher doesn't pick it twice
+pub fn claim_offer(id: u64, listing_id: u64) -> bool {
+    let mut market = MARKET.lock().unwrap();
+    let claimed = match market.offers.get_mut(&id) {
+        Some(offer) => {
+            offer.refresh(now());
+            if offer.status == OfferStatus::Active {
+                offer.status = OfferStatus::Filling;
+                offer.listing_id = Some(listing_id);
+                true
+            } else {
+                false
+            }
+        }
+        None => false,
+    };
+    if claimed {
+        save_offer(&market, id);
+    }
+    claimed
+}
+
+pub fn release_offer(id: u64, filled: bool, error: Option<String>) {
+    let mut market = MARKET.lock().unwrap();
+    if let Some(offer) = market.offers.get_mut(&id) {
+        if filled {
+            offer.status = OfferStatus::Filled;
+        } else {
+            offer.status = OfferStatus::Active;
+            offer.listing_id = None;
+        }
+        offer.error = error;
+    }
+    save_offer(&market, id);
+}
+
+// Drops listed tokens that left the seller outside of a marketplace sale, and
+// offers whose buyer now owns the token. Tokens being sold here are already
+// moved from `token_ids` to `pending`, so our own transfers don't match.
+pub fn on_transfer(contract_address: H160, to: H160, token_id: U256) {
+    let mut market = MARKET.lock().unwrap();
+    let mut changed_listings = Vec::new();
+    let mut changed_offers = Vec::new();
+    for listing in market.listings.values_mut() {
+        if listing.contract_address == contract_address
+            && listing.status == ListingStatus::Active
+            && listing.token_ids.contains(&token_id)
+            && to != listing.seller
+        {
+            listing.token_ids.retain(|listed| *listed != token_id);
+            if listing.token_ids.is_empty() && listing.pending.is_empty() {
+                listing.status = ListingStatus::Invalidated;
+            }
+            changed_listings.push(listing.id);
+        }
+    }
+    for offer in market.offers.values_mut() {
+        if offer.contract_address == contract_address
+            && offer.token_id == token_id
+            && offer.status == OfferStatus::Active
+            && offer.buyer == to
+        {
+            offer.status = OfferStatus::Invalidated;
+            changed_offers.push(offer.id);
+        }
+    }
+    for id in changed_listings {
+        save_listing(&market, id);
+    }
+    for id in changed_offers {
+        save_offer(&market, id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auction(curve: PriceCurve) -> DutchAuction {
+        DutchAuction {
+            start_price: U256::from(1000),
+            floor_price: U256::from(100),
+            start_time: 1000,
+            end_time: 2000,
+            curve,
+            rebate: false,
+        }
+    }
+
+    #[test]
+    fn linear_price_falls_evenly() {
+        let auction = auction(PriceCurve::Linear);
+        assert_eq!(auction.price_at(0), U256::from(1000));
+        assert_eq!(auction.price_at(1000), U256::from(1000));
+        assert_eq!(auction.price_at(1250), U256::from(775));
+        assert_eq!(auction.price_at(1500), U256::from(550));
+        assert_eq!(auction.price_at(2000), U256::from(100));
+        assert_eq!(auction.price_at(u64::MAX), U256::from(100));
+    }
+
+    #[test]
+    fn exponential_price_stays_between_start_and_floor() {
+        let auction = auction(PriceCurve::Exponential);
+        assert_eq!(auction.price_at(1000), U256::from(1000));
+        assert_eq!(auction.price_at(2000), U256::from(100));
+        // sqrt(1000 * 100) halfway through
+        assert_eq!(auction.price_at(1500), U256::from(316));
+        let mut last = auction.start_price;
+        for time in (1000..=2000).step_by(50) {
+            let price = auction.price_at(time);
+            assert!(price <= last && price >= auction.floor_price);
+            last = price;
+        }
+    }
+
+    #[test]
+    fn linear_price_divides_first_when_the_range_is_too_large() {
+        let auction = DutchAuction {
+            start_price: U256::MAX,
+            floor_price: U256::zero(),
+            start_time: 0,
+            end_time: 4,
+            curve: PriceCurve::Linear,
+            rebate: false,
+        };
+        assert_eq!(auction.price_at(2), U256::MAX - U256::MAX / 4 * 2);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::config::Config;
+use crate::market::now;
+use crate::metadata::{decode_data_uri, fetch_client, resolve_uri};
+use crate::storage;
+use image::io::Reader;
+use image::{DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_MEDIA_SIZE: u64 = 32 << 20;
+// Checked from the header before decoding, so a small file can't claim a huge canvas
+const MAX_PIXELS: u64 = 64_000_000;
+// Longest side of each thumbnail
+const THUMBNAIL_SIZES: &[u32] = &[256, 512];
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Thumbnail {
+    pub size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub url: String,
+}
+
+// What was found at a media URI. Failures are kept too, so a broken link isn't
+// downloaded again until the metadata is refreshed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MediaInfo {
+    pub uri: String,
+    // sha256 of the file
+    pub content_hash: Option<String>,
+    // Sniffed from the file itself
+    pub content_type: Option<String>,
+    // What the server said, when it disagrees with the file
+    pub declared_content_type: Option<String>,
+    pub size: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub thumbnails: Vec<Thumbnail>,
+    pub error: Option<String>,
+    pub processed_at: u64,
+}
+
+impl MediaInfo {
+    fn new(uri: &str) -> MediaInfo {
+        MediaInfo {
+            uri: uri.to_string(),
+            content_hash: None,
+            content_type: None,
+            declared_content_type: None,
+            size: None,
+            width: None,
+            height: None,
+            thumbnails: Vec::new(),
+            error: None,
+            processed_at: now(),
+        }
+    }
+}
+
+// Media types are taken from magic bytes; servers and data: URIs often lie
+fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    let starts = |prefix: &[u8]| bytes.starts_with(prefix);
+    let riff = |kind: &[u8]| starts(b"RIFF") && bytes.get(8..12) == Some(kind);
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if starts(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        Some("image/gif")
+    } else if riff(b"WEBP") {
+        Some("image/webp")
+    } else if riff(b"WAVE") {
+        Some("audio/wav")
+    } else if starts(b"ID3") || starts(b"\xff\xfb") || starts(b"\xff\xf3") || starts(b"\xff\xf2") {
+        Some("audio/mpeg")
+    } else if bytes.get(4..8) == Some(&b"ftyp"[..]) {
+        Some("video/mp4")
+    } else if starts(b"\x1a\x45\xdf\xa3") {
+        Some("video/webm")
+    } else if starts(b"glTF") {
+        Some("model/gltf-binary")
+    } else if is_svg(bytes) {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+fn is_svg(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(1024)];
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    (text.starts_with("<svg") || text.starts_with("<?xml") || text.starts_with("<!DOCTYPE svg"))
+        && text.contains("<svg")
+}
+
+fn raster_format(content_type: &str) -> Option<ImageFormat> {
+    match content_type {
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/gif" => Some(ImageFormat::Gif),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+fn thumbnail_dir() -> PathBuf {
+    Path::new(&Config::get_data_dir()).join("thumbnails")
+}
+
+// Photos stay JPEG; everything else becomes PNG to keep transparency
+fn thumbnail_extension(content_type: &str) -> &'static str {
+    if content_type == "image/jpeg" {
+        "jpg"
+    } else {
+        "png"
+    }
+}
+
+fn download(uri: &str) -> Result<(Vec<u8>, Option<String>), String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (media_type, bytes) = decode_data_uri(data)?;
+        if bytes.len() as u64 > MAX_MEDIA_SIZE {
+            return Err("media is larger than 32 MiB".into());
+        }
+        return Ok((
+            bytes,
+            Some(media_type).filter(|media_type| !media_type.is_empty()),
+        ));
+    }
+    let url = resolve_uri(uri);
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("unsupported media URI: {}", uri));
+    }
+    let client = fetch_client(&url, FETCH_TIMEOUT)?;
+    let response = client
+        .get(&url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    if response.content_length().unwrap_or(0) > MAX_MEDIA_SIZE {
+        return Err("media is larger than 32 MiB".into());
+    }
+    let declared = response
+        .headers()
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or(value)
+                .trim()
+                .to_ascii_lowercase()
+        });
+    // The length header is optional, so the body is capped while reading too
+    let mut bytes = Vec::new();
+    response
+        .take(MAX_MEDIA_SIZE + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() as u64 > MAX_MEDIA_SIZE {
+        return Err("media is larger than 32 MiB".into());
+    }
+    Ok((bytes, declared))
+}
+
+fn write_thumbnail(image: &DynamicImage, path: &Path, extension: &str) -> Result<(), String> {
+    if path.exists() {
+        return Ok(());
+    }
+    let format = if extension == "jpg" {
+        ImageOutputFormat::Jpeg(JPEG_QUALITY)
+    } else {
+        ImageOutputFormat::Png
+    };
+    let mut encoded = Vec::new();
+    image
+        .write_to(&mut encoded, format)
+        .map_err(|e| e.to_string())?;
+    // Written aside and renamed so a reader never sees half a file
+    let partial = path.with_extension("partial");
+    fs::write(&partial, encoded).map_err(|e| e.to_string())?;
+    fs::rename(&partial, path).map_err(|e| e.to_string())
+}
+
+fn make_thumbnails(info: &mut MediaInfo, bytes: &[u8], format: ImageFormat) -> Result<(), String> {
+    let content_hash = info.content_hash.clone().unwrap_or_default();
+    let content_type = info.content_type.clone().unwrap_or_default();
+    let reader = Reader::with_format(Cursor::new(bytes), format);
+    let (width, height) = reader.into_dimensions().map_err(|e| e.to_string())?;
+    info.width = Some(width);
+    info.height = Some(height);
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(format!(
+            "{}x{} image is too large to thumbnail",
+            width, height
+        ));
+    }
+    let image = Reader::with_format(Cursor::new(bytes), format)
+        .decode()
+        .map_err(|e| e.to_string())?;
+
+    let dir = thumbnail_dir();
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let extension = thumbnail_extension(&content_type);
+    for &size in THUMBNAIL_SIZES {
+        // Never scaled up; small images are only re-encoded
+        let thumbnail = if width <= size && height <= size {
+            image.clone()
+        } else {
+            image.thumbnail(size, size)
+        };
+        let path = dir.join(format!("{}-{}.{}", content_hash, size, extension));
+        write_thumbnail(&thumbnail, &path, extension)?;
+        let (thumbnail_width, thumbnail_height) = thumbnail.dimensions();
+        info.thumbnails.push(Thumbnail {
+            size,
+            width: thumbnail_width,
+            height: thumbnail_height,
+            url: format!("/media/{}/{}", content_hash, size),
+        });
+    }
+    Ok(())
+}
+
+fn inspect(info: &mut MediaInfo) -> Result<(), String> {
+    let (bytes, declared) = download(&info.uri)?;
+    info.size = Some(bytes.len() as u64);
+    info.content_hash = Some(hex::encode(Sha256::digest(&bytes)));
+    let content_type = sniff(&bytes).ok_or_else(|| match &declared {
+        Some(declared) => format!("unsupported media type (declared {})", declared),
+        None => "unsupported media type".to_string(),
+    })?;
+    info.content_type = Some(content_type.to_string());
+    info.declared_content_type = declared.filter(|declared| declared != content_type);
+    match raster_format(content_type) {
+        Some(format) => make_thumbnails(info, &bytes, format),
+        None => Ok(()),
+    }
+}
+
+// Downloads, sniffs and thumbnails the media at `uri` once and remembers the
+// result; `refresh` does it again
+pub fn process(uri: &str, refresh: bool) -> MediaInfo {
+    if !refresh {
+        match storage::with(|storage| storage.media(uri)) {
+            Ok(Some(info)) => return info,
+            Ok(None) => {}
+            Err(e) => eprintln!("Error: failed to read media {}: {}", uri, e),
+        }
+    }
+    let mut info = MediaInfo::new(uri);
+    if let Err(e) = inspect(&mut info) {
+        info.error = Some(e);
+    }
+    if let Err(e) = storage::with(|storage| storage.put_media(uri, &info)) {
+        eprintln!("Error: failed to save media {}: {}", uri, e);
+    }
+    info
+}
+
+// A cached thumbnail by the content hash of its original
+pub fn thumbnail(content_hash: &str, size: u32) -> Result<Option<(&'static str, Vec<u8>)>, String> {
+    if content_hash.len() != 64 || !content_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    for (extension, content_type) in &[("jpg", "image/jpeg"), ("png", "image/png")] {
+        let path = thumbnail_dir().join(format!("{}-{}.{}", content_hash, size, extension));
+        match fs::read(&path) {
+            Ok(content) => return Ok(Some((*content_type, content))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_types_are_sniffed_from_magic_bytes() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"\x89PNG\r\n\x1a\n....", "image/png"),
+            (b"\xff\xd8\xff\xe0....", "image/jpeg"),
+            (b"GIF89a....", "image/gif"),
+            (b"RIFF\0\0\0\0WEBPVP8 ", "image/webp"),
+            (b"RIFF\0\0\0\0WAVEfmt ", "audio/wav"),
+            (b"ID3\x04\0\0", "audio/mpeg"),
+            (b"\0\0\0\x18ftypmp42", "video/mp4"),
+            (b"\x1a\x45\xdf\xa3....", "video/webm"),
+            (b"glTF\x02\0\0\0", "model/gltf-binary"),
+        ];
+        for (bytes, content_type) in cases {
+            assert_eq!(sniff(bytes), Some(*content_type));
+        }
+        assert_eq!(sniff(b"RIFF\0\0\0\0AVI LIST"), None);
+        assert_eq!(sniff(b"plain text"), None);
+    }
+
+    #[test]
+    fn svg_is_recognized_by_its_root_element() {
+        let svg = b"<svg xmlns='http://www.w3.org/2000/svg'/>";
+        assert_eq!(sniff(svg), Some("image/svg+xml"));
+        let with_prolog = "\u{feff}  <?xml version=\"1.0\"?>\n<svg></svg>";
+        assert_eq!(sniff(with_prolog.as_bytes()), Some("image/svg+xml"));
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?><html></html>"), None);
+        assert_eq!(sniff(b"<html><svg></svg></html>"), None);
+    }
+
+    #[test]
+    fn data_uris_are_downloaded_without_a_request() {
+        let (bytes, media_type) = download("data:image/gif;base64,R0lGODlh").unwrap();
+        assert_eq!(bytes, b"GIF89a");
+        assert_eq!(media_type.as_deref(), Some("image/gif"));
+        let (_, media_type) = download("data:,GIF89a").unwrap();
+        assert_eq!(media_type, None);
+    }
+
+    #[test]
+    fn only_jpeg_thumbnails_stay_jpeg() {
+        assert_eq!(thumbnail_extension("image/jpeg"), "jpg");
+        assert_eq!(thumbnail_extension("image/png"), "png");
+        assert_eq!(thumbnail_extension("image/webp"), "png");
+        assert_eq!(raster_format("image/svg+xml"), None);
+        assert_eq!(raster_format("image/gif"), Some(ImageFormat::Gif));
+    }
+}
@@ -0,0 +1,521 @@
+use crate::collections;
+use crate::config::Config;
+use crate::eth::{erc1155_uri, token_uri};
+use crate::interfaces::capabilities;
+use crate::market::now;
+use crate::media::{self, MediaInfo};
+use crate::storage;
+use reqwest::blocking::Client;
+use reqwest::redirect::{Action, Attempt, Policy};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use web3::types::{H160, U256};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 10;
+// Metadata documents are small; anything bigger is not one
+const MAX_DOCUMENT_SIZE: u64 = 1 << 20;
+// display_type values marketplaces understand for numeric traits
+const DISPLAY_TYPES: &[&str] = &["number", "boost_number", "boost_percentage", "date"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenStandard {
+    Erc721,
+    Erc1155,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenMetadata {
+    pub contract_address: H160,
+    pub token_id: U256,
+    pub standard: TokenStandard,
+    pub token_uri: String,
+    pub metadata: Value,
+    // The image with ipfs:// mapped onto the gateway, ready for a browser
+    pub image_url: Option<String>,
+    // The image and animation, sniffed and thumbnailed
+    pub media: Vec<MediaInfo>,
+    pub valid: bool,
+    pub errors: Vec<String>,
+    pub fetched_at: Option<u64>,
+}
+
+// Maps ipfs:// URIs onto the configured gateway, leaving HTTP(S) URIs untouched
+pub fn resolve_uri(uri: &str) -> String {
+    match uri.strip_prefix("ipfs://") {
+        Some(path) => format!(
+            "{}{}",
+            Config::get_ipfs_gateway(),
+            path.trim_start_matches("ipfs/")
+        ),
+        None => uri.to_string(),
+    }
+}
+
+// ERC-1155 clients replace `{id}` with the token id as 64 lowercase hex digits
+fn expand_id(uri: &str, token_id: U256) -> String {
+    uri.replace("{id}", &format!("{:064x}", token_id))
+}
+
+fn percent_decode(data: &str) -> Vec<u8> {
+    let bytes = data.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+// data:[<mediatype>][;base64],<data>, without the scheme. Returns the media
+// type and the decoded bytes.
+pub fn decode_data_uri(data: &str) -> Result<(String, Vec<u8>), String> {
+    let separator = data.find(',').ok_or("malformed data URI")?;
+    let (header, payload) = (&data[..separator], &data[separator + 1..]);
+    let media_type = header.split(';').next().unwrap_or_default().to_string();
+    if header.ends_with(";base64") {
+        let bytes = base64::decode(payload).map_err(|e| e.to_string())?;
+        Ok((media_type, bytes))
+    } else {
+        Ok((media_type, percent_decode(payload)))
+    }
+}
+
+fn parse_data_uri(data: &str) -> Result<Value, String> {
+    let (_, bytes) = decode_data_uri(data)?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+// Serves documents already in storage and stores anything newly fetched.
+// data: URIs carry the document themselves and are never stored.
+pub fn fetch(uri: &str) -> Result<Value, String> {
+    fetch_cached(uri, false).map(|(document, _)| document)
+}
+
+// Like `fetch`, but `refresh` skips the stored copy and replaces it. Returns
+// when the document was fetched, or None for data: URIs.
+pub fn fetch_cached(uri: &str, refresh: bool) -> Result<(Value, Option<u64>), String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        return Ok((parse_data_uri(data)?, None));
+    }
+    if !refresh {
+        if let Some((document, fetched_at)) = storage::with(|storage| storage.metadata(uri))? {
+            return Ok((document, Some(fetched_at)));
+        }
+    }
+    let document = fetch_uri(uri)?;
+    let fetched_at = now();
+    storage::with(|storage| storage.put_metadata(uri, &document, fetched_at))?;
+    Ok((document, Some(fetched_at)))
+}
+
+// Addresses a token URI may make the server connect to. Loopback, private,
+// link-local (cloud metadata endpoints) and other special ranges are refused.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+// Resolves the URL's host and checks every address it resolves to
+fn public_addrs(url: &Url) -> Result<Vec<SocketAddr>, String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("{} has no host", url))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| format!("{} has no port", url))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("failed to resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() || addrs.iter().any(|addr| !is_public(addr.ip())) {
+        return Err(format!("{} does not resolve to a public address", host));
+    }
+    Ok(addrs)
+}
+
+fn check_redirect(attempt: Attempt) -> Action {
+    if attempt.previous().len() >= MAX_REDIRECTS {
+        return attempt.error("too many redirects");
+    }
+    match public_addrs(attempt.url()) {
+        Ok(_) => attempt.follow(),
+        Err(e) => attempt.error(e),
+    }
+}
+
+// A client for fetching a token's metadata or media. The host is resolved and
+// checked up front and the connection is pinned to the checked addresses, so
+// the URI can't point the server at internal hosts. Redirects are checked the
+// same way. The configured IPFS gateway may be a local node and is trusted.
+pub fn fetch_client(url: &str, timeout: Duration) -> Result<Client, String> {
+    let builder = Client::builder().timeout(timeout);
+    if url.starts_with(&Config::get_ipfs_gateway()) {
+        return builder.build().map_err(|e| e.to_string());
+    }
+    let parsed = Url::parse(url).map_err(|e| format!("invalid URL {}: {}", url, e))?;
+    let addrs = public_addrs(&parsed)?;
+    let host = parsed.host_str().unwrap_or_default();
+    builder
+        .resolve_to_addrs(host, &addrs)
+        .redirect(Policy::custom(check_redirect))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn fetch_uri(uri: &str) -> Result<Value, String> {
+    let url = resolve_uri(uri);
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("unsupported token URI: {}", uri));
+    }
+    let client = fetch_client(&url, FETCH_TIMEOUT)?;
+    let response = client
+        .get(&url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    // Read no more than the limit, whatever the server claims the length is
+    let mut body = Vec::new();
+    response
+        .take(MAX_DOCUMENT_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| e.to_string())?;
+    if body.len() as u64 > MAX_DOCUMENT_SIZE {
+        return Err(format!("metadata at {} is larger than 1 MiB", uri));
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("metadata at {} is not JSON: {}", uri, e))
+}
+
+fn is_uri(value: &str) -> bool {
+    ["http://", "https://", "ipfs://", "ar://", "data:"]
+        .iter()
+        .any(|scheme| value.starts_with(scheme))
+}
+
+fn check_attribute(index: usize, attribute: &Value, errors: &mut Vec<String>) {
+    let attribute = match attribute.as_object() {
+        Some(attribute) => attribute,
+        None => return errors.push(format!("attributes[{}] is not an object", index)),
+    };
+    if let Some(trait_type) = attribute.get("trait_type") {
+        if !trait_type.is_string() {
+            errors.push(format!("attributes[{}].trait_type is not a string", index));
+        }
+    }
+    let value = match attribute.get("value") {
+        Some(value) if value.is_string() || value.is_number() || value.is_boolean() => value,
+        Some(_) => {
+            return errors.push(format!(
+                "attributes[{}].value is not a string, number or boolean",
+                index
+            ))
+        }
+        None => return errors.push(format!("attributes[{}] has no value", index)),
+    };
+    match attribute.get("display_type").map(Value::as_str) {
+        None => {}
+        Some(Some(display_type)) if DISPLAY_TYPES.contains(&display_type) => {
+            if !value.is_number() {
+                errors.push(format!(
+                    "attributes[{}] has display_type {} but a non-numeric value",
+                    index, display_type
+                ));
+            }
+        }
+        Some(_) => errors.push(format!(
+            "attributes[{}].display_type is not supported",
+            index
+        )),
+    }
+    if let Some(max_value) = attribute.get("max_value") {
+        if !max_value.is_number() {
+            errors.push(format!("attributes[{}].max_value is not a number", index));
+        }
+    }
+}
+
+// Checks a document against the OpenSea metadata standard. Every field is
+// optional, but the ones present must have the expected shape.
+pub fn validate(document: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    let fields = match document.as_object() {
+        Some(fields) => fields,
+        None => return vec!["metadata is not a JSON object".into()],
+    };
+    for field in &["name", "description"] {
+        if let Some(value) = fields.get(*field) {
+            if !value.is_string() {
+                errors.push(format!("{} is not a string", field));
+            }
+        }
+    }
+    for field in &[
+        "image",
+        "image_url",
+        "animation_url",
+        "external_url",
+        "youtube_url",
+    ] {
+        match fields.get(*field).map(Value::as_str) {
+            None | Some(Some("")) => {}
+            Some(Some(value)) if is_uri(value) => {}
+            Some(Some(_)) => errors.push(format!("{} is not a URI", field)),
+            Some(None) => errors.push(format!("{} is not a string", field)),
+        }
+    }
+    if let Some(image_data) = fields.get("image_data") {
+        if !image_data.is_string() {
+            errors.push("image_data is not a string".into());
+        }
+    }
+    if let Some(color) = fields.get("background_color") {
+        let hex = color
+            .as_str()
+            .filter(|color| color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit()));
+        if hex.is_none() {
+            errors.push("background_color is not six hex digits without a leading #".into());
+        }
+    }
+    match fields.get("attributes") {
+        None => {}
+        Some(Value::Array(attributes)) => {
+            for (index, attribute) in attributes.iter().enumerate() {
+                check_attribute(index, attribute, &mut errors);
+            }
+        }
+        Some(_) => errors.push("attributes is not an array".into()),
+    }
+    errors
+}
+
+// Contracts found to implement ERC-1155 are ERC-1155; anything else,
+// including contracts that couldn't be probed, is treated as ERC-721
+pub async fn probe_standard(contract_address: H160) -> TokenStandard {
+    let erc1155 = capabilities(contract_address, false)
+        .await
+        .map_or(false, |capabilities| capabilities.erc1155);
+    if erc1155 {
+        TokenStandard::Erc1155
+    } else {
+        TokenStandard::Erc721
+    }
+}
+
+// Finds the token's metadata URI: `uri(id)` for ERC-1155 and `tokenURI(id)`
+// for ERC-721. Registered collections say which standard they follow.
+pub async fn resolve_token_uri(
+    contract_address: H160,
+    token_id: U256,
+) -> Result<(TokenStandard, String), String> {
+    let standard = match collections::by_address(contract_address) {
+        Some(collection) => collection.standard,
+        None => probe_standard(contract_address).await,
+    };
+    if standard == TokenStandard::Erc1155 {
+        let uri = erc1155_uri(contract_address, token_id).await?;
+        Ok((TokenStandard::Erc1155, expand_id(&uri, token_id)))
+    } else {
+        let uri = token_uri(contract_address, token_id).await?;
+        Ok((TokenStandard::Erc721, uri))
+    }
+}
+
+pub async fn token_metadata(
+    contract_address: H160,
+    token_id: U256,
+    refresh: bool,
+) -> Result<TokenMetadata, String> {
+    let (standard, token_uri) = resolve_token_uri(contract_address, token_id).await?;
+    if token_uri.is_empty() {
+        return Err(format!("token {} has no metadata URI", token_id));
+    }
+    let (metadata, fetched_at) = fetch_cached(&token_uri, refresh)?;
+    let errors = validate(&metadata);
+    let image_url = metadata
+        .get("image")
+        .or_else(|| metadata.get("image_url"))
+        .and_then(Value::as_str)
+        .filter(|image| !image.is_empty())
+        .map(resolve_uri);
+    let mut media_uris: Vec<&str> = Vec::new();
+    for field in &["image", "image_url", "animation_url"] {
+        if let Some(uri) = metadata.get(*field).and_then(Value::as_str) {
+            if !uri.is_empty() && !media_uris.contains(&uri) {
+                media_uris.push(uri);
+            }
+        }
+    }
+    let media = media_uris
+        .into_iter()
+        .map(|uri| media::process(uri, refresh))
+        .collect();
+    Ok(TokenMetadata {
+        contract_address,
+        token_id,
+        standard,
+        token_uri,
+        metadata,
+        image_url,
+        media,
+        valid: errors.is_empty(),
+        errors,
+        fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn urls_resolving_to_internal_addresses_are_refused() {
+        let url = Url::parse("http://127.0.0.1:8080/metadata.json").unwrap();
+        assert!(public_addrs(&url).is_err());
+        let url = Url::parse("http://[::1]/metadata.json").unwrap();
+        assert!(public_addrs(&url).is_err());
+    }
+
+    #[test]
+    fn erc1155_ids_are_expanded_to_64_hex_digits() {
+        let uri = expand_id("https://example.com/{id}.json", U256::from(314592));
+        assert_eq!(
+            uri,
+            "https://example.com/000000000000000000000000000000000000000000000000000000000004cce0.json"
+        );
+        assert_eq!(
+            expand_id("ipfs://abc/1.json", U256::one()),
+            "ipfs://abc/1.json"
+        );
+    }
+
+    #[test]
+    fn data_uris_are_decoded() {
+        let (media_type, bytes) = decode_data_uri("application/json;base64,eyJhIjoxfQ==").unwrap();
+        assert_eq!(media_type, "application/json");
+        assert_eq!(bytes, br#"{"a":1}"#);
+
+        let (media_type, bytes) = decode_data_uri("application/json,%7B%22a%22%3A1%7D").unwrap();
+        assert_eq!(media_type, "application/json");
+        assert_eq!(bytes, br#"{"a":1}"#);
+
+        let (media_type, bytes) = decode_data_uri(",100%").unwrap();
+        assert_eq!(media_type, "");
+        assert_eq!(bytes, b"100%");
+
+        assert!(decode_data_uri("application/json").is_err());
+        assert!(decode_data_uri("image/png;base64,!!!").is_err());
+    }
+
+    #[test]
+    fn valid_metadata_has_no_errors() {
+        let document = json!({
+            "name": "Token",
+            "description": "A token",
+            "image": "ipfs://abc",
+            "external_url": "",
+            "background_color": "ffffff",
+            "attributes": [
+                { "trait_type": "Level", "value": 5, "display_type": "number", "max_value": 10 },
+                { "value": "plain" },
+            ],
+        });
+        assert!(validate(&document).is_empty());
+    }
+
+    #[test]
+    fn malformed_metadata_is_reported() {
+        let document = json!({
+            "name": 1,
+            "image": "/relative.png",
+            "background_color": "#ffffff",
+            "attributes": [
+                "level",
+                { "trait_type": "Level" },
+                { "value": "high", "display_type": "number" },
+                { "value": 1, "display_type": "stars" },
+            ],
+        });
+        let errors = validate(&document);
+        assert_eq!(
+            errors,
+            vec![
+                "name is not a string",
+                "image is not a URI",
+                "background_color is not six hex digits without a leading #",
+                "attributes[0] is not an object",
+                "attributes[1] has no value",
+                "attributes[2] has display_type number but a non-numeric value",
+                "attributes[3].display_type is not supported",
+            ]
+        );
+        assert_eq!(validate(&json!([])), vec!["metadata is not a JSON object"]);
+    }
+}
@@ -0,0 +1,320 @@
+use crate::currency::Currency;
+use crate::market::{
+    claim_offer, execute, list_listings, list_offers, now, release_offer, reserve, Fill, Listing,
+    MarketError, Offer, OfferStatus,
+};
+use futures::executor::block_on;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+use web3::types::{H160, U256};
+
+const MATCH_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Ask {
+    pub listing_id: u64,
+    pub seller: H160,
+    pub token_ids: Vec<U256>,
+    pub currency: Currency,
+    pub price: U256,
+    pub price_formatted: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Bid {
+    pub offer_id: u64,
+    pub buyer: H160,
+    pub token_id: U256,
+    pub currency: Currency,
+    pub price: U256,
+    pub price_formatted: String,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderBook {
+    pub contract_address: H160,
+    pub token_id: Option<U256>,
+    pub asks: Vec<Ask>,
+    pub bids: Vec<Bid>,
+}
+
+impl From<&Listing> for Ask {
+    fn from(listing: &Listing) -> Ask {
+        Ask {
+            listing_id: listing.id,
+            seller: listing.seller,
+            token_ids: listing.token_ids.clone(),
+            currency: listing.currency.clone(),
+            price: listing.current_price,
+            price_formatted: listing.current_price_formatted.clone(),
+        }
+    }
+}
+
+impl From<&Offer> for Bid {
+    fn from(offer: &Offer) -> Bid {
+        Bid {
+            offer_id: offer.id,
+            buyer: offer.buyer,
+            token_id: offer.token_id,
+            currency: offer.currency.clone(),
+            price: offer.price,
+            price_formatted: offer.price_formatted.clone(),
+            expires_at: offer.expires_at,
+        }
+    }
+}
+
+fn open_asks(contract_address: H160, token_id: Option<U256>, currency: Option<&Currency>) -> Vec<Ask> {
+    let time = now();
+    let mut asks: Vec<Ask> = list_listings()
+        .iter()
+        .filter(|listing| listing.contract_address == contract_address && listing.is_open(time))
+        .filter(|listing| token_id.map_or(true, |token_id| listing.token_ids.contains(&token_id)))
+        .filter(|listing| currency.map_or(true, |currency| listing.currency == *currency))
+        .map(Ask::from)
+        .collect();
+    asks.sort_by(|a, b| a.price.cmp(&b.price).then(a.listing_id.cmp(&b.listing_id)));
+    asks
+}
+
+fn open_bids(contract_address: H160, token_id: Option<U256>, currency: Option<&Currency>) -> Vec<Bid> {
+    let mut bids: Vec<Bid> = list_offers()
+        .iter()
+        .filter(|offer| offer.contract_address == contract_address)
+        .filter(|offer| offer.status == OfferStatus::Active)
+        .filter(|offer| token_id.map_or(true, |token_id| offer.token_id == token_id))
+        .filter(|offer| currency.map_or(true, |currency| offer.currency == *currency))
+        .map(Bid::from)
+        .collect();
+    bids.sort_by(|a, b| b.price.cmp(&a.price).then(a.offer_id.cmp(&b.offer_id)));
+    bids
+}
+
+// Prices in different currencies don't compare, so best prices are reported per currency
+fn best_per_currency<T, F>(orders: Vec<T>, currency_of: F) -> Vec<T>
+where
+    F: Fn(&T) -> &Currency,
+{
+    let mut seen: Vec<Currency> = Vec::new();
+    let mut best = Vec::new();
+    for order in orders {
+        if !seen.contains(currency_of(&order)) {
+            seen.push(currency_of(&order).clone());
+            best.push(order);
+        }
+    }
+    best
+}
+
+pub fn order_book(
+    contract_address: H160,
+    token_id: Option<U256>,
+    currency: Option<&Currency>,
+) -> OrderBook {
+    OrderBook {
+        contract_address,
+        token_id,
+        asks: open_asks(contract_address, token_id, currency),
+        bids: open_bids(contract_address, token_id, currency),
+    }
+}
+
+pub fn floor(contract_address: H160, currency: Option<&Currency>) -> Vec<Ask> {
+    best_per_currency(open_asks(contract_address, None, currency), |ask| {
+        &ask.currency
+    })
+}
+
+pub fn best_offer(contract_address: H160, token_id: U256, currency: Option<&Currency>) -> Vec<Bid> {
+    best_per_currency(
+        open_bids(contract_address, Some(token_id), currency),
+        |bid| &bid.currency,
+    )
+}
+
+struct Cross {
+    listing_id: u64,
+    offer_id: u64,
+    buyer: H160,
+    token_id: U256,
+    price: U256,
+}
+
+// Finds the highest offer that crosses an open listing. The order that was
+// resting first sets the price.
+fn find_cross(skip: &HashSet<u64>) -> Option<Cross> {
+    let time = now();
+    let listings: Vec<Listing> = list_listings()
+        .into_iter()
+        .filter(|listing| listing.is_open(time))
+        .collect();
+    let offers: Vec<Offer> = list_offers()
+        .into_iter()
+        .filter(|offer| offer.status == OfferStatus::Active && !skip.contains(&offer.id))
+        .collect();
+    cross(&listings, offers)
+}
+
+fn cross(listings: &[Listing], mut offers: Vec<Offer>) -> Option<Cross> {
+    offers.sort_by(|a, b| b.price.cmp(&a.price).then(a.id.cmp(&b.id)));
+
+    for offer in offers {
+        let listing = listings.iter().find(|listing| {
+            listing.contract_address == offer.contract_address
+                && listing.currency == offer.currency
+                && listing.seller != offer.buyer
+                && listing.token_ids.contains(&offer.token_id)
+                && listing.current_price <= offer.price
+        });
+        if let Some(listing) = listing {
+            let price = if offer.created_at < listing.created_at {
+                offer.price
+            } else {
+                listing.current_price
+            };
+            return Some(Cross {
+                listing_id: listing.id,
+                offer_id: offer.id,
+                buyer: offer.buyer,
+                token_id: offer.token_id,
+                price,
+            });
+        }
+    }
+    None
+}
+
+pub async fn match_orders() -> Vec<Fill> {
+    let mut fills = Vec::new();
+    let mut tried = HashSet::new();
+    while let Some(cross) = find_cross(&tried) {
+        tried.insert(cross.offer_id);
+        if !claim_offer(cross.offer_id, cross.listing_id) {
+            continue;
+        }
+        let reservation = match reserve(cross.listing_id, Some(cross.token_id)) {
+            Ok(reservation) => reservation,
+            Err(e) => {
+                release_offer(cross.offer_id, false, Some(format!("{:?}", e)));
+                continue;
+            }
+        };
+        // The buyer signed the offer when placing it, and ERC-20 amounts are
+        // pulled by the operator, so no buyer key is needed
+        match execute(reservation, cross.buyer, None, cross.price, Some(cross.offer_id)).await {
+            Ok(fill) => {
+                release_offer(cross.offer_id, true, fill.receipt.error.clone());
+                fills.push(fill);
+            }
+            // The buyer has paid, so the offer must not be matched again
+            Err(MarketError::Settlement(fill)) => {
+                release_offer(cross.offer_id, true, fill.receipt.error)
+            }
+            Err(e) => release_offer(cross.offer_id, false, Some(format!("{:?}", e))),
+        }
+    }
+    fills
+}
+
+// Runs a matching pass right away, e.g. after a new order was placed
+pub fn trigger_match() {
+    thread::spawn(|| block_on(match_orders()));
+}
+
+pub fn spawn_matcher() {
+    thread::spawn(|| loop {
+        block_on(match_orders());
+        thread::sleep(MATCH_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::{FixedPrice, ListingKind, ListingStatus};
+
+    fn listing(id: u64, price: u64, created_at: u64) -> Listing {
+        Listing {
+            id,
+            contract_address: H160::repeat_byte(1),
+            seller: H160::repeat_byte(2),
+            kind: ListingKind::FixedPrice(FixedPrice {
+                price: U256::from(price),
+                expires_at: None,
+            }),
+            token_ids: vec![U256::from(7)],
+            pending: Vec::new(),
+            fills: Vec::new(),
+            failed: Vec::new(),
+            status: ListingStatus::Active,
+            currency: Currency::eth(),
+            current_price: U256::from(price),
+            current_price_formatted: String::new(),
+            clearing_price: None,
+            created_at,
+            order_hash: None,
+        }
+    }
+
+    fn offer(id: u64, price: u64, created_at: u64) -> Offer {
+        Offer {
+            id,
+            contract_address: H160::repeat_byte(1),
+            token_id: U256::from(7),
+            buyer: H160::repeat_byte(3),
+            currency: Currency::eth(),
+            price: U256::from(price),
+            price_formatted: String::new(),
+            expires_at: None,
+            status: OfferStatus::Active,
+            listing_id: None,
+            error: None,
+            created_at,
+            order_hash: None,
+        }
+    }
+
+    #[test]
+    fn the_highest_crossing_offer_is_matched() {
+        let listings = [listing(1, 100, 10)];
+        let offers = vec![offer(1, 90, 20), offer(2, 120, 20), offer(3, 110, 20)];
+        let cross = cross(&listings, offers).unwrap();
+        assert_eq!((cross.listing_id, cross.offer_id), (1, 2));
+        assert_eq!(cross.buyer, H160::repeat_byte(3));
+    }
+
+    #[test]
+    fn the_resting_order_sets_the_price() {
+        let listings = [listing(1, 100, 10)];
+        let cross_price = |created_at| {
+            cross(&listings, vec![offer(1, 120, created_at)])
+                .unwrap()
+                .price
+        };
+        assert_eq!(cross_price(20), U256::from(100));
+        assert_eq!(cross_price(5), U256::from(120));
+    }
+
+    #[test]
+    fn offers_that_dont_cross_are_left() {
+        let listings = [listing(1, 100, 10)];
+        assert!(cross(&listings, vec![offer(1, 99, 20)]).is_none());
+
+        let mut own = offer(2, 120, 20);
+        own.buyer = listings[0].seller;
+        assert!(cross(&listings, vec![own]).is_none());
+
+        let mut other_token = offer(3, 120, 20);
+        other_token.token_id = U256::from(8);
+        assert!(cross(&listings, vec![other_token]).is_none());
+
+        let mut other_currency = offer(4, 120, 20);
+        other_currency.currency.symbol = "WETH".into();
+        other_currency.currency.address = Some(H160::repeat_byte(9));
+        assert!(cross(&listings, vec![other_currency]).is_none());
+    }
+}
@@ -0,0 +1,345 @@
+use crate::collections::standard;
+use crate::config::Config;
+use crate::eth::{code_at, eth_call, CallOutcome};
+use crate::jobs::JobRequest;
+use crate::metadata::TokenStandard;
+use crate::types::BurnRequest;
+use base64::decode as decode_base64;
+use web3::ethabi::{decode, encode, ParamType, Token};
+use web3::signing::keccak256;
+use web3::types::{H160, U256};
+
+// IERC721Receiver.onERC721Received.selector, returned by a willing recipient
+const ERC721_RECEIVED: [u8; 4] = [0x15, 0x0b, 0x7a, 0x02];
+
+#[derive(Debug)]
+pub enum PreflightError {
+    // ownerOf reverted, so the token doesn't exist
+    NotFound(String),
+    // `from` isn't the owner
+    NotOwner(String),
+    // The signing account is neither the owner nor approved
+    NotAuthorized(String),
+    // The recipient would make the transfer revert
+    BadRecipient(String),
+    Invalid(String),
+    Chain(String),
+}
+
+// The return data, or the revert reason
+async fn call(
+    contract_address: H160,
+    from: Option<H160>,
+    signature: &str,
+    args: &[Token],
+) -> Result<Result<Vec<u8>, String>, PreflightError> {
+    let mut data = keccak256(signature.as_bytes())[..4].to_vec();
+    data.extend(encode(args));
+    match eth_call(contract_address, from, data, None)
+        .await
+        .map_err(PreflightError::Chain)?
+    {
+        CallOutcome::Returned(output) => Ok(Ok(output)),
+        CallOutcome::Reverted(reason) => Ok(Err(reason)),
+    }
+}
+
+fn output(kind: ParamType, output: &[u8], signature: &str) -> Result<Token, PreflightError> {
+    decode(&[kind], output)
+        .ok()
+        .and_then(|tokens| tokens.into_iter().next())
+        .ok_or_else(|| PreflightError::Chain(format!("unexpected output from {}", signature)))
+}
+
+async fn owner_of(contract_address: H160, token_id: U256) -> Result<H160, PreflightError> {
+    let signature = "ownerOf(uint256)";
+    match call(contract_address, None, signature, &[Token::Uint(token_id)]).await? {
+        Ok(data) => match output(ParamType::Address, &data, signature)? {
+            Token::Address(owner) => Ok(owner),
+            _ => unreachable!("decoded as an address"),
+        },
+        Err(_) => Err(PreflightError::NotFound(format!(
+            "token {} doesn't exist",
+            token_id
+        ))),
+    }
+}
+
+// Owner, approved for the token, or an operator of the owner
+async fn may_transfer(
+    contract_address: H160,
+    owner: H160,
+    signer: H160,
+    token_id: U256,
+) -> Result<bool, PreflightError> {
+    if signer == owner {
+        return Ok(true);
+    }
+    let signature = "getApproved(uint256)";
+    let approved = call(contract_address, None, signature, &[Token::Uint(token_id)]).await?;
+    if let Ok(data) = approved {
+        if output(ParamType::Address, &data, signature)? == Token::Address(signer) {
+            return Ok(true);
+        }
+    }
+    is_approved_for_all(contract_address, owner, signer).await
+}
+
+// A contract recipient of a safe transfer must return the receiver selector
+// from onERC721Received. The hook is called the way the token contract would
+// call it, with the token contract as the sender.
+async fn check_receiver(
+    contract_address: H160,
+    operator: H160,
+    from: H160,
+    to: H160,
+    token_id: U256,
+    data: Vec<u8>,
+) -> Result<(), PreflightError> {
+    if code_at(to).await.map_err(PreflightError::Chain)?.is_empty() {
+        return Ok(());
+    }
+    let args = [
+        Token::Address(operator),
+        Token::Address(from),
+        Token::Uint(token_id),
+        Token::Bytes(data),
+    ];
+    let signature = "onERC721Received(address,address,uint256,bytes)";
+    match call(to, Some(contract_address), signature, &args).await? {
+        Ok(output) if output.len() >= 32 && output[..4] == ERC721_RECEIVED => Ok(()),
+        Ok(_) => Err(PreflightError::BadRecipient(format!(
+            "{:?} is a contract that doesn't implement IERC721Receiver",
+            to
+        ))),
+        Err(reason) => Err(PreflightError::BadRecipient(format!(
+            "{:?} rejects the token: {}",
+            to, reason
+        ))),
+    }
+}
+
+async fn is_approved_for_all(
+    contract_address: H160,
+    owner: H160,
+    operator: H160,
+) -> Result<bool, PreflightError> {
+    let signature = "isApprovedForAll(address,address)";
+    let args = [Token::Address(owner), Token::Address(operator)];
+    match call(contract_address, None, signature, &args).await? {
+        Ok(data) => Ok(output(ParamType::Bool, &data, signature)? == Token::Bool(true)),
+        Err(_) => Ok(false),
+    }
+}
+
+// burn() on either standard reverts unless the signer holds the tokens or
+// operates for whoever does
+async fn check_burn(
+    contract_address: H160,
+    signer: H160,
+    request: &BurnRequest,
+) -> Result<(), PreflightError> {
+    let token_id = request.token_id;
+    let owner = match standard(contract_address) {
+        TokenStandard::Erc721 => {
+            let owner = owner_of(contract_address, token_id).await?;
+            if let Some(expected) = request.owner {
+                if owner != expected {
+                    return Err(PreflightError::NotOwner(format!(
+                        "token {} is owned by {:?}, not {:?}",
+                        token_id, owner, expected
+                    )));
+                }
+            }
+            if !may_transfer(contract_address, owner, signer, token_id).await? {
+                return Err(PreflightError::NotAuthorized(format!(
+                    "{:?} is neither the owner of token {} nor approved for it",
+                    signer, token_id
+                )));
+            }
+            return Ok(());
+        }
+        TokenStandard::Erc1155 => request.owner.unwrap_or(signer),
+    };
+    let amount = request.amount.unwrap_or_else(U256::one);
+    let signature = "balanceOf(address,uint256)";
+    let args = [Token::Address(owner), Token::Uint(token_id)];
+    let balance = match call(contract_address, None, signature, &args).await? {
+        Ok(data) => output(ParamType::Uint(256), &data, signature)?
+            .into_uint()
+            .unwrap_or_default(),
+        Err(reason) => return Err(PreflightError::Chain(reason)),
+    };
+    if balance < amount {
+        return Err(PreflightError::NotOwner(format!(
+            "{:?} holds {} of token {}, not {}",
+            owner, balance, token_id, amount
+        )));
+    }
+    if signer != owner && !is_approved_for_all(contract_address, owner, signer).await? {
+        return Err(PreflightError::NotAuthorized(format!(
+            "{:?} is not an operator for {:?}",
+            signer, owner
+        )));
+    }
+    Ok(())
+}
+
+// Jobs sign with the configured account
+fn signer() -> Result<H160, PreflightError> {
+    Config::get_my_account().map_err(|e| PreflightError::Chain(e.to_string()))
+}
+
+// From, to, token and, for a safe transfer, the data passed to the receiver
+type Transfer = (H160, H160, U256, Option<Vec<u8>>);
+
+// The transfer a request makes, checked as far as it can be without the chain
+fn transfer(request: &JobRequest) -> Result<Option<Transfer>, PreflightError> {
+    let transfer = match request {
+        JobRequest::TransferFrom(data) => (data.from, data.to, data.token_id, None),
+        JobRequest::SafeTransferFrom(data) => (data.from, data.to, data.token_id, Some(Vec::new())),
+        JobRequest::SafeTransferFromData(data) => {
+            let bytes =
+                decode_base64(&data.data).map_err(|e| PreflightError::Invalid(e.to_string()))?;
+            (data.from, data.to, data.token_id, Some(bytes))
+        }
+        _ => return Ok(None),
+    };
+    if transfer.1 == H160::zero() {
+        return Err(PreflightError::BadRecipient(
+            "transfers to the zero address revert".into(),
+        ));
+    }
+    Ok(Some(transfer))
+}
+
+// Checks a transfer or burn against the chain before it is queued, so one
+// that would revert is refused instead of spending gas. Other requests pass.
+pub async fn check(contract_address: H160, request: &JobRequest) -> Result<(), PreflightError> {
+    if let JobRequest::Burn(data) = request {
+        return check_burn(contract_address, signer()?, data).await;
+    }
+    let (from, to, token_id, safe_data) = match transfer(request)? {
+        Some(transfer) => transfer,
+        None => return Ok(()),
+    };
+    let signer = signer()?;
+    let owner = owner_of(contract_address, token_id).await?;
+    if owner != from {
+        return Err(PreflightError::NotOwner(format!(
+            "token {} is owned by {:?}, not {:?}",
+            token_id, owner, from
+        )));
+    }
+    if !may_transfer(contract_address, owner, signer, token_id).await? {
+        return Err(PreflightError::NotAuthorized(format!(
+            "{:?} is neither the owner of token {} nor approved for it",
+            signer, token_id
+        )));
+    }
+    if let Some(data) = safe_data {
+        check_receiver(contract_address, signer, from, to, token_id, data).await?;
+    }
+    Ok(())
+}
+
+// A listing can only be filled if the seller owns every token and the
+// operator, which transfers them at settlement, is approved for each
+pub async fn check_listing(
+    contract_address: H160,
+    seller: H160,
+    token_ids: &[U256],
+) -> Result<(), PreflightError> {
+    let operator = signer()?;
+    for &token_id in token_ids {
+        let owner = owner_of(contract_address, token_id).await?;
+        if owner != seller {
+            return Err(PreflightError::NotOwner(format!(
+                "token {} is owned by {:?}, not {:?}",
+                token_id, owner, seller
+            )));
+        }
+        if !may_transfer(contract_address, owner, operator, token_id).await? {
+            return Err(PreflightError::NotAuthorized(format!(
+                "{:?} hasn't approved the operator {:?} for token {}",
+                seller, operator, token_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{TransferFormDataResponse, TransferFormResponse, TransferFromResponse};
+
+    fn safe_transfer(to: H160, data: &str) -> JobRequest {
+        JobRequest::SafeTransferFromData(TransferFormDataResponse {
+            private_key: String::new(),
+            from: H160::repeat_byte(1),
+            to,
+            token_id: U256::from(7),
+            data: data.to_string(),
+        })
+    }
+
+    #[test]
+    fn transfers_to_the_zero_address_are_refused() {
+        let request = JobRequest::TransferFrom(TransferFromResponse {
+            private_key: String::new(),
+            from: H160::repeat_byte(1),
+            to: H160::zero(),
+            token_id: U256::from(7),
+        });
+        assert!(matches!(
+            transfer(&request),
+            Err(PreflightError::BadRecipient(_))
+        ));
+        assert!(matches!(
+            transfer(&safe_transfer(H160::zero(), "")),
+            Err(PreflightError::BadRecipient(_))
+        ));
+    }
+
+    #[test]
+    fn safe_transfers_carry_their_data() {
+        let request = JobRequest::SafeTransferFrom(TransferFormResponse {
+            private_key: String::new(),
+            from: H160::repeat_byte(1),
+            to: H160::repeat_byte(2),
+            token_id: U256::from(7),
+        });
+        let (from, to, token_id, data) = transfer(&request).unwrap().unwrap();
+        assert_eq!(
+            (from, to, token_id),
+            (H160::repeat_byte(1), H160::repeat_byte(2), 7.into())
+        );
+        assert_eq!(data, Some(Vec::new()));
+
+        let (_, _, _, data) = transfer(&safe_transfer(H160::repeat_byte(2), "AQID"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(data, Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn undecodable_data_is_invalid() {
+        let request = safe_transfer(H160::repeat_byte(2), "not base64!");
+        assert!(matches!(
+            transfer(&request),
+            Err(PreflightError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn other_requests_pass() {
+        let request = JobRequest::Burn(BurnRequest {
+            private_key: String::new(),
+            token_id: U256::from(7),
+            owner: None,
+            amount: None,
+        });
+        assert!(transfer(&request).unwrap().is_none());
+    }
+}
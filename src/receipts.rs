@@ -0,0 +1,161 @@
+use crate::config::Config;
+use crate::eth::transaction_receipt;
+use crate::market::now;
+use crate::storage;
+use crate::stream::{self, Subject};
+use crate::webhooks;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use web3::types::{TransactionReceipt, H256};
+
+lazy_static! {
+    static ref TRACKED: Mutex<HashMap<H256, TrackedTx>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxState {
+    Pending,
+    Included,
+    Final,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TrackedTx {
+    pub transaction_hash: H256,
+    pub state: TxState,
+    pub block_number: Option<u64>,
+    pub block_hash: Option<H256>,
+    pub success: Option<bool>,
+    pub confirmations: u64,
+    pub reorgs: u32,
+    pub updated_at: u64,
+}
+
+impl TrackedTx {
+    fn update(&mut self, receipt: Option<&TransactionReceipt>, head: Option<u64>) {
+        let was_final = self.state == TxState::Final;
+        let block = receipt.and_then(|receipt| {
+            Some((receipt.block_number?.as_u64(), receipt.block_hash?))
+        });
+        match block {
+            Some((block_number, block_hash)) => {
+                if self.block_hash.map_or(false, |hash| hash != block_hash) {
+                    self.reorgs += 1;
+                }
+                self.block_number = Some(block_number);
+                self.block_hash = Some(block_hash);
+                self.success = receipt
+                    .and_then(|receipt| receipt.status)
+                    .map(|status| !status.is_zero());
+                self.confirmations = head
+                    .map_or(1, |head| (head + 1).saturating_sub(block_number))
+                    .max(1);
+                self.state = if self.confirmations >= Config::get_confirmations() {
+                    TxState::Final
+                } else {
+                    TxState::Included
+                };
+            }
+            None => self.reorg(),
+        }
+        self.updated_at = now();
+        if !was_final && self.state == TxState::Final {
+            webhooks::publish("transaction.final", self);
+        }
+    }
+
+    // Every change is saved and streamed to clients following the transaction
+    fn save(&self) {
+        if let Err(e) = storage::with(|storage| storage.upsert_transaction(self)) {
+            eprintln!("Error: failed to save transaction {:?}: {}", self.transaction_hash, e);
+        }
+        let subject = Subject {
+            transaction_hash: Some(self.transaction_hash),
+            ..Subject::default()
+        };
+        stream::publish("transaction", subject, self);
+    }
+
+    fn reorg(&mut self) {
+        if self.block_hash.is_some() {
+            self.reorgs += 1;
+        }
+        self.state = TxState::Pending;
+        self.block_number = None;
+        self.block_hash = None;
+        self.success = None;
+        self.confirmations = 0;
+    }
+}
+
+// Starts tracking a transaction from its first receipt. It is only reported as
+// final once `confirmations` blocks have been built on top of it.
+pub fn track(receipt: &TransactionReceipt) {
+    let mut tracked = TRACKED.lock().unwrap();
+    let tx = tracked
+        .entry(receipt.transaction_hash)
+        .or_insert_with(|| TrackedTx {
+            transaction_hash: receipt.transaction_hash,
+            state: TxState::Pending,
+            block_number: None,
+            block_hash: None,
+            success: None,
+            confirmations: 0,
+            reorgs: 0,
+            updated_at: now(),
+        });
+    tx.update(Some(receipt), None);
+    tx.save();
+}
+
+// Moves every transaction mined after `fork_block` back to pending, or all of
+// them when the fork point is unknown. The next refresh picks up their new blocks.
+pub fn rollback(fork_block: Option<u64>) {
+    let mut tracked = TRACKED.lock().unwrap();
+    for tx in tracked.values_mut() {
+        let reorged = match (tx.block_number, fork_block) {
+            (Some(block_number), Some(fork_block)) => block_number > fork_block,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if reorged {
+            tx.reorg();
+            tx.updated_at = now();
+            tx.save();
+        }
+    }
+}
+
+pub async fn refresh(head: u64) -> Result<(), String> {
+    let unsettled: Vec<H256> = TRACKED
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|tx| tx.state != TxState::Final)
+        .map(|tx| tx.transaction_hash)
+        .collect();
+    for tx_hash in unsettled {
+        let receipt = transaction_receipt(tx_hash).await?;
+        if let Some(tx) = TRACKED.lock().unwrap().get_mut(&tx_hash) {
+            tx.update(receipt.as_ref(), Some(head));
+            tx.save();
+        }
+    }
+    Ok(())
+}
+
+pub fn load() -> Result<(), String> {
+    let transactions = storage::with(|storage| storage.transactions())?;
+    let mut tracked = TRACKED.lock().unwrap();
+    for tx in transactions {
+        tracked.insert(tx.transaction_hash, tx);
+    }
+    Ok(())
+}
+
+pub fn get_transaction(tx_hash: H256) -> Option<TrackedTx> {
+    TRACKED.lock().unwrap().get(&tx_hash).cloned()
+}
@@ -0,0 +1,226 @@
+use crate::collections;
+use crate::config::Config;
+use crate::currency::Currency;
+use crate::eth::{
+    erc20_allowance, erc20_balance_of, erc20_transfer_from, royalty_info, safe_transfer_from,
+    send_value,
+};
+use serde::{Deserialize, Serialize};
+use web3::types::{TransactionReceipt, H160, H256, U256};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SaleReceipt {
+    pub currency: Currency,
+    pub price: U256,
+    pub royalty_receiver: Option<H160>,
+    pub royalty: U256,
+    pub fee_recipient: Option<H160>,
+    pub fee: U256,
+    pub seller_proceeds: U256,
+    pub payment_tx: Option<H256>,
+    pub royalty_tx: Option<H256>,
+    pub fee_tx: Option<H256>,
+    pub transfer_tx: Option<H256>,
+    pub error: Option<String>,
+}
+
+impl SaleReceipt {
+    fn note_error(&mut self, error: String) {
+        self.error = Some(match self.error.take() {
+            Some(previous) => format!("{}; {}", previous, error),
+            None => error,
+        });
+    }
+}
+
+pub async fn quote(
+    contract_address: H160,
+    token_id: U256,
+    currency: &Currency,
+    price: U256,
+) -> Result<SaleReceipt, String> {
+    let royalty = royalty_info(contract_address, token_id, price).await?;
+    let (royalty_receiver, royalty) = match royalty {
+        Some((receiver, amount)) if !amount.is_zero() && !receiver.is_zero() => {
+            (Some(receiver), amount)
+        }
+        _ => (None, U256::zero()),
+    };
+
+    let fee = price
+        .checked_mul(U256::from(collections::platform_fee(contract_address)))
+        .ok_or_else(|| format!("sale price {} is too large", price))?
+        / U256::from(10000);
+    let fee_recipient = if fee.is_zero() {
+        None
+    } else {
+        Some(Config::get_fee_recipient().map_err(|e| e.to_string())?)
+    };
+    // Royalty amounts come from the token contract and may be anything
+    if royalty.checked_add(fee).map_or(true, |total| total > price) {
+        return Err(format!(
+            "royalty {} and fee {} exceed sale price {}",
+            royalty, fee, price
+        ));
+    }
+
+    Ok(SaleReceipt {
+        currency: currency.clone(),
+        price,
+        royalty_receiver,
+        royalty,
+        fee_recipient,
+        fee,
+        seller_proceeds: price - royalty - fee,
+        payment_tx: None,
+        royalty_tx: None,
+        fee_tx: None,
+        transfer_tx: None,
+        error: None,
+    })
+}
+
+fn reverted(receipt: &TransactionReceipt) -> bool {
+    receipt.status.map_or(false, |status| status.is_zero())
+}
+
+// ETH is sent by the buyer directly; ERC-20 amounts are pulled from the buyer by
+// the operator account, which the buyer must have approved beforehand. A
+// payment that was mined but reverted is an error.
+async fn pay(
+    currency: &Currency,
+    buyer: H160,
+    buyer_private_key: Option<&str>,
+    to: H160,
+    amount: U256,
+) -> Result<TransactionReceipt, String> {
+    let receipt = match currency.address {
+        None => {
+            let buyer_private_key =
+                buyer_private_key.ok_or("ETH payments are signed by the buyer")?;
+            send_value(buyer, buyer_private_key, to, amount).await?
+        }
+        Some(token_address) => {
+            let operator = Config::get_my_account().map_err(|e| e.to_string())?;
+            erc20_transfer_from(
+                token_address,
+                operator,
+                &Config::get_my_private_key(),
+                buyer,
+                to,
+                amount,
+            )
+            .await?
+        }
+    };
+    if reverted(&receipt) {
+        return Err(format!("payment {:?} reverted", receipt.transaction_hash));
+    }
+    Ok(receipt)
+}
+
+// Checks the buyer can cover the price before anything is sent, so an ERC-20
+// settlement doesn't fail halfway through on balance or allowance.
+async fn check_funds(currency: &Currency, buyer: H160, price: U256) -> Result<(), String> {
+    let token_address = match currency.address {
+        Some(token_address) => token_address,
+        None => return Ok(()),
+    };
+    let operator = Config::get_my_account().map_err(|e| e.to_string())?;
+    let balance = erc20_balance_of(token_address, buyer).await?;
+    if balance < price {
+        return Err(format!(
+            "buyer balance {} is below price {}",
+            currency.format(balance),
+            currency.format(price)
+        ));
+    }
+    let allowance = erc20_allowance(token_address, buyer, operator).await?;
+    if allowance < price {
+        return Err(format!(
+            "buyer allowance {} is below price {}",
+            currency.format(allowance),
+            currency.format(price)
+        ));
+    }
+    Ok(())
+}
+
+// Pays the seller, royalty receiver and platform from the buyer, then moves the token
+// with the operator account. Returns Err only when no funds have moved yet. The
+// token stays with the seller unless every payment went through.
+pub async fn settle(
+    contract_address: H160,
+    seller: H160,
+    buyer: H160,
+    buyer_private_key: Option<&str>,
+    token_id: U256,
+    mut receipt: SaleReceipt,
+) -> Result<SaleReceipt, String> {
+    let currency = receipt.currency.clone();
+    check_funds(&currency, buyer, receipt.price).await?;
+
+    let payment = pay(
+        &currency,
+        buyer,
+        buyer_private_key,
+        seller,
+        receipt.seller_proceeds,
+    )
+    .await?;
+    receipt.payment_tx = Some(payment.transaction_hash);
+
+    if let Some(royalty_receiver) = receipt.royalty_receiver {
+        match pay(
+            &currency,
+            buyer,
+            buyer_private_key,
+            royalty_receiver,
+            receipt.royalty,
+        )
+        .await
+        {
+            Ok(royalty) => receipt.royalty_tx = Some(royalty.transaction_hash),
+            Err(e) => {
+                receipt.note_error(format!("royalty payment failed: {}", e));
+                return Ok(receipt);
+            }
+        }
+    }
+
+    if let Some(fee_recipient) = receipt.fee_recipient {
+        match pay(&currency, buyer, buyer_private_key, fee_recipient, receipt.fee).await {
+            Ok(fee) => receipt.fee_tx = Some(fee.transaction_hash),
+            Err(e) => {
+                receipt.note_error(format!("platform fee payment failed: {}", e));
+                return Ok(receipt);
+            }
+        }
+    }
+
+    let operator = Config::get_my_account().map_err(|e| e.to_string());
+    let transfer = match operator {
+        Ok(operator) => {
+            safe_transfer_from(
+                contract_address,
+                operator,
+                &Config::get_my_private_key(),
+                seller,
+                buyer,
+                token_id,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    match transfer {
+        Ok(transfer) if reverted(&transfer) => receipt.note_error(format!(
+            "token transfer {:?} reverted",
+            transfer.transaction_hash
+        )),
+        Ok(transfer) => receipt.transfer_tx = Some(transfer.transaction_hash),
+        Err(e) => receipt.note_error(format!("token transfer failed: {}", e)),
+    }
+
+    Ok(receipt)
+}
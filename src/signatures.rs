@@ -0,0 +1,151 @@
+use crate::market::PriceCurve;
+use crate::types::{DutchAuctionResponse, FixedPriceResponse, OfferResponse};
+use web3::signing::{keccak256, recover};
+use web3::types::{H160, H256, U256};
+
+// Orders are signed with personal_sign (EIP-191) over a plain text message:
+// a title line, then one `name: value` line per term, with the amounts and
+// currency exactly as sent in the request and addresses in lowercase hex.
+
+pub fn message_hash(message: &str) -> H256 {
+    let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    data.extend_from_slice(message.as_bytes());
+    H256::from(keccak256(&data))
+}
+
+// The address that signed `message`, from 65 bytes of r, s and v in hex
+pub fn signer(message: &str, signature: &str) -> Result<H160, String> {
+    let bytes = hex::decode(signature.trim_start_matches("0x"))
+        .map_err(|e| format!("invalid signature: {}", e))?;
+    if bytes.len() != 65 {
+        return Err(format!("a signature is 65 bytes, got {}", bytes.len()));
+    }
+    let recovery_id = match bytes[64] {
+        0 | 1 => bytes[64],
+        27 | 28 => bytes[64] - 27,
+        v => return Err(format!("invalid signature v {}", v)),
+    };
+    recover(
+        message_hash(message).as_bytes(),
+        &bytes[..64],
+        recovery_id as i32,
+    )
+    .map_err(|_| "invalid signature".to_string())
+}
+
+pub fn verify(address: H160, message: &str, signature: &str) -> Result<(), String> {
+    let signer = signer(message, signature)?;
+    if signer != address {
+        return Err(format!(
+            "the order is signed by {:?}, not {:?}",
+            signer, address
+        ));
+    }
+    Ok(())
+}
+
+fn token_ids(token_ids: &[U256]) -> String {
+    token_ids
+        .iter()
+        .map(U256::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn optional<T: ToString>(value: Option<T>, missing: &str) -> String {
+    value.map_or_else(|| missing.to_string(), |value| value.to_string())
+}
+
+pub fn dutch_auction(contract_address: H160, data: &DutchAuctionResponse) -> String {
+    let curve = match data.curve {
+        PriceCurve::Linear => "linear",
+        PriceCurve::Exponential => "exponential",
+    };
+    format!(
+        "nft_market dutch auction\ncontract: {:?}\nseller: {:?}\ntoken_ids: {}\ncurrency: {}\n\
+         start_price: {}\nfloor_price: {}\nstart_time: {}\nduration: {}\ncurve: {}\n\
+         rebate: {}\nnonce: {}",
+        contract_address,
+        data.seller,
+        token_ids(&data.token_ids),
+        data.currency.as_deref().unwrap_or("ETH"),
+        data.start_price,
+        data.floor_price,
+        optional(data.start_time, "now"),
+        data.duration,
+        curve,
+        data.rebate,
+        data.nonce
+    )
+}
+
+pub fn fixed_price(contract_address: H160, data: &FixedPriceResponse) -> String {
+    format!(
+        "nft_market fixed price\ncontract: {:?}\nseller: {:?}\ntoken_id: {}\ncurrency: {}\n\
+         price: {}\nduration: {}\nnonce: {}",
+        contract_address,
+        data.seller,
+        data.token_id,
+        data.currency.as_deref().unwrap_or("ETH"),
+        data.price,
+        optional(data.duration, "none"),
+        data.nonce
+    )
+}
+
+pub fn offer(contract_address: H160, data: &OfferResponse) -> String {
+    format!(
+        "nft_market offer\ncontract: {:?}\nbuyer: {:?}\ntoken_id: {}\ncurrency: {}\n\
+         price: {}\nduration: {}\nnonce: {}",
+        contract_address,
+        data.buyer,
+        data.token_id,
+        data.currency,
+        data.price,
+        optional(data.duration, "none"),
+        data.nonce
+    )
+}
+
+// `kind` is "listing" or "offer"
+pub fn cancel(kind: &str, id: u64) -> String {
+    format!("nft_market cancel {} {}", kind, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // personal_sign("Some data") with the web3.js documentation's example key
+    const SIGNATURE: &str = "0xb91467e570a6466aa9e9876cbcd013baba02900b8979d43fe208a4a4f339f5fd\
+                             6007e74cd82e037b800186422fc2da167c747ef045e5d18a5f5d4300f8e1a0291c";
+
+    fn address() -> H160 {
+        H160::from_str("2c7536e3605d9c16a7a3d7b1898e529396a65c23").unwrap()
+    }
+
+    #[test]
+    fn personal_sign_messages_are_prefixed() {
+        let expected = "1da44b586eb0729ff70a73c326926f6ed5a25f5b056e7f47fbc6e58d86871655";
+        assert_eq!(message_hash("Some data"), H256::from_str(expected).unwrap());
+    }
+
+    #[test]
+    fn the_signer_is_recovered() {
+        assert_eq!(signer("Some data", SIGNATURE).unwrap(), address());
+        assert!(verify(address(), "Some data", SIGNATURE).is_ok());
+        // v as 0 or 1 works too
+        let raw = format!("{}01", &SIGNATURE[..SIGNATURE.len() - 2]);
+        assert_eq!(signer("Some data", &raw).unwrap(), address());
+    }
+
+    #[test]
+    fn other_messages_and_bad_signatures_are_refused() {
+        assert!(verify(address(), "Other data", SIGNATURE).is_err());
+        assert!(verify(H160::repeat_byte(1), "Some data", SIGNATURE).is_err());
+        assert!(signer("Some data", "0x1234").is_err());
+        let bad_v = format!("{}05", &SIGNATURE[..SIGNATURE.len() - 2]);
+        assert!(signer("Some data", &bad_v).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::abi;
+use crate::collections::{by_address, standard};
+use crate::config::Config;
+use crate::currency::resolve;
+use crate::deploy;
+use crate::eth::{simulate, CallOutcome};
+use crate::jobs::JobRequest;
+use crate::metadata::TokenStandard;
+use crate::types::DryRun;
+use base64::decode;
+use serde_json::json;
+use std::str::FromStr;
+use web3::ethabi::{Contract, Function, Token};
+use web3::types::{H160, U256};
+
+// The mint eth::mint sends; the bundled ERC-721 ABI doesn't declare it
+const MINT_ABI: &str = r#"[{"type":"function","name":"mint","stateMutability":"nonpayable",
+"inputs":[{"name":"to","type":"address"},{"name":"tokenURI","type":"string"},
+{"name":"amount","type":"uint8"}],"outputs":[]}]"#;
+
+// The first transaction a job would send
+struct Planned {
+    from: H160,
+    to: Option<H160>,
+    function: Option<Function>,
+    data: Vec<u8>,
+    value: Option<U256>,
+    // Transactions the job sends afterwards, which depend on the first
+    not_simulated: Vec<String>,
+}
+
+fn function(abi: &[u8], signature: &str) -> Result<Function, String> {
+    let contract = Contract::load(abi).map_err(|e| e.to_string())?;
+    abi::find_function(&contract, signature, 0).map(Function::clone)
+}
+
+// burn from the collection's stored ABI, the same one the burn route checks,
+// or from the bundled Burnable ABI when none was stored
+fn burn_function(contract_address: H160) -> Result<Function, String> {
+    match by_address(contract_address).filter(|collection| collection.abi.is_some()) {
+        Some(collection) => {
+            let contract = abi::load(&abi::collection_abi(&collection)?)?;
+            let arity = match collection.standard {
+                TokenStandard::Erc721 => 1,
+                TokenStandard::Erc1155 => 3,
+            };
+            abi::find_function(&contract, "burn", arity).map(Function::clone)
+        }
+        None => match standard(contract_address) {
+            TokenStandard::Erc721 => function(
+                &include_bytes!("../abi/ERC721Burnable.json")[..],
+                "burn(uint256)",
+            ),
+            TokenStandard::Erc1155 => function(
+                &include_bytes!("../abi/ERC1155Burnable.json")[..],
+                "burn(address,uint256,uint256)",
+            ),
+        },
+    }
+}
+
+fn planned(from: H160, to: H160, function: Function, tokens: &[Token]) -> Result<Planned, String> {
+    let data = function.encode_input(tokens).map_err(|e| e.to_string())?;
+    Ok(Planned {
+        from,
+        to: Some(to),
+        function: Some(function),
+        data,
+        value: None,
+        not_simulated: Vec::new(),
+    })
+}
+
+fn plan(contract_address: Option<H160>, request: &JobRequest) -> Result<Planned, String> {
+    let my_address = Config::get_my_account().map_err(|e| e.to_string())?;
+    if let JobRequest::Deploy(data) = request {
+        let deployment = deploy::prepare(data)?;
+        let mut not_simulated = Vec::new();
+        if deployment.set_royalty {
+            not_simulated.push("setDefaultRoyalty(address,uint96)".to_string());
+        }
+        return Ok(Planned {
+            from: my_address,
+            to: None,
+            function: None,
+            data: deployment.code,
+            value: None,
+            not_simulated,
+        });
+    }
+    let contract_address = match contract_address {
+        Some(contract_address) => contract_address,
+        None => Config::get_contract_address().map_err(|e| e.to_string())?,
+    };
+    let erc721 = &include_bytes!("../abi/ERC721.json")[..];
+    let royalty = &include_bytes!("../abi/ERC721Royalty.json")[..];
+    match request {
+        JobRequest::Mint(data) => {
+            let user_address = H160::from_str(&data.account_address).map_err(|e| e.to_string())?;
+            let tokens = [
+                Token::Address(user_address),
+                Token::String(data.token_uri.clone()),
+                Token::Uint(U256::from(data.amount)),
+            ];
+            let mint = function(MINT_ABI.as_bytes(), "mint(address,string,uint8)")?;
+            let mut planned = planned(my_address, contract_address, mint, &tokens)?;
+            if data.royalty_receiver.is_some() && data.royalty_fee.is_some() {
+                planned
+                    .not_simulated
+                    .push("setTokenRoyalty(uint256,address,uint96)".to_string());
+            }
+            Ok(planned)
+        }
+        JobRequest::Approve(data) => {
+            let address_to = H160::from_str(&data.address_to).map_err(|e| e.to_string())?;
+            let tokens = [Token::Address(address_to), Token::Uint(data.token_id)];
+            let approve = function(erc721, "approve(address,uint256)")?;
+            planned(my_address, contract_address, approve, &tokens)
+        }
+        JobRequest::SafeTransferFrom(data) => {
+            let tokens = [
+                Token::Address(data.from),
+                Token::Address(data.to),
+                Token::Uint(data.token_id),
+            ];
+            let transfer = function(erc721, "safeTransferFrom(address,address,uint256)")?;
+            planned(my_address, contract_address, transfer, &tokens)
+        }
+        JobRequest::SafeTransferFromData(data) => {
+            let bytes = decode(&data.data).map_err(|e| e.to_string())?;
+            let tokens = [
+                Token::Address(data.from),
+                Token::Address(data.to),
+                Token::Uint(data.token_id),
+                Token::Bytes(bytes),
+            ];
+            let transfer = function(erc721, "safeTransferFrom(address,address,uint256,bytes)")?;
+            planned(my_address, contract_address, transfer, &tokens)
+        }
+        JobRequest::SetApprovalForAll(data) => {
+            let tokens = [Token::Address(data.operator), Token::Bool(data.approved)];
+            let approve = function(erc721, "setApprovalForAll(address,bool)")?;
+            planned(my_address, contract_address, approve, &tokens)
+        }
+        JobRequest::TransferFrom(data) => {
+            let tokens = [
+                Token::Address(data.from),
+                Token::Address(data.to),
+                Token::Uint(data.token_id),
+            ];
+            let transfer = function(erc721, "transferFrom(address,address,uint256)")?;
+            planned(my_address, contract_address, transfer, &tokens)
+        }
+        JobRequest::Royalty(data) => {
+            let receiver = Token::Address(data.receiver);
+            let fee = Token::Uint(U256::from(data.fee));
+            match data.token_id {
+                Some(token_id) => {
+                    let set = function(royalty, "setTokenRoyalty(uint256,address,uint96)")?;
+                    let tokens = [Token::Uint(token_id), receiver, fee];
+                    planned(my_address, contract_address, set, &tokens)
+                }
+                None => {
+                    let set = function(royalty, "setDefaultRoyalty(address,uint96)")?;
+                    planned(my_address, contract_address, set, &[receiver, fee])
+                }
+            }
+        }
+        // Signed by the buyer, on the token contract
+        JobRequest::Erc20Approve(data) => {
+            let currency = resolve(Some(&data.currency))?;
+            let token_address = currency
+                .address
+                .ok_or_else(|| format!("{} is not an ERC-20 token", currency.symbol))?;
+            let amount = currency.parse(&data.amount)?;
+            let approve = function(
+                &include_bytes!("../abi/ERC20.json")[..],
+                "approve(address,uint256)",
+            )?;
+            let tokens = [Token::Address(my_address), Token::Uint(amount)];
+            planned(data.account_address, token_address, approve, &tokens)
+        }
+        JobRequest::Call(data) => {
+            let bytes =
+                hex::decode(data.data.trim_start_matches("0x")).map_err(|e| e.to_string())?;
+            Ok(Planned {
+                from: my_address,
+                to: Some(contract_address),
+                function: None,
+                data: bytes,
+                value: data.value,
+                not_simulated: Vec::new(),
+            })
+        }
+        JobRequest::Burn(data) => {
+            let burn = burn_function(contract_address)?;
+            let tokens = match standard(contract_address) {
+                TokenStandard::Erc721 => vec![Token::Uint(data.token_id)],
+                TokenStandard::Erc1155 => vec![
+                    Token::Address(data.owner.unwrap_or(my_address)),
+                    Token::Uint(data.token_id),
+                    Token::Uint(data.amount.unwrap_or_else(U256::one)),
+                ],
+            };
+            planned(my_address, contract_address, burn, &tokens)
+        }
+        JobRequest::Deploy(_) => unreachable!("deployments are planned above"),
+    }
+}
+
+// Runs the job's first transaction with eth_call at the pending block and
+// estimates its gas. Nothing is signed, queued or broadcast.
+pub async fn dry_run(
+    contract_address: Option<H160>,
+    request: &JobRequest,
+) -> Result<DryRun, String> {
+    let planned = plan(contract_address, request)?;
+    let (outcome, gas_estimate) = simulate(
+        planned.from,
+        planned.to,
+        planned.data.clone(),
+        planned.value,
+    )
+    .await?;
+    let mut dry_run = DryRun {
+        kind: request.kind().to_string(),
+        from: planned.from,
+        to: planned.to,
+        function: planned.function.as_ref().map(abi::signature),
+        data: format!("0x{}", hex::encode(&planned.data)),
+        value: planned.value,
+        return_value: None,
+        reverted: false,
+        revert_reason: None,
+        gas_estimate,
+        not_simulated: planned.not_simulated,
+    };
+    match outcome {
+        CallOutcome::Returned(output) => {
+            dry_run.return_value = Some(match &planned.function {
+                Some(function) => match function.decode_output(&output) {
+                    Ok(tokens) => json!(tokens.iter().map(abi::to_json).collect::<Vec<_>>()),
+                    Err(_) => json!(format!("0x{}", hex::encode(&output))),
+                },
+                // Raw calls and deployments return bytes as-is
+                None => json!(format!("0x{}", hex::encode(&output))),
+            });
+        }
+        CallOutcome::Reverted(reason) => {
+            dry_run.reverted = true;
+            dry_run.revert_reason = Some(reason);
+        }
+    }
+    Ok(dry_run)
+}
@@ -0,0 +1,263 @@
+mod pg;
+mod sqlite;
+
+use crate::collections::Collection;
+use crate::config::Config;
+use crate::indexer::TokenEvent;
+use crate::interfaces::Capabilities;
+use crate::jobs::Job;
+use crate::journal::JournalEntry;
+use crate::ledger::Payout;
+use crate::market::{Listing, Offer};
+use crate::media::MediaInfo;
+use crate::receipts::TrackedTx;
+use crate::webhooks::{Delivery, Webhook};
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Mutex;
+use web3::types::H160;
+
+pub use pg::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+lazy_static! {
+    static ref STORAGE: Mutex<Option<Box<dyn Storage>>> = Mutex::new(None);
+}
+
+// Schema changes in order. Each one runs once, inside a transaction, and is
+// recorded in `schema_migrations`. The SQL has to work on SQLite and Postgres.
+const MIGRATIONS: &[(i64, &str)] = &[
+    (1, INITIAL_SCHEMA),
+    (2, JOURNAL),
+    (3, JOBS),
+    (4, WEBHOOKS),
+    (5, MEDIA),
+    (6, COLLECTIONS),
+    (7, INTERFACES),
+    (8, PAYOUTS),
+];
+
+const INITIAL_SCHEMA: &str = "CREATE TABLE transactions (
+    transaction_hash TEXT PRIMARY KEY,
+    state TEXT NOT NULL,
+    block_number BIGINT,
+    data TEXT NOT NULL
+);
+CREATE TABLE listings (
+    id BIGINT PRIMARY KEY,
+    contract_address TEXT NOT NULL,
+    status TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE offers (
+    id BIGINT PRIMARY KEY,
+    contract_address TEXT NOT NULL,
+    status TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE events (
+    block_number BIGINT NOT NULL,
+    log_index BIGINT NOT NULL,
+    contract_address TEXT NOT NULL,
+    transaction_hash TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (block_number, log_index)
+);
+CREATE INDEX events_contract_address ON events (contract_address);
+CREATE TABLE metadata (
+    uri TEXT PRIMARY KEY,
+    data TEXT NOT NULL,
+    fetched_at BIGINT NOT NULL
+);
+CREATE TABLE state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);";
+
+const JOURNAL: &str = "CREATE TABLE journal (
+    key TEXT PRIMARY KEY,
+    route TEXT NOT NULL,
+    status TEXT NOT NULL,
+    data TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);";
+
+const JOBS: &str = "CREATE TABLE jobs (
+    id BIGINT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    status TEXT NOT NULL,
+    data TEXT NOT NULL
+);";
+
+const WEBHOOKS: &str = "CREATE TABLE webhooks (
+    id BIGINT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE webhook_deliveries (
+    id BIGINT PRIMARY KEY,
+    webhook_id BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX webhook_deliveries_webhook_id ON webhook_deliveries (webhook_id);
+CREATE INDEX webhook_deliveries_status ON webhook_deliveries (status);";
+
+const MEDIA: &str = "CREATE TABLE media (
+    uri TEXT PRIMARY KEY,
+    content_hash TEXT,
+    data TEXT NOT NULL
+);";
+
+const COLLECTIONS: &str = "CREATE TABLE collections (
+    id BIGINT PRIMARY KEY,
+    contract_address TEXT NOT NULL UNIQUE,
+    data TEXT NOT NULL
+);";
+
+const INTERFACES: &str = "CREATE TABLE interfaces (
+    contract_address TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);";
+
+const PAYOUTS: &str = "CREATE TABLE payouts (
+    id BIGINT PRIMARY KEY,
+    contract_address TEXT NOT NULL,
+    seller TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    data TEXT NOT NULL
+);";
+
+// Everything the service persists. Rows keep a few columns for lookups and the
+// full record as JSON, so both backends share one schema.
+pub trait Storage: Send {
+    fn migrate(&mut self) -> Result<(), String>;
+
+    // Transactions sent by the service and their confirmation state
+    fn upsert_transaction(&mut self, tx: &TrackedTx) -> Result<(), String>;
+    fn transactions(&mut self) -> Result<Vec<TrackedTx>, String>;
+
+    // Orders
+    fn upsert_listing(&mut self, listing: &Listing) -> Result<(), String>;
+    fn upsert_offer(&mut self, offer: &Offer) -> Result<(), String>;
+    fn listings(&mut self) -> Result<Vec<Listing>, String>;
+    fn offers(&mut self) -> Result<Vec<Offer>, String>;
+
+    // Indexed chain events, ordered by block and log index
+    fn insert_events(&mut self, events: &[TokenEvent]) -> Result<(), String>;
+    fn delete_events_after(&mut self, block_number: Option<u64>) -> Result<(), String>;
+    fn events(&mut self) -> Result<Vec<TokenEvent>, String>;
+
+    // Token metadata documents keyed by URI
+    fn put_metadata(&mut self, uri: &str, document: &Value, fetched_at: u64) -> Result<(), String>;
+    fn metadata(&mut self, uri: &str) -> Result<Option<(Value, u64)>, String>;
+
+    // Write requests by idempotency key. Inserting returns false when the key is taken.
+    fn insert_journal_entry(&mut self, entry: &JournalEntry) -> Result<bool, String>;
+    fn update_journal_entry(&mut self, entry: &JournalEntry) -> Result<(), String>;
+    fn delete_journal_entry(&mut self, key: &str) -> Result<(), String>;
+    fn journal_entry(&mut self, key: &str) -> Result<Option<JournalEntry>, String>;
+
+    // Queued chain writes and their outcome
+    fn upsert_job(&mut self, job: &Job) -> Result<(), String>;
+    fn jobs(&mut self) -> Result<Vec<Job>, String>;
+
+    // Webhook subscriptions and every delivery made to them
+    fn upsert_webhook(&mut self, webhook: &Webhook) -> Result<(), String>;
+    fn delete_webhook(&mut self, id: u64) -> Result<(), String>;
+    fn webhooks(&mut self) -> Result<Vec<Webhook>, String>;
+    fn upsert_delivery(&mut self, delivery: &Delivery) -> Result<(), String>;
+    fn delivery(&mut self, id: u64) -> Result<Option<Delivery>, String>;
+    fn deliveries(&mut self, webhook_id: u64) -> Result<Vec<Delivery>, String>;
+    fn pending_deliveries(&mut self) -> Result<Vec<Delivery>, String>;
+    fn last_delivery_id(&mut self) -> Result<Option<u64>, String>;
+    // Highest webhook id ever used, including by deleted webhooks' deliveries
+    fn last_webhook_id(&mut self) -> Result<Option<u64>, String>;
+
+    // Media referenced by metadata, keyed by URI, and what was made of it
+    fn put_media(&mut self, uri: &str, media: &MediaInfo) -> Result<(), String>;
+    fn media(&mut self, uri: &str) -> Result<Option<MediaInfo>, String>;
+
+    // Settlement breakdown of every sale, for the payout report
+    fn insert_payout(&mut self, payout: &Payout) -> Result<(), String>;
+    fn payouts(&mut self) -> Result<Vec<Payout>, String>;
+
+    // Registered collections
+    fn upsert_collection(&mut self, collection: &Collection) -> Result<(), String>;
+    fn delete_collection(&mut self, id: u64) -> Result<(), String>;
+    fn collections(&mut self) -> Result<Vec<Collection>, String>;
+
+    // What a contract was found to implement, keyed by contract address
+    fn put_capabilities(&mut self, capabilities: &Capabilities) -> Result<(), String>;
+    fn capabilities(&mut self, contract_address: H160) -> Result<Option<Capabilities>, String>;
+
+    // Small service state, e.g. the indexer cursor
+    fn put_state(&mut self, key: &str, value: &str) -> Result<(), String>;
+    fn state(&mut self, key: &str) -> Result<Option<String>, String>;
+}
+
+// postgres:// and postgresql:// URLs use Postgres, anything else is a SQLite
+// file path. An empty URL puts the database in the data directory.
+pub fn open(database_url: &str) -> Result<Box<dyn Storage>, String> {
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        return Ok(Box::new(PostgresStorage::connect(database_url)?));
+    }
+    let path = match database_url.strip_prefix("sqlite://") {
+        Some(path) => path.to_string(),
+        None if database_url.is_empty() => {
+            let data_dir = Config::get_data_dir();
+            std::fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+            Path::new(&data_dir)
+                .join("market.db")
+                .to_string_lossy()
+                .into_owned()
+        }
+        None => database_url.to_string(),
+    };
+    Ok(Box::new(SqliteStorage::open(&path)?))
+}
+
+pub fn init() -> Result<(), String> {
+    let mut storage = open(&Config::get_database_url())?;
+    storage.migrate()?;
+    *STORAGE.lock().unwrap() = Some(storage);
+    Ok(())
+}
+
+// Tests share one in-memory database, so they should use their own keys
+#[cfg(test)]
+pub fn init_in_memory() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        storage.migrate().unwrap();
+        *STORAGE.lock().unwrap() = Some(Box::new(storage));
+    });
+}
+
+pub fn with<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce(&mut dyn Storage) -> Result<T, String>,
+{
+    let mut storage = STORAGE.lock().unwrap();
+    match storage.as_mut() {
+        Some(storage) => f(storage.as_mut()),
+        None => Err("storage is not initialized".into()),
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(data: &str) -> Result<T, String> {
+    serde_json::from_str(data).map_err(|e| e.to_string())
+}
+
+// Status enums are stored by their serialized name so they can be filtered in SQL
+fn label<T: serde::Serialize>(value: &T) -> Result<String, String> {
+    match serde_json::to_value(value).map_err(|e| e.to_string())? {
+        Value::String(label) => Ok(label),
+        other => Ok(other.to_string()),
+    }
+}
@@ -0,0 +1,522 @@
+use super::{from_json, label, to_json, Storage, MIGRATIONS};
+use crate::collections::Collection;
+use crate::indexer::TokenEvent;
+use crate::interfaces::Capabilities;
+use crate::jobs::Job;
+use crate::journal::JournalEntry;
+use crate::ledger::Payout;
+use crate::market::{now, Listing, Offer};
+use crate::media::MediaInfo;
+use crate::receipts::TrackedTx;
+use crate::webhooks::{Delivery, Webhook};
+use postgres::{Client, NoTls};
+use serde_json::Value;
+use web3::types::H160;
+
+// Production backend
+pub struct PostgresStorage {
+    client: Client,
+}
+
+impl PostgresStorage {
+    pub fn connect(database_url: &str) -> Result<PostgresStorage, String> {
+        let client = Client::connect(database_url, NoTls).map_err(|e| e.to_string())?;
+        Ok(PostgresStorage { client })
+    }
+
+    fn rows(&mut self, sql: &str) -> Result<Vec<String>, String> {
+        let rows = self.client.query(sql, &[]).map_err(|e| e.to_string())?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
+
+impl Storage for PostgresStorage {
+    fn migrate(&mut self) -> Result<(), String> {
+        self.client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (
+                    version BIGINT PRIMARY KEY,
+                    applied_at BIGINT NOT NULL
+                )",
+            )
+            .map_err(|e| e.to_string())?;
+        for (version, sql) in MIGRATIONS {
+            let applied = self
+                .client
+                .query_opt(
+                    "SELECT version FROM schema_migrations WHERE version = $1",
+                    &[version],
+                )
+                .map_err(|e| e.to_string())?;
+            if applied.is_some() {
+                continue;
+            }
+            let mut tx = self.client.transaction().map_err(|e| e.to_string())?;
+            tx.batch_execute(sql).map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, applied_at) VALUES ($1, $2)",
+                &[version, &(now() as i64)],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn upsert_transaction(&mut self, tx: &TrackedTx) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO transactions (transaction_hash, state, block_number, data)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (transaction_hash) DO UPDATE SET
+                    state = excluded.state,
+                    block_number = excluded.block_number,
+                    data = excluded.data",
+                &[
+                    &format!("{:?}", tx.transaction_hash),
+                    &label(&tx.state)?,
+                    &tx.block_number.map(|block_number| block_number as i64),
+                    &to_json(tx)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn transactions(&mut self) -> Result<Vec<TrackedTx>, String> {
+        self.rows("SELECT data FROM transactions")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_listing(&mut self, listing: &Listing) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO listings (id, contract_address, status, data)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                &[
+                    &(listing.id as i64),
+                    &format!("{:?}", listing.contract_address),
+                    &label(&listing.status)?,
+                    &to_json(listing)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn upsert_offer(&mut self, offer: &Offer) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO offers (id, contract_address, status, data)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                &[
+                    &(offer.id as i64),
+                    &format!("{:?}", offer.contract_address),
+                    &label(&offer.status)?,
+                    &to_json(offer)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn listings(&mut self) -> Result<Vec<Listing>, String> {
+        self.rows("SELECT data FROM listings ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn offers(&mut self) -> Result<Vec<Offer>, String> {
+        self.rows("SELECT data FROM offers ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn insert_events(&mut self, events: &[TokenEvent]) -> Result<(), String> {
+        let mut tx = self.client.transaction().map_err(|e| e.to_string())?;
+        for event in events {
+            tx.execute(
+                "INSERT INTO events (block_number, log_index, contract_address, transaction_hash, data)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (block_number, log_index) DO UPDATE SET
+                    contract_address = excluded.contract_address,
+                    transaction_hash = excluded.transaction_hash,
+                    data = excluded.data",
+                &[
+                    &(event.block_number as i64),
+                    &(event.log_index as i64),
+                    &format!("{:?}", event.contract_address),
+                    &format!("{:?}", event.transaction_hash),
+                    &to_json(event)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn delete_events_after(&mut self, block_number: Option<u64>) -> Result<(), String> {
+        let after = block_number.map_or(-1, |block_number| block_number as i64);
+        self.client
+            .execute("DELETE FROM events WHERE block_number > $1", &[&after])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn events(&mut self) -> Result<Vec<TokenEvent>, String> {
+        self.rows("SELECT data FROM events ORDER BY block_number, log_index")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn put_metadata(&mut self, uri: &str, document: &Value, fetched_at: u64) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO metadata (uri, data, fetched_at) VALUES ($1, $2, $3)
+                 ON CONFLICT (uri) DO UPDATE SET
+                    data = excluded.data,
+                    fetched_at = excluded.fetched_at",
+                &[&uri, &to_json(document)?, &(fetched_at as i64)],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn metadata(&mut self, uri: &str) -> Result<Option<(Value, u64)>, String> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT data, fetched_at FROM metadata WHERE uri = $1",
+                &[&uri],
+            )
+            .map_err(|e| e.to_string())?;
+        match row {
+            Some(row) => {
+                let data: String = row.get(0);
+                let fetched_at: i64 = row.get(1);
+                Ok(Some((from_json(&data)?, fetched_at as u64)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn insert_journal_entry(&mut self, entry: &JournalEntry) -> Result<bool, String> {
+        let inserted = self
+            .client
+            .execute(
+                "INSERT INTO journal (key, route, status, data, created_at)
+                 VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (key) DO NOTHING",
+                &[
+                    &entry.key,
+                    &entry.route,
+                    &label(&entry.status)?,
+                    &to_json(entry)?,
+                    &(entry.created_at as i64),
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(inserted == 1)
+    }
+
+    fn update_journal_entry(&mut self, entry: &JournalEntry) -> Result<(), String> {
+        self.client
+            .execute(
+                "UPDATE journal SET status = $2, data = $3 WHERE key = $1",
+                &[&entry.key, &label(&entry.status)?, &to_json(entry)?],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_journal_entry(&mut self, key: &str) -> Result<(), String> {
+        self.client
+            .execute("DELETE FROM journal WHERE key = $1", &[&key])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn journal_entry(&mut self, key: &str) -> Result<Option<JournalEntry>, String> {
+        let row = self
+            .client
+            .query_opt("SELECT data FROM journal WHERE key = $1", &[&key])
+            .map_err(|e| e.to_string())?;
+        match row {
+            Some(row) => {
+                let data: String = row.get(0);
+                Ok(Some(from_json(&data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn upsert_job(&mut self, job: &Job) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO jobs (id, kind, status, data)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                &[
+                    &(job.id as i64),
+                    &job.request.kind(),
+                    &label(&job.status)?,
+                    &to_json(job)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn jobs(&mut self) -> Result<Vec<Job>, String> {
+        self.rows("SELECT data FROM jobs ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_webhook(&mut self, webhook: &Webhook) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO webhooks (id, data) VALUES ($1, $2)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                &[&(webhook.id as i64), &to_json(webhook)?],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_webhook(&mut self, id: u64) -> Result<(), String> {
+        self.client
+            .execute("DELETE FROM webhooks WHERE id = $1", &[&(id as i64)])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn webhooks(&mut self) -> Result<Vec<Webhook>, String> {
+        self.rows("SELECT data FROM webhooks ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_delivery(&mut self, delivery: &Delivery) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO webhook_deliveries (id, webhook_id, status, data)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                &[
+                    &(delivery.id as i64),
+                    &(delivery.webhook_id as i64),
+                    &label(&delivery.status)?,
+                    &to_json(delivery)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delivery(&mut self, id: u64) -> Result<Option<Delivery>, String> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT data FROM webhook_deliveries WHERE id = $1",
+                &[&(id as i64)],
+            )
+            .map_err(|e| e.to_string())?;
+        match row {
+            Some(row) => {
+                let data: String = row.get(0);
+                Ok(Some(from_json(&data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn deliveries(&mut self, webhook_id: u64) -> Result<Vec<Delivery>, String> {
+        let rows = self
+            .client
+            .query(
+                "SELECT data FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY id DESC",
+                &[&(webhook_id as i64)],
+            )
+            .map_err(|e| e.to_string())?;
+        rows.iter()
+            .map(|row| from_json(&row.get::<_, String>(0)))
+            .collect()
+    }
+
+    fn pending_deliveries(&mut self) -> Result<Vec<Delivery>, String> {
+        self.rows("SELECT data FROM webhook_deliveries WHERE status = 'pending' ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn last_delivery_id(&mut self) -> Result<Option<u64>, String> {
+        let row = self
+            .client
+            .query_one("SELECT MAX(id) FROM webhook_deliveries", &[])
+            .map_err(|e| e.to_string())?;
+        let id: Option<i64> = row.get(0);
+        Ok(id.map(|id| id as u64))
+    }
+
+    fn last_webhook_id(&mut self) -> Result<Option<u64>, String> {
+        let row = self
+            .client
+            .query_one(
+                "SELECT MAX(id) FROM (SELECT id FROM webhooks
+                 UNION ALL SELECT webhook_id FROM webhook_deliveries) AS ids",
+                &[],
+            )
+            .map_err(|e| e.to_string())?;
+        let id: Option<i64> = row.get(0);
+        Ok(id.map(|id| id as u64))
+    }
+
+    fn put_media(&mut self, uri: &str, media: &MediaInfo) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO media (uri, content_hash, data) VALUES ($1, $2, $3)
+                 ON CONFLICT (uri) DO UPDATE SET
+                    content_hash = excluded.content_hash,
+                    data = excluded.data",
+                &[&uri, &media.content_hash, &to_json(media)?],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn media(&mut self, uri: &str) -> Result<Option<MediaInfo>, String> {
+        let row = self
+            .client
+            .query_opt("SELECT data FROM media WHERE uri = $1", &[&uri])
+            .map_err(|e| e.to_string())?;
+        match row {
+            Some(row) => {
+                let data: String = row.get(0);
+                Ok(Some(from_json(&data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn insert_payout(&mut self, payout: &Payout) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO payouts (id, contract_address, seller, created_at, data)
+                 VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &(payout.id as i64),
+                    &format!("{:?}", payout.contract_address),
+                    &format!("{:?}", payout.seller),
+                    &(payout.timestamp as i64),
+                    &to_json(payout)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn payouts(&mut self) -> Result<Vec<Payout>, String> {
+        self.rows("SELECT data FROM payouts ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_collection(&mut self, collection: &Collection) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO collections (id, contract_address, data) VALUES ($1, $2, $3)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                &[
+                    &(collection.id as i64),
+                    &format!("{:?}", collection.contract_address),
+                    &to_json(collection)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_collection(&mut self, id: u64) -> Result<(), String> {
+        self.client
+            .execute("DELETE FROM collections WHERE id = $1", &[&(id as i64)])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn collections(&mut self) -> Result<Vec<Collection>, String> {
+        self.rows("SELECT data FROM collections ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn put_capabilities(&mut self, capabilities: &Capabilities) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO interfaces (contract_address, data) VALUES ($1, $2)
+                 ON CONFLICT (contract_address) DO UPDATE SET data = excluded.data",
+                &[
+                    &format!("{:?}", capabilities.contract_address),
+                    &to_json(capabilities)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn capabilities(&mut self, contract_address: H160) -> Result<Option<Capabilities>, String> {
+        let row = self
+            .client
+            .query_opt(
+                "SELECT data FROM interfaces WHERE contract_address = $1",
+                &[&format!("{:?}", contract_address)],
+            )
+            .map_err(|e| e.to_string())?;
+        match row {
+            Some(row) => {
+                let data: String = row.get(0);
+                Ok(Some(from_json(&data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn put_state(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.client
+            .execute(
+                "INSERT INTO state (key, value) VALUES ($1, $2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                &[&key, &value],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn state(&mut self, key: &str) -> Result<Option<String>, String> {
+        let row = self
+            .client
+            .query_opt("SELECT value FROM state WHERE key = $1", &[&key])
+            .map_err(|e| e.to_string())?;
+        Ok(row.map(|row| row.get(0)))
+    }
+}
@@ -0,0 +1,631 @@
+use super::{from_json, label, to_json, Storage, MIGRATIONS};
+use crate::collections::Collection;
+use crate::indexer::TokenEvent;
+use crate::interfaces::Capabilities;
+use crate::jobs::Job;
+use crate::journal::JournalEntry;
+use crate::ledger::Payout;
+use crate::market::{now, Listing, Offer};
+use crate::media::MediaInfo;
+use crate::receipts::TrackedTx;
+use crate::webhooks::{Delivery, Webhook};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use web3::types::H160;
+
+// Embedded backend for development and tests
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<SqliteStorage, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        Ok(SqliteStorage { conn })
+    }
+
+    fn rows(&self, sql: &str) -> Result<Vec<String>, String> {
+        let mut statement = self.conn.prepare(sql).map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<String>, _>>()
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn migrate(&mut self) -> Result<(), String> {
+        self.conn
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (
+                    version BIGINT PRIMARY KEY,
+                    applied_at BIGINT NOT NULL
+                )",
+            )
+            .map_err(|e| e.to_string())?;
+        for (version, sql) in MIGRATIONS {
+            let applied: Option<i64> = self
+                .conn
+                .query_row(
+                    "SELECT version FROM schema_migrations WHERE version = ?1",
+                    params![version],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if applied.is_some() {
+                continue;
+            }
+            let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+            tx.execute_batch(sql).map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT INTO schema_migrations (version, applied_at) VALUES (?1, ?2)",
+                params![version, now() as i64],
+            )
+            .map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn upsert_transaction(&mut self, tx: &TrackedTx) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO transactions (transaction_hash, state, block_number, data)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (transaction_hash) DO UPDATE SET
+                    state = excluded.state,
+                    block_number = excluded.block_number,
+                    data = excluded.data",
+                params![
+                    format!("{:?}", tx.transaction_hash),
+                    label(&tx.state)?,
+                    tx.block_number.map(|block_number| block_number as i64),
+                    to_json(tx)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn transactions(&mut self) -> Result<Vec<TrackedTx>, String> {
+        self.rows("SELECT data FROM transactions")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_listing(&mut self, listing: &Listing) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO listings (id, contract_address, status, data)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                params![
+                    listing.id as i64,
+                    format!("{:?}", listing.contract_address),
+                    label(&listing.status)?,
+                    to_json(listing)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn upsert_offer(&mut self, offer: &Offer) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO offers (id, contract_address, status, data)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                params![
+                    offer.id as i64,
+                    format!("{:?}", offer.contract_address),
+                    label(&offer.status)?,
+                    to_json(offer)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn listings(&mut self) -> Result<Vec<Listing>, String> {
+        self.rows("SELECT data FROM listings ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn offers(&mut self) -> Result<Vec<Offer>, String> {
+        self.rows("SELECT data FROM offers ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn insert_events(&mut self, events: &[TokenEvent]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+        for event in events {
+            tx.execute(
+                "INSERT INTO events (block_number, log_index, contract_address, transaction_hash, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (block_number, log_index) DO UPDATE SET
+                    contract_address = excluded.contract_address,
+                    transaction_hash = excluded.transaction_hash,
+                    data = excluded.data",
+                params![
+                    event.block_number as i64,
+                    event.log_index as i64,
+                    format!("{:?}", event.contract_address),
+                    format!("{:?}", event.transaction_hash),
+                    to_json(event)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn delete_events_after(&mut self, block_number: Option<u64>) -> Result<(), String> {
+        let after = block_number.map_or(-1, |block_number| block_number as i64);
+        self.conn
+            .execute("DELETE FROM events WHERE block_number > ?1", params![after])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn events(&mut self) -> Result<Vec<TokenEvent>, String> {
+        self.rows("SELECT data FROM events ORDER BY block_number, log_index")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn put_metadata(&mut self, uri: &str, document: &Value, fetched_at: u64) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO metadata (uri, data, fetched_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (uri) DO UPDATE SET
+                    data = excluded.data,
+                    fetched_at = excluded.fetched_at",
+                params![uri, to_json(document)?, fetched_at as i64],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn metadata(&mut self, uri: &str) -> Result<Option<(Value, u64)>, String> {
+        let row: Option<(String, i64)> = self
+            .conn
+            .query_row(
+                "SELECT data, fetched_at FROM metadata WHERE uri = ?1",
+                params![uri],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        match row {
+            Some((data, fetched_at)) => Ok(Some((from_json(&data)?, fetched_at as u64))),
+            None => Ok(None),
+        }
+    }
+
+    fn insert_journal_entry(&mut self, entry: &JournalEntry) -> Result<bool, String> {
+        let inserted = self
+            .conn
+            .execute(
+                "INSERT INTO journal (key, route, status, data, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (key) DO NOTHING",
+                params![
+                    entry.key,
+                    entry.route,
+                    label(&entry.status)?,
+                    to_json(entry)?,
+                    entry.created_at as i64,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(inserted == 1)
+    }
+
+    fn update_journal_entry(&mut self, entry: &JournalEntry) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE journal SET status = ?2, data = ?3 WHERE key = ?1",
+                params![entry.key, label(&entry.status)?, to_json(entry)?],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_journal_entry(&mut self, key: &str) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM journal WHERE key = ?1", params![key])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn journal_entry(&mut self, key: &str) -> Result<Option<JournalEntry>, String> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM journal WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        data.map(|data| from_json(&data)).transpose()
+    }
+
+    fn upsert_job(&mut self, job: &Job) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO jobs (id, kind, status, data)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                params![
+                    job.id as i64,
+                    job.request.kind(),
+                    label(&job.status)?,
+                    to_json(job)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn jobs(&mut self) -> Result<Vec<Job>, String> {
+        self.rows("SELECT data FROM jobs ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_webhook(&mut self, webhook: &Webhook) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO webhooks (id, data) VALUES (?1, ?2)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                params![webhook.id as i64, to_json(webhook)?],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_webhook(&mut self, id: u64) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM webhooks WHERE id = ?1", params![id as i64])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn webhooks(&mut self) -> Result<Vec<Webhook>, String> {
+        self.rows("SELECT data FROM webhooks ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_delivery(&mut self, delivery: &Delivery) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO webhook_deliveries (id, webhook_id, status, data)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (id) DO UPDATE SET
+                    status = excluded.status,
+                    data = excluded.data",
+                params![
+                    delivery.id as i64,
+                    delivery.webhook_id as i64,
+                    label(&delivery.status)?,
+                    to_json(delivery)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delivery(&mut self, id: u64) -> Result<Option<Delivery>, String> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM webhook_deliveries WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        data.map(|data| from_json(&data)).transpose()
+    }
+
+    fn deliveries(&mut self, webhook_id: u64) -> Result<Vec<Delivery>, String> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT data FROM webhook_deliveries WHERE webhook_id = ?1 ORDER BY id DESC")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![webhook_id as i64], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<String>, _>>()
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn pending_deliveries(&mut self) -> Result<Vec<Delivery>, String> {
+        self.rows("SELECT data FROM webhook_deliveries WHERE status = 'pending' ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn last_delivery_id(&mut self) -> Result<Option<u64>, String> {
+        let id: Option<i64> = self
+            .conn
+            .query_row("SELECT MAX(id) FROM webhook_deliveries", params![], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(id.map(|id| id as u64))
+    }
+
+    fn last_webhook_id(&mut self) -> Result<Option<u64>, String> {
+        let id: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT MAX(id) FROM (SELECT id FROM webhooks
+                 UNION ALL SELECT webhook_id FROM webhook_deliveries) AS ids",
+                params![],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(id.map(|id| id as u64))
+    }
+
+    fn put_media(&mut self, uri: &str, media: &MediaInfo) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO media (uri, content_hash, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (uri) DO UPDATE SET
+                    content_hash = excluded.content_hash,
+                    data = excluded.data",
+                params![uri, media.content_hash, to_json(media)?],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn media(&mut self, uri: &str) -> Result<Option<MediaInfo>, String> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM media WHERE uri = ?1",
+                params![uri],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        data.map(|data| from_json(&data)).transpose()
+    }
+
+    fn insert_payout(&mut self, payout: &Payout) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO payouts (id, contract_address, seller, created_at, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    payout.id as i64,
+                    format!("{:?}", payout.contract_address),
+                    format!("{:?}", payout.seller),
+                    payout.timestamp as i64,
+                    to_json(payout)?,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn payouts(&mut self) -> Result<Vec<Payout>, String> {
+        self.rows("SELECT data FROM payouts ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn upsert_collection(&mut self, collection: &Collection) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO collections (id, contract_address, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data",
+                params![
+                    collection.id as i64,
+                    format!("{:?}", collection.contract_address),
+                    to_json(collection)?
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_collection(&mut self, id: u64) -> Result<(), String> {
+        self.conn
+            .execute("DELETE FROM collections WHERE id = ?1", params![id as i64])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn collections(&mut self) -> Result<Vec<Collection>, String> {
+        self.rows("SELECT data FROM collections ORDER BY id")?
+            .iter()
+            .map(|data| from_json(data))
+            .collect()
+    }
+
+    fn put_capabilities(&mut self, capabilities: &Capabilities) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO interfaces (contract_address, data) VALUES (?1, ?2)
+                 ON CONFLICT (contract_address) DO UPDATE SET data = excluded.data",
+                params![
+                    format!("{:?}", capabilities.contract_address),
+                    to_json(capabilities)?
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn capabilities(&mut self, contract_address: H160) -> Result<Option<Capabilities>, String> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM interfaces WHERE contract_address = ?1",
+                params![format!("{:?}", contract_address)],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        data.map(|data| from_json(&data)).transpose()
+    }
+
+    fn put_state(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.conn
+            .execute(
+                "INSERT INTO state (key, value) VALUES (?1, ?2)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn state(&mut self, key: &str) -> Result<Option<String>, String> {
+        self.conn
+            .query_row(
+                "SELECT value FROM state WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+    use crate::journal::JournalStatus;
+    use serde_json::json;
+    use web3::types::{H256, U256};
+
+    fn storage() -> SqliteStorage {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        storage.migrate().unwrap();
+        storage
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let mut storage = storage();
+        storage.migrate().unwrap();
+        let versions = storage
+            .rows("SELECT CAST(version AS TEXT) FROM schema_migrations ORDER BY version")
+            .unwrap();
+        let expected: Vec<String> = MIGRATIONS
+            .iter()
+            .map(|(version, _)| version.to_string())
+            .collect();
+        assert_eq!(versions, expected);
+    }
+
+    #[test]
+    fn journal_entries_round_trip() {
+        let mut storage = storage();
+        let mut entry = JournalEntry {
+            key: "key".into(),
+            route: "mint".into(),
+            request: json!({ "token_id": 1 }),
+            request_hash: H256::repeat_byte(1),
+            status: JournalStatus::InProgress,
+            tx_hashes: Vec::new(),
+            job_id: Some(3),
+            response: None,
+            http_status: None,
+            created_at: 1,
+            updated_at: 1,
+        };
+        assert!(storage.insert_journal_entry(&entry).unwrap());
+        assert!(!storage.insert_journal_entry(&entry).unwrap());
+
+        entry.status = JournalStatus::Completed;
+        entry.tx_hashes.push(H256::repeat_byte(2));
+        entry.response = Some(json!({ "id": 7 }));
+        storage.update_journal_entry(&entry).unwrap();
+        let stored = storage.journal_entry("key").unwrap().unwrap();
+        assert_eq!(stored.status, JournalStatus::Completed);
+        assert_eq!(stored.tx_hashes, entry.tx_hashes);
+        assert_eq!(stored.response, entry.response);
+        assert_eq!(stored.request, entry.request);
+        assert_eq!(stored.job_id, Some(3));
+
+        storage.delete_journal_entry("key").unwrap();
+        assert!(storage.journal_entry("key").unwrap().is_none());
+    }
+
+    #[test]
+    fn payouts_round_trip_in_order() {
+        let mut storage = storage();
+        for id in &[2u64, 1] {
+            let payout = Payout {
+                id: *id,
+                listing_id: 3,
+                contract_address: H160::repeat_byte(4),
+                token_id: U256::from(5),
+                seller: H160::repeat_byte(6),
+                buyer: H160::repeat_byte(7),
+                currency: Currency::eth(),
+                gross_price: U256::from(100),
+                fee: U256::from(2),
+                royalty: U256::from(5),
+                seller_proceeds: U256::from(93),
+                tx_hash: Some(H256::repeat_byte(8)),
+                error: None,
+                timestamp: 10 + id,
+            };
+            storage.insert_payout(&payout).unwrap();
+        }
+        let payouts = storage.payouts().unwrap();
+        assert_eq!(
+            payouts.iter().map(|payout| payout.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(payouts[0].seller_proceeds, U256::from(93));
+        assert_eq!(payouts[0].currency, Currency::eth());
+        assert_eq!(payouts[1].timestamp, 12);
+    }
+
+    #[test]
+    fn state_and_metadata_round_trip() {
+        let mut storage = storage();
+        assert_eq!(storage.state("cursor").unwrap(), None);
+        storage.put_state("cursor", "10").unwrap();
+        storage.put_state("cursor", "11").unwrap();
+        assert_eq!(storage.state("cursor").unwrap(), Some("11".into()));
+
+        let document = json!({ "name": "token" });
+        storage.put_metadata("ipfs://a", &document, 5).unwrap();
+        assert_eq!(storage.metadata("ipfs://a").unwrap(), Some((document, 5)));
+        assert_eq!(storage.metadata("ipfs://b").unwrap(), None);
+    }
+}
@@ -0,0 +1,198 @@
+use lazy_static::lazy_static;
+use rocket::http::ContentType;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Read};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use web3::types::{H160, H256, U256};
+
+// Events a slow client may fall behind by before new ones are dropped for it
+const BUFFER_SIZE: usize = 256;
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+const CHUNK_SIZE: u64 = 4096;
+
+lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+    // Set from the server's worker count before it starts
+    static ref MAX_SUBSCRIBERS: Mutex<usize> = Mutex::new(0);
+}
+
+// Each open stream holds a server worker until the client goes away, so
+// streams may take at most half of them and the rest serve other requests
+pub fn set_workers(workers: u16) {
+    *MAX_SUBSCRIBERS.lock().unwrap() = workers as usize / 2;
+}
+
+// What an event is about. Clients subscribe to events by any of these.
+#[derive(Default)]
+pub struct Subject {
+    pub contract_address: Option<H160>,
+    pub token_ids: Vec<U256>,
+    pub addresses: Vec<H160>,
+    pub transaction_hash: Option<H256>,
+}
+
+// Unset fields match everything
+#[derive(Default)]
+pub struct Filter {
+    pub contract_address: Option<H160>,
+    pub token_id: Option<U256>,
+    pub address: Option<H160>,
+    pub transaction_hash: Option<H256>,
+}
+
+struct Subscriber {
+    filter: Filter,
+    sender: SyncSender<Vec<u8>>,
+    // Set when the stream is dropped, so it is let go even if no event
+    // ever matches its filter
+    closed: Arc<AtomicBool>,
+}
+
+#[derive(Serialize)]
+struct StreamEvent<'a> {
+    event: &'a str,
+    data: &'a Value,
+}
+
+impl Filter {
+    fn matches(&self, subject: &Subject) -> bool {
+        self.contract_address
+            .map_or(true, |address| subject.contract_address == Some(address))
+            && self
+                .token_id
+                .map_or(true, |token_id| subject.token_ids.contains(&token_id))
+            && self
+                .address
+                .map_or(true, |address| subject.addresses.contains(&address))
+            && self
+                .transaction_hash
+                .map_or(true, |hash| subject.transaction_hash == Some(hash))
+    }
+}
+
+// Sends an event to every open stream whose filter matches. Streams whose
+// client went away are dropped here, whatever their filter.
+pub fn publish<T: Serialize>(event: &str, subject: Subject, data: &T) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let data = match serde_json::to_value(data) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error: failed to serialize {} event: {}", event, e);
+            return;
+        }
+    };
+    let message = match serde_json::to_string(&StreamEvent { event, data: &data }) {
+        Ok(json) => format!("event: {}\ndata: {}\n\n", event, json).into_bytes(),
+        Err(_) => return,
+    };
+    subscribers.retain(|subscriber| {
+        if subscriber.closed.load(Ordering::Relaxed) {
+            return false;
+        }
+        if !subscriber.filter.matches(&subject) {
+            return true;
+        }
+        match subscriber.sender.try_send(message.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
+
+// A server-sent events body. Reads block until the next event and report
+// WouldBlock after each one so the server flushes it to the client right away.
+pub struct EventStream {
+    receiver: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    flushed: bool,
+    closed: Arc<AtomicBool>,
+}
+
+pub fn subscribe(filter: Filter) -> Option<EventStream> {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    subscribers.retain(|subscriber| !subscriber.closed.load(Ordering::Relaxed));
+    if subscribers.len() >= *MAX_SUBSCRIBERS.lock().unwrap() {
+        return None;
+    }
+    let (sender, receiver) = sync_channel(BUFFER_SIZE);
+    let closed = Arc::new(AtomicBool::new(false));
+    subscribers.push(Subscriber {
+        filter,
+        sender,
+        closed: closed.clone(),
+    });
+    Some(EventStream {
+        receiver,
+        closed,
+        // Sent first so clients know the stream is open
+        pending: b": connected\n\n".to_vec(),
+        flushed: false,
+    })
+}
+
+impl Read for EventStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            if !self.flushed {
+                self.flushed = true;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.pending = match self.receiver.recv_timeout(KEEP_ALIVE) {
+                Ok(message) => message,
+                // Comments keep proxies from closing the connection and let a
+                // failed write tell us the client is gone
+                Err(RecvTimeoutError::Timeout) => b": keep-alive\n\n".to_vec(),
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+            self.flushed = false;
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<'r> Responder<'r> for EventStream {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::new("text", "event-stream"))
+            .raw_header("Cache-Control", "no-cache")
+            .chunked_body(self, CHUNK_SIZE)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_streams_free_their_slot() {
+        set_workers(2);
+        let filter = || Filter {
+            token_id: Some(U256::from(1)),
+            ..Filter::default()
+        };
+        let stream = subscribe(filter()).unwrap();
+        assert!(subscribe(filter()).is_none());
+        publish("token.transfer", Subject::default(), &0);
+        drop(stream);
+        assert!(subscribe(filter()).is_some());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::Config;
+use crate::indexer;
+use crate::types::BlockHeader;
+use futures::stream::{self, StreamExt};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_compat_02::FutureExt;
+use web3::types::{FilterBuilder, Log};
+
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+// A connection that stayed up this long resets the reconnect backoff
+const STABLE_CONNECTION: Duration = Duration::from_secs(300);
+
+enum Notification {
+    Head(web3::types::BlockHeader),
+    Log(Log),
+}
+
+// Follows newHeads and the indexed contracts' logs over eth_subscribe until the
+// connection drops. Blocks missed while disconnected are backfilled by the
+// indexer's eth_getLogs sync, which is woken as soon as the subscription is up.
+async fn follow(url: &str) -> Result<(), String> {
+    let (contracts, topics) = indexer::subscription_filter();
+    // An empty address list would subscribe to every log on the chain
+    if contracts.is_empty() {
+        return Err("no contract to follow".into());
+    }
+    let transport = web3::transports::WebSocket::new(url)
+        .await
+        .map_err(|e| e.to_string())?;
+    let web3 = web3::Web3::new(transport);
+    let heads = web3
+        .eth_subscribe()
+        .subscribe_new_heads()
+        .await
+        .map_err(|e| e.to_string())?;
+    let filter = FilterBuilder::default()
+        .address(contracts.clone())
+        .topics(Some(topics), None, None, None)
+        .build();
+    let logs = web3
+        .eth_subscribe()
+        .subscribe_logs(filter)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    indexer::set_subscription(Some(contracts.clone()));
+    indexer::wake();
+    let mut notifications = stream::select(
+        heads.map(|head| head.map(Notification::Head)),
+        logs.map(|log| log.map(Notification::Log)),
+    );
+    while let Some(notification) = notifications.next().await {
+        match notification.map_err(|e| e.to_string())? {
+            Notification::Head(head) => {
+                // A collection was added or removed; the logs filter is stale
+                if indexer::subscription_filter().0 != contracts {
+                    return Err("collections changed, subscribing again".into());
+                }
+                let header = match (head.number, head.hash) {
+                    (Some(number), Some(hash)) => BlockHeader {
+                        number: number.as_u64(),
+                        hash,
+                        parent_hash: head.parent_hash,
+                        timestamp: head.timestamp.as_u64(),
+                    },
+                    // Pending blocks have neither
+                    _ => continue,
+                };
+                indexer::notify_head(header);
+            }
+            Notification::Log(log) => indexer::notify_log(log),
+        }
+    }
+    Err("subscription closed".into())
+}
+
+pub fn spawn_subscriber() {
+    let url = Config::get_ws_url();
+    if url.is_empty() {
+        return;
+    }
+    if !url.starts_with("ws://") && !url.starts_with("wss://") {
+        eprintln!(
+            "Warning: ws_url {} is not a ws:// or wss:// endpoint, polling only",
+            url
+        );
+        return;
+    }
+    thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut delay = MIN_RECONNECT_DELAY;
+        loop {
+            let connected_at = Instant::now();
+            // web3's WebSocket transport runs on tokio 0.2
+            if let Err(e) = rt.block_on(follow(&url).compat()) {
+                eprintln!("Error: subscription: {}", e);
+            }
+            indexer::set_subscription(None);
+            if connected_at.elapsed() >= STABLE_CONNECTION {
+                delay = MIN_RECONNECT_DELAY;
+            }
+            thread::sleep(delay);
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    });
+}
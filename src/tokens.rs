@@ -0,0 +1,258 @@
+use crate::eth::{balance_of, token_of_owner_by_index};
+use crate::currency::Currency;
+use crate::indexer::{token_events, tokens_of_owner, TokenEvent, TokenEventKind};
+use crate::interfaces::capabilities;
+use crate::market::{list_listings, Fill};
+use crate::metadata;
+use crate::types::{OwnedToken, OwnedTokens};
+use serde::Serialize;
+use web3::types::{H160, H256, U256};
+
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    Mint,
+    Transfer,
+    Sale,
+    Approval,
+    Burn,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    pub block_number: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub transaction_hash: Option<H256>,
+    pub from: Option<H160>,
+    pub to: Option<H160>,
+    // Amount of an ERC-1155 token moved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<U256>,
+    pub price: Option<U256>,
+    pub currency: Option<Currency>,
+    pub price_formatted: Option<String>,
+    pub listing_id: Option<u64>,
+    pub error: Option<String>,
+    #[serde(skip)]
+    log_index: u64,
+}
+
+struct Sale {
+    listing_id: u64,
+    seller: H160,
+    fill: Fill,
+}
+
+impl HistoryEntry {
+    fn from_event(kind: HistoryKind, event: &TokenEvent, from: H160, to: H160) -> HistoryEntry {
+        HistoryEntry {
+            kind,
+            block_number: Some(event.block_number),
+            timestamp: event.timestamp,
+            transaction_hash: Some(event.transaction_hash),
+            from: Some(from),
+            to: Some(to),
+            amount: None,
+            price: None,
+            currency: None,
+            price_formatted: None,
+            listing_id: None,
+            error: None,
+            log_index: event.log_index,
+        }
+    }
+
+    // A sale whose token transfer isn't in the index, e.g. it failed or the
+    // indexer hasn't reached its block yet
+    fn from_sale(sale: &Sale) -> HistoryEntry {
+        let receipt = &sale.fill.receipt;
+        HistoryEntry {
+            kind: HistoryKind::Sale,
+            block_number: None,
+            timestamp: Some(sale.fill.timestamp),
+            transaction_hash: receipt.transfer_tx.or(receipt.payment_tx),
+            from: Some(sale.seller),
+            to: Some(sale.fill.buyer),
+            amount: None,
+            price: None,
+            currency: None,
+            price_formatted: None,
+            listing_id: None,
+            error: None,
+            log_index: 0,
+        }
+        .with_sale(sale)
+    }
+
+    fn with_sale(mut self, sale: &Sale) -> HistoryEntry {
+        let receipt = &sale.fill.receipt;
+        self.kind = HistoryKind::Sale;
+        self.price = Some(receipt.price);
+        self.price_formatted = Some(receipt.currency.format(receipt.price));
+        self.currency = Some(receipt.currency.clone());
+        self.listing_id = Some(sale.listing_id);
+        self.error = receipt.error.clone();
+        self
+    }
+}
+
+async fn describe(contract_address: H160, token_id: U256) -> OwnedToken {
+    let token_uri = metadata::resolve_token_uri(contract_address, token_id)
+        .await
+        .ok()
+        .map(|(_, uri)| uri);
+    let metadata = token_uri
+        .as_deref()
+        .and_then(|uri| metadata::fetch(uri).ok());
+    OwnedToken {
+        token_id,
+        token_uri,
+        metadata,
+    }
+}
+
+// Lists one page of an owner's tokens. ERC721Enumerable contracts are asked
+// directly; anything else is answered from the event index.
+pub async fn owned_tokens(
+    contract_address: H160,
+    owner: H160,
+    offset: u64,
+    limit: u64,
+) -> Result<OwnedTokens, String> {
+    let limit = limit.min(MAX_PAGE_SIZE);
+    // Contracts that can't be probed are answered from the index too
+    let enumerable = capabilities(contract_address, false)
+        .await
+        .map_or(false, |capabilities| capabilities.erc721_enumerable);
+
+    let (source, total, token_ids) = if enumerable {
+        let balance = balance_of(contract_address, owner).await?;
+        if balance.bits() > 64 {
+            return Err(format!("balance {} of {:?} is too large", balance, owner));
+        }
+        let total = balance.as_u64();
+        let mut token_ids = Vec::new();
+        for index in offset..total.min(offset.saturating_add(limit)) {
+            let token_id =
+                token_of_owner_by_index(contract_address, owner, U256::from(index)).await?;
+            token_ids.push(token_id);
+        }
+        ("enumerable", total, token_ids)
+    } else {
+        let all = tokens_of_owner(contract_address, owner);
+        let token_ids = all
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .copied()
+            .collect();
+        ("index", all.len() as u64, token_ids)
+    };
+
+    let mut tokens = Vec::new();
+    for token_id in token_ids {
+        tokens.push(describe(contract_address, token_id).await);
+    }
+    Ok(OwnedTokens {
+        owner,
+        contract_address,
+        source: source.to_string(),
+        total,
+        offset,
+        limit,
+        tokens,
+    })
+}
+
+fn token_sales(contract_address: H160, token_id: U256) -> Vec<Sale> {
+    list_listings()
+        .into_iter()
+        .filter(|listing| listing.contract_address == contract_address)
+        .flat_map(|listing| {
+            let (listing_id, seller) = (listing.id, listing.seller);
+            listing
+                .fills
+                .into_iter()
+                .chain(listing.failed)
+                .filter(move |fill| fill.token_id == token_id)
+                .map(move |fill| Sale {
+                    listing_id,
+                    seller,
+                    fill,
+                })
+        })
+        .collect()
+}
+
+// Provenance of a token from the indexed events. Transfers made by a
+// marketplace sale are matched on their transaction hash and carry the price.
+pub fn token_history(contract_address: H160, token_id: U256) -> Vec<HistoryEntry> {
+    let mut sales: Vec<Option<Sale>> = token_sales(contract_address, token_id)
+        .into_iter()
+        .map(Some)
+        .collect();
+    let mut history = Vec::new();
+    for event in token_events(contract_address, token_id) {
+        let entry = match event.kind {
+            TokenEventKind::Transfer { from, to, .. } if from.is_zero() => {
+                HistoryEntry::from_event(HistoryKind::Mint, &event, from, to)
+            }
+            TokenEventKind::Transfer { from, to, .. } if to.is_zero() => {
+                HistoryEntry::from_event(HistoryKind::Burn, &event, from, to)
+            }
+            TokenEventKind::Transfer { from, to, .. } => {
+                let entry = HistoryEntry::from_event(HistoryKind::Transfer, &event, from, to);
+                let sale = sales.iter_mut().find(|sale| {
+                    sale.as_ref().map_or(false, |sale| {
+                        sale.fill.receipt.transfer_tx == Some(event.transaction_hash)
+                    })
+                });
+                match sale.and_then(Option::take) {
+                    Some(sale) => entry.with_sale(&sale),
+                    None => entry,
+                }
+            }
+            TokenEventKind::Approval {
+                owner, approved, ..
+            } => HistoryEntry::from_event(HistoryKind::Approval, &event, owner, approved),
+            TokenEventKind::ApprovalForAll { .. } => continue,
+            TokenEventKind::Erc1155Transfer {
+                from,
+                to,
+                ref token_ids,
+                ref amounts,
+                ..
+            } => {
+                let kind = if from.is_zero() {
+                    HistoryKind::Mint
+                } else if to.is_zero() {
+                    HistoryKind::Burn
+                } else {
+                    HistoryKind::Transfer
+                };
+                let mut entry = HistoryEntry::from_event(kind, &event, from, to);
+                entry.amount = token_ids
+                    .iter()
+                    .zip(amounts)
+                    .filter(|(id, _)| **id == token_id)
+                    .map(|(_, amount)| *amount)
+                    .reduce(|total, amount| total.saturating_add(amount));
+                entry
+            }
+        };
+        history.push(entry);
+    }
+    history.extend(sales.iter().flatten().map(HistoryEntry::from_sale));
+
+    history.sort_by_key(|entry| {
+        (
+            entry.timestamp.unwrap_or(u64::MAX),
+            entry.block_number.unwrap_or(u64::MAX),
+            entry.log_index,
+        )
+    });
+    history
+}
@@ -1,6 +1,10 @@
+use crate::currency::Currency;
+use crate::market::PriceCurve;
+use crate::metadata::TokenStandard;
 use serde::Deserialize;
 use serde::Serialize;
 use web3::types::H160;
+use web3::types::H256;
 use web3::types::U256;
 
 #[derive(Serialize)]
@@ -8,22 +12,24 @@ pub struct NftBalance {
     pub balance: U256,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct MintResponse {
     pub private_key: String,
     pub account_address: String,
     pub amount: u8,
     pub token_uri: String,
+    pub royalty_receiver: Option<H160>,
+    pub royalty_fee: Option<u16>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct ApproveResponse {
     pub private_key: String,
     pub address_to: String,
     pub token_id: U256,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct TransferFormResponse {
     pub private_key: String,
     pub from: H160,
@@ -31,7 +37,7 @@ pub struct TransferFormResponse {
     pub token_id: U256,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct TransferFormDataResponse {
     pub private_key: String,
     pub from: H160,
@@ -40,17 +46,240 @@ pub struct TransferFormDataResponse {
     pub data: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct SetApprovalForAllResponse {
     pub private_key: String,
     pub operator: H160,
     pub approved: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct TransferFromResponse{
     pub private_key: String,
     pub from: H160,
     pub to: H160,
     pub token_id: U256,
-}
\ No newline at end of file
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct RoyaltyResponse {
+    pub private_key: String,
+    pub receiver: H160,
+    pub fee: u16,
+    pub token_id: Option<U256>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DutchAuctionResponse {
+    pub seller: H160,
+    pub token_ids: Vec<U256>,
+    pub currency: Option<String>,
+    pub start_price: String,
+    pub floor_price: String,
+    pub start_time: Option<u64>,
+    pub duration: u64,
+    pub curve: PriceCurve,
+    #[serde(default)]
+    pub rebate: bool,
+    // Lets the same terms be signed again; a signed order is placed once
+    pub nonce: u64,
+    // The seller's personal_sign signature over the order (see signatures.rs)
+    pub signature: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FixedPriceResponse {
+    pub seller: H160,
+    pub token_id: U256,
+    pub currency: Option<String>,
+    pub price: String,
+    pub duration: Option<u64>,
+    pub nonce: u64,
+    pub signature: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct OfferResponse {
+    pub buyer: H160,
+    pub token_id: U256,
+    pub currency: String,
+    pub price: String,
+    pub duration: Option<u64>,
+    pub nonce: u64,
+    // The buyer's signature; it authorizes the operator to pull the price
+    pub signature: String,
+}
+
+// Signed by the seller or buyer of the order being cancelled
+#[derive(Deserialize, Serialize)]
+pub struct CancelRequest {
+    pub signature: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PurchaseResponse {
+    pub private_key: String,
+    pub buyer: H160,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Erc20ApproveResponse {
+    pub private_key: String,
+    pub account_address: H160,
+    pub currency: String,
+    pub amount: String,
+}
+
+#[derive(Serialize)]
+pub struct Erc20Balance {
+    pub currency: Currency,
+    pub balance: U256,
+    pub allowance: U256,
+    pub formatted_balance: String,
+    pub formatted_allowance: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlockHeader {
+    pub number: u64,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize)]
+pub struct OwnedToken {
+    pub token_id: U256,
+    pub token_uri: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+pub struct OwnedTokens {
+    pub owner: H160,
+    pub contract_address: H160,
+    pub source: String,
+    pub total: u64,
+    pub offset: u64,
+    pub limit: u64,
+    pub tokens: Vec<OwnedToken>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct WebhookRequest {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub secret: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AssetRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub content_type: String,
+    // base64 encoded file
+    pub media: String,
+    #[serde(default)]
+    pub attributes: Vec<serde_json::Value>,
+    pub external_url: Option<String>,
+    pub background_color: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CollectionRequest {
+    pub contract_address: H160,
+    pub standard: Option<TokenStandard>,
+    pub name: String,
+    pub network: Option<String>,
+    #[serde(default)]
+    pub start_block: u64,
+    pub platform_fee: Option<u16>,
+    pub royalty_receiver: Option<H160>,
+    pub royalty_fee: Option<u16>,
+    // Without one the bundled ABI for the standard is used
+    pub abi: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CollectionUpdate {
+    pub name: String,
+    pub platform_fee: Option<u16>,
+    pub royalty_receiver: Option<H160>,
+    pub royalty_fee: Option<u16>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct DeployRequest {
+    pub private_key: String,
+    pub standard: TokenStandard,
+    pub name: String,
+    pub symbol: Option<String>,
+    pub base_uri: Option<String>,
+    pub royalty_receiver: Option<H160>,
+    pub royalty_fee: Option<u16>,
+    pub platform_fee: Option<u16>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ContractCallRequest {
+    // Signs sends; defaults to the configured account's key
+    pub private_key: Option<String>,
+    // A function name, or its signature when it is overloaded
+    pub function: String,
+    #[serde(default)]
+    pub args: Vec<serde_json::Value>,
+    // Caller a simulation runs as; defaults to the configured account
+    pub from: Option<H160>,
+    // Ether sent along, e.g. "0.01"
+    pub value: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ContractCallResult {
+    pub signature: String,
+    // Encoded calldata, 0x-prefixed
+    pub data: String,
+    pub outputs: Vec<serde_json::Value>,
+    pub reverted: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ContractCallJob {
+    pub private_key: String,
+    pub signature: String,
+    // Calldata, 0x-prefixed
+    pub data: String,
+    pub value: Option<U256>,
+}
+
+#[derive(Serialize)]
+pub struct DryRun {
+    pub kind: String,
+    pub from: H160,
+    // None for a deployment
+    pub to: Option<H160>,
+    pub function: Option<String>,
+    // Calldata, or the creation code of a deployment, 0x-prefixed
+    pub data: String,
+    pub value: Option<U256>,
+    pub return_value: Option<serde_json::Value>,
+    pub reverted: bool,
+    pub revert_reason: Option<String>,
+    pub gas_estimate: Option<U256>,
+    // Later transactions of the job, which need the first one mined
+    pub not_simulated: Vec<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BurnRequest {
+    pub private_key: String,
+    pub token_id: U256,
+    // Holder of ERC-1155 tokens, the signing account by default; checked
+    // against the owner of an ERC-721 token when given
+    pub owner: Option<H160>,
+    // ERC-1155 only, 1 by default
+    pub amount: Option<U256>,
+}
@@ -0,0 +1,497 @@
+use crate::market::now;
+use crate::storage;
+use hmac::{Hmac, Mac, NewMac};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use web3::signing::keccak256;
+use web3::types::H256;
+
+// Events a webhook can subscribe to. An empty filter subscribes to all of them.
+pub const EVENTS: &[&str] = &[
+    "transaction.final",
+    "job.succeeded",
+    "job.failed",
+    "token.transfer",
+    "listing.sold",
+    "listing.settlement_failed",
+];
+
+const REDACTED: &str = "[redacted]";
+const MAX_ATTEMPTS: usize = 8;
+const RETRY_DELAY: u64 = 10;
+const MAX_RETRY_DELAY: u64 = 3600;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    static ref WEBHOOKS: Mutex<BTreeMap<u64, Webhook>> = Mutex::new(BTreeMap::new());
+    // Deliveries that still have attempts left, by id
+    static ref QUEUE: Mutex<BTreeMap<u64, Delivery>> = Mutex::new(BTreeMap::new());
+    static ref NEXT_DELIVERY_ID: Mutex<u64> = Mutex::new(1);
+    // Ids of deleted webhooks are never reused, so their deliveries stay theirs
+    static ref NEXT_WEBHOOK_ID: Mutex<u64> = Mutex::new(1);
+    // Webhooks with a delivery thread running
+    static ref BUSY: Mutex<HashSet<u64>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    pub id: u64,
+    pub url: String,
+    pub events: Vec<String>,
+    pub secret: String,
+    pub created_at: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    // Out of attempts; kept for inspection and manual redelivery
+    Dead,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeliveryAttempt {
+    pub at: u64,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Delivery {
+    pub id: u64,
+    pub webhook_id: u64,
+    pub event_id: H256,
+    pub event: String,
+    pub payload: Value,
+    pub status: DeliveryStatus,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub next_attempt_at: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Webhook {
+    fn wants(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|wanted| wanted == event)
+    }
+
+    // The secret is only ever used for signing, never handed back out
+    pub fn redacted(&self) -> Webhook {
+        Webhook {
+            secret: REDACTED.to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+fn save(delivery: &Delivery) {
+    if let Err(e) = storage::with(|storage| storage.upsert_delivery(delivery)) {
+        eprintln!(
+            "Error: failed to save webhook delivery {}: {}",
+            delivery.id, e
+        );
+    }
+}
+
+pub fn create_webhook(url: &str, events: Vec<String>, secret: &str) -> Result<Webhook, String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("unsupported webhook URL: {}", url));
+    }
+    if secret.is_empty() {
+        return Err("a signing secret is required".into());
+    }
+    if let Some(event) = events
+        .iter()
+        .find(|event| !EVENTS.contains(&event.as_str()))
+    {
+        return Err(format!("unknown event: {}", event));
+    }
+    let mut webhooks = WEBHOOKS.lock().unwrap();
+    let mut next_id = NEXT_WEBHOOK_ID.lock().unwrap();
+    let webhook = Webhook {
+        id: *next_id,
+        url: url.to_string(),
+        events,
+        secret: secret.to_string(),
+        created_at: now(),
+    };
+    storage::with(|storage| storage.upsert_webhook(&webhook))?;
+    *next_id += 1;
+    webhooks.insert(webhook.id, webhook.clone());
+    Ok(webhook)
+}
+
+pub fn delete_webhook(id: u64) -> Result<bool, String> {
+    let mut webhooks = WEBHOOKS.lock().unwrap();
+    if webhooks.remove(&id).is_none() {
+        return Ok(false);
+    }
+    storage::with(|storage| storage.delete_webhook(id))?;
+    Ok(true)
+}
+
+pub fn list_webhooks() -> Vec<Webhook> {
+    WEBHOOKS
+        .lock()
+        .unwrap()
+        .values()
+        .map(Webhook::redacted)
+        .collect()
+}
+
+pub fn get_webhook(id: u64) -> Option<Webhook> {
+    WEBHOOKS.lock().unwrap().get(&id).map(Webhook::redacted)
+}
+
+pub fn deliveries(webhook_id: u64) -> Result<Vec<Delivery>, String> {
+    storage::with(|storage| storage.deliveries(webhook_id))
+}
+
+// Queues a delivery of `event` to every webhook subscribed to it. The event id
+// only depends on the event and its data, so receivers can drop duplicates.
+pub fn publish<T: Serialize>(event: &str, data: &T) {
+    let data = match serde_json::to_value(data) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error: failed to serialize {} event: {}", event, e);
+            return;
+        }
+    };
+    let event_id = H256::from(keccak256(format!("{} {}", event, data).as_bytes()));
+    let created_at = now();
+    let payload = serde_json::json!({
+        "id": event_id,
+        "event": event,
+        "created_at": created_at,
+        "data": data,
+    });
+
+    let webhooks = WEBHOOKS.lock().unwrap();
+    let mut queue = QUEUE.lock().unwrap();
+    let mut next_id = NEXT_DELIVERY_ID.lock().unwrap();
+    for webhook in webhooks.values().filter(|webhook| webhook.wants(event)) {
+        let delivery = Delivery {
+            id: *next_id,
+            webhook_id: webhook.id,
+            event_id,
+            event: event.to_string(),
+            payload: payload.clone(),
+            status: DeliveryStatus::Pending,
+            attempts: Vec::new(),
+            next_attempt_at: created_at,
+            created_at,
+            updated_at: created_at,
+        };
+        *next_id += 1;
+        save(&delivery);
+        queue.insert(delivery.id, delivery);
+    }
+}
+
+// Queues a dead delivery again with a fresh set of attempts
+pub fn redeliver(id: u64) -> Result<Option<Delivery>, String> {
+    let mut delivery = match storage::with(|storage| storage.delivery(id))? {
+        Some(delivery) => delivery,
+        None => return Ok(None),
+    };
+    if delivery.status == DeliveryStatus::Pending {
+        return Ok(Some(delivery));
+    }
+    delivery.status = DeliveryStatus::Pending;
+    delivery.attempts.clear();
+    delivery.next_attempt_at = now();
+    delivery.updated_at = now();
+    storage::with(|storage| storage.upsert_delivery(&delivery))?;
+    QUEUE.lock().unwrap().insert(delivery.id, delivery.clone());
+    Ok(Some(delivery))
+}
+
+// `X-Webhook-Signature: t=<timestamp>,v1=<hex>` where the MAC is
+// HMAC-SHA256 over "<timestamp>.<body>" keyed with the webhook secret
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+fn send(webhook: &Webhook, delivery: &Delivery) -> DeliveryAttempt {
+    let at = now();
+    let body = delivery.payload.to_string();
+    let result = reqwest::blocking::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .and_then(|client| {
+            client
+                .post(&webhook.url)
+                .header("Content-Type", "application/json")
+                .header("X-Webhook-Id", format!("{:?}", delivery.event_id))
+                .header("X-Webhook-Event", &delivery.event)
+                .header("X-Webhook-Delivery", delivery.id.to_string())
+                .header("X-Webhook-Signature", sign(&webhook.secret, at, &body))
+                .body(body)
+                .send()
+        });
+    match result {
+        Ok(response) if response.status().is_success() => DeliveryAttempt {
+            at,
+            status_code: Some(response.status().as_u16()),
+            error: None,
+        },
+        Ok(response) => DeliveryAttempt {
+            at,
+            status_code: Some(response.status().as_u16()),
+            error: Some(format!("receiver answered {}", response.status())),
+        },
+        Err(e) => DeliveryAttempt {
+            at,
+            status_code: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+fn retry_delay(attempts: usize) -> u64 {
+    (RETRY_DELAY << attempts.saturating_sub(1).min(12)).min(MAX_RETRY_DELAY)
+}
+
+fn deliver(mut delivery: Delivery) {
+    let webhook = WEBHOOKS.lock().unwrap().get(&delivery.webhook_id).cloned();
+    let attempt = match &webhook {
+        Some(webhook) => send(webhook, &delivery),
+        None => DeliveryAttempt {
+            at: now(),
+            status_code: None,
+            error: Some("webhook was deleted".into()),
+        },
+    };
+    let delivered = attempt.error.is_none();
+    delivery.attempts.push(attempt);
+    delivery.updated_at = now();
+    if delivered {
+        delivery.status = DeliveryStatus::Delivered;
+    } else if webhook.is_none() || delivery.attempts.len() >= MAX_ATTEMPTS {
+        delivery.status = DeliveryStatus::Dead;
+    } else {
+        delivery.next_attempt_at = now() + retry_delay(delivery.attempts.len());
+    }
+    save(&delivery);
+
+    let mut queue = QUEUE.lock().unwrap();
+    if delivery.status == DeliveryStatus::Pending {
+        queue.insert(delivery.id, delivery);
+    } else {
+        queue.remove(&delivery.id);
+    }
+}
+
+pub fn load() -> Result<(), String> {
+    let (webhooks, pending, last_id, last_webhook_id) = storage::with(|storage| {
+        Ok((
+            storage.webhooks()?,
+            storage.pending_deliveries()?,
+            storage.last_delivery_id()?,
+            storage.last_webhook_id()?,
+        ))
+    })?;
+    *WEBHOOKS.lock().unwrap() = webhooks
+        .into_iter()
+        .map(|webhook| (webhook.id, webhook))
+        .collect();
+    *QUEUE.lock().unwrap() = pending
+        .into_iter()
+        .map(|delivery| (delivery.id, delivery))
+        .collect();
+    *NEXT_DELIVERY_ID.lock().unwrap() = last_id.map_or(1, |id| id + 1);
+    *NEXT_WEBHOOK_ID.lock().unwrap() = last_webhook_id.map_or(1, |id| id + 1);
+    Ok(())
+}
+
+// Due deliveries by webhook, leaving out webhooks that are still being
+// delivered to
+fn due(time: u64) -> BTreeMap<u64, Vec<Delivery>> {
+    let busy = BUSY.lock().unwrap();
+    let mut due: BTreeMap<u64, Vec<Delivery>> = BTreeMap::new();
+    for delivery in QUEUE.lock().unwrap().values() {
+        if delivery.next_attempt_at <= time && !busy.contains(&delivery.webhook_id) {
+            due.entry(delivery.webhook_id)
+                .or_default()
+                .push(delivery.clone());
+        }
+    }
+    due
+}
+
+// Each webhook gets its own delivery thread, so a slow or unreachable
+// receiver only holds up its own deliveries. A webhook's deliveries are still
+// made in order.
+pub fn spawn_dispatcher() {
+    thread::spawn(|| loop {
+        for (webhook_id, deliveries) in due(now()) {
+            BUSY.lock().unwrap().insert(webhook_id);
+            thread::spawn(move || {
+                for delivery in deliveries {
+                    deliver(delivery);
+                }
+                BUSY.lock().unwrap().remove(&webhook_id);
+            });
+        }
+        thread::sleep(POLL_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+
+    struct Received {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    // A receiver on a local port that answers every request with `status`
+    fn receiver(status: u16) -> (String, Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, received) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(": ") {
+                        Some((name, value)) => headers.push((name.to_string(), value.to_string())),
+                        None => break,
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                    .map_or(0, |(_, value)| value.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                let body = String::from_utf8(body).unwrap();
+                if sender.send(Received { headers, body }).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, received)
+    }
+
+    fn delivery(webhook_id: u64, id: u64, attempts: usize) -> Delivery {
+        let attempt = DeliveryAttempt {
+            at: 0,
+            status_code: Some(500),
+            error: Some("receiver answered 500".into()),
+        };
+        Delivery {
+            id,
+            webhook_id,
+            event_id: H256::repeat_byte(1),
+            event: "token.transfer".into(),
+            payload: serde_json::json!({ "event": "token.transfer", "data": { "token_id": 1 } }),
+            status: DeliveryStatus::Pending,
+            attempts: vec![attempt; attempts],
+            next_attempt_at: 0,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn deliveries_are_signed() {
+        storage::init_in_memory();
+        let (url, received) = receiver(200);
+        let webhook = create_webhook(&url, Vec::new(), "secret").unwrap();
+        let delivery = delivery(webhook.id, 1000, 0);
+        let attempt = send(&webhook, &delivery);
+        assert_eq!(attempt.status_code, Some(200));
+        assert_eq!(attempt.error, None);
+
+        let request = received.recv().unwrap();
+        assert_eq!(request.body, delivery.payload.to_string());
+        assert_eq!(request.header("X-Webhook-Event"), Some("token.transfer"));
+        assert_eq!(request.header("X-Webhook-Delivery"), Some("1000"));
+        let signature = request.header("X-Webhook-Signature").unwrap();
+        let (timestamp, mac) = signature.split_once(",v1=").unwrap();
+        let timestamp = timestamp.strip_prefix("t=").unwrap();
+        let mut expected = Hmac::<Sha256>::new_varkey(b"secret").unwrap();
+        expected.update(format!("{}.{}", timestamp, request.body).as_bytes());
+        expected.verify(&hex::decode(mac).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn failed_deliveries_are_retried_then_dead_lettered() {
+        storage::init_in_memory();
+        let (url, received) = receiver(500);
+        let webhook = create_webhook(&url, Vec::new(), "secret").unwrap();
+
+        deliver(delivery(webhook.id, 2000, 0));
+        received.recv().unwrap();
+        let retried = QUEUE.lock().unwrap().get(&2000).cloned().unwrap();
+        assert_eq!(retried.status, DeliveryStatus::Pending);
+        assert_eq!(retried.attempts.len(), 1);
+        assert_eq!(retried.attempts[0].status_code, Some(500));
+        assert!(retried.next_attempt_at > retried.attempts[0].at);
+
+        deliver(delivery(webhook.id, 2000, MAX_ATTEMPTS - 1));
+        received.recv().unwrap();
+        assert!(QUEUE.lock().unwrap().get(&2000).is_none());
+        let dead = storage::with(|storage| storage.delivery(2000))
+            .unwrap()
+            .unwrap();
+        assert_eq!(dead.status, DeliveryStatus::Dead);
+        assert_eq!(dead.attempts.len(), MAX_ATTEMPTS);
+
+        let redelivered = redeliver(2000).unwrap().unwrap();
+        assert_eq!(redelivered.status, DeliveryStatus::Pending);
+        assert!(redelivered.attempts.is_empty());
+        assert!(QUEUE.lock().unwrap().contains_key(&2000));
+    }
+
+    #[test]
+    fn deleted_webhook_ids_are_not_reused() {
+        storage::init_in_memory();
+        let webhook = create_webhook("http://127.0.0.1:1/hook", Vec::new(), "secret").unwrap();
+        assert!(delete_webhook(webhook.id).unwrap());
+        let next = create_webhook("http://127.0.0.1:1/hook", Vec::new(), "secret").unwrap();
+        assert!(next.id > webhook.id);
+    }
+}